@@ -0,0 +1,72 @@
+use blaseball_vcr::archive::{Archive, ArchiveWriter};
+use blaseball_vcr::VCRResult;
+use clap::clap_app;
+use std::path::Path;
+
+pub fn main() -> VCRResult<()> {
+    let matches = clap_app!(archive =>
+        (version: "1.0")
+        (author: "allie signet <allie@sibr.dev>")
+        (about: "blaseball.vcr single-file archive tool")
+        (@subcommand pack =>
+            (about: "bundle tapes, dictionaries, feed and site data into a .vcr file")
+            (@arg TAPES: -t --tapes [FOLDER] "set tapes folder (default: ./tapes)")
+            (@arg SITE_DATA: -s --site [FOLDER] "set site data folder (default: ./tapes/site_data)")
+            (@arg DICTS: -d --dicts [FOLDER] "set zstd dictionaries folder (default: ./zstd-dictionaries)")
+            (@arg FEED: -f --feed [FOLDER] "set feed folder (default: ./tapes/feed)")
+            (@arg OUT: <FILE> "output archive")
+        )
+        (@subcommand unpack =>
+            (about: "extract a .vcr file into a folder")
+            (@arg INPUT: <FILE> "input archive")
+            (@arg OUT: <FOLDER> "output folder")
+        )
+        (@subcommand list =>
+            (about: "list the contents of a .vcr file")
+            (@arg INPUT: <FILE> "input archive")
+        )
+    )
+    .get_matches();
+
+    match matches.subcommand() {
+        ("pack", Some(args)) => {
+            let mut writer = ArchiveWriter::new();
+            let folders = [
+                ("tapes", args.value_of("TAPES").unwrap_or("./tapes")),
+                (
+                    "site_data",
+                    args.value_of("SITE_DATA").unwrap_or("./tapes/site_data"),
+                ),
+                (
+                    "dictionaries",
+                    args.value_of("DICTS").unwrap_or("./zstd-dictionaries"),
+                ),
+                ("feed", args.value_of("FEED").unwrap_or("./tapes/feed")),
+            ];
+
+            for (name, folder) in folders {
+                if Path::new(folder).is_dir() {
+                    println!("| packing {} from {}", name, folder);
+                    writer.add_folder(name, folder)?;
+                } else {
+                    println!("| skipping {} ({} not found)", name, folder);
+                }
+            }
+
+            writer.write(args.value_of("OUT").unwrap())?;
+        }
+        ("unpack", Some(args)) => {
+            let archive = Archive::open(args.value_of("INPUT").unwrap())?;
+            archive.unpack(args.value_of("OUT").unwrap())?;
+        }
+        ("list", Some(args)) => {
+            let archive = Archive::open(args.value_of("INPUT").unwrap())?;
+            for (name, len) in archive.entries() {
+                println!("{:>12} {}", len, name);
+            }
+        }
+        _ => println!("{}", matches.usage()),
+    }
+
+    Ok(())
+}
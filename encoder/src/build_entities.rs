@@ -1,16 +1,19 @@
+use ::encoder::fetch::Fetcher;
+use ::encoder::journal::{BuildJournal, JournalEntry};
+use ::encoder::pipeline::{Failure, Reorder};
 use blaseball_vcr::encoder::*;
 use blaseball_vcr::*;
 use clap::clap_app;
+use crossbeam::channel::bounded;
 use indicatif::{
     MultiProgress, MultiProgressAlignment, ProgressBar, ProgressDrawTarget, ProgressStyle,
 };
-use integer_encoding::VarIntWriter;
 use serde::Serialize;
 use serde_json::Value as JSONValue;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::fs::File;
 use std::io::{BufWriter, Read, Seek, Write};
 use std::path::Path;
-use uuid::Uuid;
 
 #[derive(Debug, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -27,49 +30,42 @@ struct ChroniclerParameters {
     count: u32,
 }
 
-async fn paged_get(
-    client: &reqwest::Client,
+fn paged_get(
+    fetcher: &Fetcher,
     url: &str,
-    mpb: &MultiProgress,
     mut parameters: ChroniclerParameters,
 ) -> anyhow::Result<Vec<ChroniclerEntity<JSONValue>>> {
     let mut results: Vec<ChroniclerEntity<JSONValue>> = Vec::new();
 
-    let mut page = 1;
-    let spinny = mpb.add(ProgressBar::new_spinner());
-    spinny.enable_steady_tick(std::time::Duration::from_millis(120));
-    spinny.set_style(
-        ProgressStyle::default_spinner()
-            .template("{spinner:.blue} {msg}")
-            .unwrap(),
-    );
     loop {
-        spinny.set_message(format!("downloading entities - page {}", page));
-        let mut chron_response: ChroniclerResponse<ChroniclerEntity<JSONValue>> = client
-            .get(url)
-            .query(&parameters)
-            .send()
-            .await?
-            .json()
-            .await?;
+        let mut chron_response: ChroniclerResponse<ChroniclerEntity<JSONValue>> =
+            fetcher.get_json(url, &parameters)?;
         results.append(&mut chron_response.items);
 
         if let Some(next_page) = chron_response.next_page {
             parameters.next_page = Some(next_page);
-            page += 1;
         } else {
             break;
         }
     }
-    mpb.remove(&spinny);
 
     Ok(results)
 }
 
-#[tokio::main]
-pub async fn main() -> VCRResult<()> {
-    let client = reqwest::Client::new();
+/// What travels down the pipeline. Every message carries a sequence number so that later stages can put things back in the order they were queued in.
+enum Job<P> {
+    Entity {
+        id: String,
+        patches: P,
+        path_map: BTreeMap<u16, String>,
+        base: JSONValue,
+        values: ValueTableDelta,
+    },
+    /// marks the end of an entity type; carries its value table once it's been filled in.
+    TypeDone(Option<ValueTable>),
+}
 
+pub fn main() -> VCRResult<()> {
     let matches = clap_app!(build_entities =>
         (version: "1.0")
         (author: "allie signet <allie@sibr.dev>")
@@ -78,7 +74,9 @@ pub async fn main() -> VCRResult<()> {
         (@arg COMPRESSION_LEVEL: -l --level [LEVEL] "set compression level")
         (@arg CHECKPOINTS: -c --checkpoints [CHECKPOINTS] "make a checkpoint every n entities")
         (@arg OUTPUT_FOLDER: -o --output [FOLDER] "set output folder for resulting tapes")
+        (@arg THREADS: -t --threads [THREADS] "set amount of fetch and compression workers to use (default: 4)")
         (@arg WHEE: --whee "show extra progress bars for patch compression")
+        (@arg RESUME: --resume "pick up an interrupted build where it left off")
         (@arg ENTITIES: <TYPE> ... "entity types to encode")
     )
     .get_matches();
@@ -93,145 +91,432 @@ pub async fn main() -> VCRResult<()> {
         .map(|v| v.parse::<u16>().unwrap())
         .unwrap_or(u16::MAX);
 
+    let n_workers = matches
+        .value_of("THREADS")
+        .map(|v| v.parse::<usize>().unwrap())
+        .unwrap_or(4);
+
     let base_path = Path::new(matches.value_of("OUTPUT_FOLDER").unwrap_or("./tapes"));
     let entity_types: Vec<&str> = matches.values_of("ENTITIES").unwrap().collect();
 
-    let mut patch_compressor = if let Some(dict_path) = matches.value_of("ZSTD_DICT") {
-        let mut dict_f = File::open(&dict_path).map_err(VCRError::IOError)?;
+    let resume = matches.is_present("RESUME");
+
+    // a type whose journal is gone but whose header exists was finished by an earlier run
+    let mut completed: Vec<Option<HashSet<String>>> = Vec::new();
+    let mut journals: Vec<Option<BuildJournal>> = Vec::new();
+    let mut value_tables: HashMap<usize, ValueTable> = HashMap::new();
+
+    for (type_idx, etype) in entity_types.iter().enumerate() {
+        let journal_path = base_path.join(format!("{}.journal", etype));
+        let header_path = base_path.join(format!("{}.header.riv.zstd", etype));
+
+        if resume && !journal_path.exists() && header_path.exists() {
+            println!("| {} is already done, skipping", etype);
+            completed.push(None);
+            journals.push(None);
+            continue;
+        }
+
+        let journal = BuildJournal::open(journal_path, resume)?;
+        if !journal.entries().is_empty() {
+            println!(
+                "| resuming {} after {} entities",
+                etype,
+                journal.entries().len()
+            );
+        }
+
+        let mut value_table = ValueTable::new();
+        for entry in journal.entries() {
+            if let Some(delta) = &entry.values {
+                value_table.apply_delta(delta.clone());
+            }
+        }
+
+        completed.push(Some(journal.completed()));
+        journals.push(Some(journal));
+        value_tables.insert(type_idx, value_table);
+    }
+
+    let dict: Option<Vec<u8>> = if let Some(dict_path) = matches.value_of("ZSTD_DICT") {
+        let mut dict_f = File::open(dict_path)?;
         let mut dict: Vec<u8> = Vec::new();
-        dict_f.read_to_end(&mut dict).map_err(VCRError::IOError)?;
-        zstd::block::Compressor::with_dict(dict)
+        dict_f.read_to_end(&mut dict)?;
+        Some(dict)
     } else {
-        zstd::block::Compressor::new()
+        None
     };
 
-    for etype in entity_types {
-        let bars = MultiProgress::new();
-        bars.set_alignment(MultiProgressAlignment::Top);
-
-        let entity_ids: Vec<String> = paged_get(
-            &client,
-            "https://api.sibr.dev/chronicler/v2/entities",
-            &bars,
-            ChroniclerParameters {
-                next_page: None,
-                entity_type: etype.to_owned(),
-                id: None,
-                order: None,
-                count: 1000,
-            },
-        )
-        .await?
-        .into_iter()
-        .map(|e| e.entity_id)
-        .collect();
-
-        println!("| found {} entities", entity_ids.len());
-
-        let bar_style = ProgressStyle::default_bar()
+    let fetcher = Fetcher::from_env();
+
+    let bars = MultiProgress::new();
+    bars.set_alignment(MultiProgressAlignment::Top);
+    bars.set_draw_target(ProgressDrawTarget::stderr_with_hz(10));
+
+    let progress_bar = bars.add(ProgressBar::new(0));
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
             .template("{msg:.bold} - {pos}/{len} {wide_bar:40.green/white}")
-            .unwrap();
-
-        let entity_id_bar = bars.add(ProgressBar::new(entity_ids.len() as u64));
-        entity_id_bar.set_style(bar_style.clone());
-        entity_id_bar.set_message("encoding entities");
-
-        let out_file =
-            File::create(base_path.join(&format!("{}.riv", etype))).map_err(VCRError::IOError)?;
-        let mut out = BufWriter::new(out_file);
-
-        let entity_table_f = File::create(base_path.join(&format!("{}.header.riv.zstd", etype)))
-            .map_err(VCRError::IOError)?;
-        let mut entity_table_writer = zstd::Encoder::new(entity_table_f, 21).unwrap();
-
-        for id in entity_id_bar.wrap_iter(entity_ids.into_iter()) {
-            entity_id_bar.tick();
-            entity_id_bar.set_message(format!("encoding {}", id));
-
-            let mut entity_versions: Vec<(u32, JSONValue)> = paged_get(
-                &client,
-                "https://api.sibr.dev/chronicler/v2/versions",
-                &bars,
-                ChroniclerParameters {
-                    next_page: None,
-                    entity_type: etype.to_owned(),
-                    id: Some(id.clone()),
-                    order: Some("asc".to_owned()),
-                    count: 1000,
-                },
-            )
-            .await?
-            .into_iter()
-            .map(|e| (e.valid_from.timestamp() as u32, e.data))
-            .collect();
-
-            entity_versions.sort_by_key(|v| v.0);
-
-            let (patches, path_map, baseval) = encode(entity_versions, checkpoint_every);
-
-            let mut last_position = out.stream_position().unwrap() as u32;
-            let mut header_encoder = HeaderEncoder::new(
-                baseval,
-                checkpoint_every,
-                path_map,
-                last_position,
-                Vec::new(),
-            )
-            .unwrap();
-
-            let compression_bar = bars.add(ProgressBar::new(patches.len() as u64));
-            if !matches.is_present("WHEE") {
-                compression_bar.set_draw_target(ProgressDrawTarget::hidden());
+            .unwrap(),
+    );
+    progress_bar.set_message("fetching entity lists");
+
+    // feeder -> fetch workers -> sequencer (value interning) -> compression workers -> writer
+    let (id_snd, id_rcv) = bounded::<(usize, usize, Option<String>)>(n_workers);
+    let (encoded_snd, encoded_rcv) = bounded(n_workers);
+    let (interned_snd, interned_rcv) = bounded(n_workers);
+    let (compressed_snd, compressed_rcv) = bounded(n_workers);
+
+    let failure = Failure::default();
+    let failure = &failure;
+
+    crossbeam::scope(|s| {
+        let spinny = bars.add(ProgressBar::new_spinner());
+        spinny.enable_steady_tick(std::time::Duration::from_millis(120));
+        spinny.set_style(
+            ProgressStyle::default_spinner()
+                .template("{spinner:.blue} {msg}")
+                .unwrap(),
+        );
+
+        let feeder_types = entity_types.clone();
+        let feeder_bar = progress_bar.clone();
+        let feeder_fetcher = fetcher.clone();
+        s.spawn(move |_| {
+            let mut seq = 0;
+
+            'types: for (type_idx, etype) in feeder_types.into_iter().enumerate() {
+                let completed = match &completed[type_idx] {
+                    Some(completed) => completed,
+                    None => continue,
+                };
+
+                spinny.set_message(format!("fetching {} list", etype));
+
+                let entity_ids: Vec<String> = match paged_get(
+                    &feeder_fetcher,
+                    "https://api.sibr.dev/chronicler/v2/entities",
+                    ChroniclerParameters {
+                        next_page: None,
+                        entity_type: etype.to_owned(),
+                        id: None,
+                        order: None,
+                        count: 1000,
+                    },
+                ) {
+                    Ok(entities) => entities
+                        .into_iter()
+                        .map(|e| e.entity_id)
+                        .filter(|id| !completed.contains(id))
+                        .collect(),
+                    Err(e) => {
+                        failure.set(e);
+                        break;
+                    }
+                };
+
+                spinny.println(format!(
+                    "| found {} {} entities to encode",
+                    entity_ids.len(),
+                    etype
+                ));
+                feeder_bar.inc_length(entity_ids.len() as u64);
+
+                for id in entity_ids {
+                    if failure.is_set() || id_snd.send((seq, type_idx, Some(id))).is_err() {
+                        break 'types;
+                    }
+                    seq += 1;
+                }
+
+                if id_snd.send((seq, type_idx, None)).is_err() {
+                    break;
+                }
+                seq += 1;
+            }
+
+            spinny.finish_and_clear();
+        });
+
+        for _ in 0..n_workers {
+            let (recvr, sendr) = (id_rcv.clone(), encoded_snd.clone());
+            let fetcher = fetcher.clone();
+            let entity_types = &entity_types;
+
+            s.spawn(move |_| {
+                for (seq, type_idx, id) in recvr.iter() {
+                    if failure.is_set() {
+                        break;
+                    }
+
+                    let id = match id {
+                        Some(id) => id,
+                        None => {
+                            if sendr.send((seq, type_idx, Job::TypeDone(None))).is_err() {
+                                break;
+                            }
+                            continue;
+                        }
+                    };
+
+                    let mut entity_versions: Vec<(u32, JSONValue)> = match paged_get(
+                        &fetcher,
+                        "https://api.sibr.dev/chronicler/v2/versions",
+                        ChroniclerParameters {
+                            next_page: None,
+                            entity_type: entity_types[type_idx].to_owned(),
+                            id: Some(id.clone()),
+                            order: Some("asc".to_owned()),
+                            count: 1000,
+                        },
+                    ) {
+                        Ok(versions) => versions
+                            .into_iter()
+                            .map(|e| (e.valid_from.timestamp() as u32, e.data))
+                            .collect(),
+                        Err(e) => {
+                            failure.set(e);
+                            break;
+                        }
+                    };
+
+                    entity_versions.sort_by_key(|v| v.0);
+
+                    let (patches, path_map, base) = encode(entity_versions, checkpoint_every);
+                    let job = Job::Entity {
+                        id,
+                        patches,
+                        path_map,
+                        base,
+                        values: ValueTableDelta::default(),
+                    };
+                    if sendr.send((seq, type_idx, job)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+
+        drop(id_rcv);
+        drop(encoded_snd);
+
+        // value tables have to be filled in entity order for the output to be reproducible, so interning happens on a single thread, in sequence.
+        s.spawn(move |_| {
+            let mut reorder = Reorder::new();
+
+            for (seq, type_idx, job) in encoded_rcv.iter() {
+                for (seq, type_idx, job) in reorder.push(seq, (seq, type_idx, job)) {
+                    let job = match job {
+                        Job::Entity {
+                            id,
+                            patches,
+                            path_map,
+                            base,
+                            ..
+                        } => {
+                            let value_table = value_tables.entry(type_idx).or_default();
+                            let patches = value_table.intern(patches);
+
+                            Job::Entity {
+                                id,
+                                patches,
+                                path_map,
+                                base,
+                                values: value_table.take_delta(),
+                            }
+                        }
+                        Job::TypeDone(_) => Job::TypeDone(value_tables.remove(&type_idx)),
+                    };
+
+                    if interned_snd.send((seq, type_idx, job)).is_err() {
+                        return;
+                    }
+                }
             }
+        });
 
-            compression_bar.set_style(
+        for threadn in 0..n_workers {
+            let (recvr, sendr) = (interned_rcv.clone(), compressed_snd.clone());
+            let zstd_dict = dict.clone();
+            let pb = bars.add(ProgressBar::new(0));
+
+            pb.set_style(
                 ProgressStyle::default_bar()
                     .template("{msg:.bold} {pos:>7}/{len:7} [{bar:40.blue/cyan}]")
                     .unwrap()
                     .progress_chars("##-"),
             );
 
-            compression_bar.set_message("[compressing patches]");
-
-            for (time, patch) in compression_bar.wrap_iter(patches.into_iter()) {
-                let start_pos = out.stream_position().map_err(VCRError::IOError)? as u32;
-                header_encoder
-                    .write_patch(time, start_pos - last_position)
-                    .unwrap();
+            pb.set_message(format!("[THREAD {} - compressing]", threadn + 1));
 
-                let patch_bytes = patch.concat();
-                out.write_all(
-                    &patch_compressor
-                        .compress(&patch_bytes, compression_level)
-                        .unwrap(),
-                )
-                .unwrap();
-
-                last_position = start_pos;
+            if !matches.is_present("WHEE") {
+                pb.set_draw_target(ProgressDrawTarget::hidden());
             }
 
-            compression_bar.finish_and_clear();
-
-            let header = header_encoder.release();
-            entity_table_writer
-                .write_varint(header.len() as u32)
-                .unwrap();
-            entity_table_writer
-                .write_varint(out.stream_position().unwrap() as u32)
-                .unwrap();
-            entity_table_writer
-                .write_all(Uuid::parse_str(&id).unwrap().as_bytes())
-                .unwrap();
-            entity_table_writer.write_all(&header).unwrap();
-
-            out.flush().map_err(VCRError::IOError)?;
+            s.spawn(move |_| {
+                let mut compressor = match zstd_dict {
+                    Some(dict) => zstd::block::Compressor::with_dict(dict),
+                    None => zstd::block::Compressor::new(),
+                };
+
+                for (seq, type_idx, job) in recvr.iter() {
+                    let job = match job {
+                        Job::Entity {
+                            id,
+                            patches,
+                            path_map,
+                            base,
+                            values,
+                        } => {
+                            pb.set_length(patches.len() as u64);
+                            let patches = patches
+                                .into_iter()
+                                .map(|(t, v)| {
+                                    pb.inc(1);
+                                    compressor
+                                        .compress(&v.concat(), compression_level)
+                                        .map(|compressed| (t, compressed))
+                                })
+                                .collect::<Result<Vec<(u32, Vec<u8>)>, _>>();
+                            pb.set_position(0);
+
+                            let patches = match patches {
+                                Ok(patches) => patches,
+                                Err(e) => {
+                                    failure.set(e);
+                                    break;
+                                }
+                            };
+
+                            Job::Entity {
+                                id,
+                                patches,
+                                path_map,
+                                base,
+                                values,
+                            }
+                        }
+                        Job::TypeDone(values) => Job::TypeDone(values),
+                    };
+
+                    if sendr.send((seq, type_idx, job)).is_err() {
+                        break;
+                    }
+                }
+            });
         }
 
-        entity_id_bar.finish_with_message("done!");
-        entity_table_writer.finish().unwrap();
+        drop(interned_rcv);
+        drop(compressed_snd);
+
+        let mut write = || -> VCRResult<()> {
+            let mut reorder = Reorder::new();
+            let mut writer: Option<BufWriter<File>> = None;
+
+            for (seq, type_idx, job) in compressed_rcv.iter() {
+                for (type_idx, job) in reorder.push(seq, (type_idx, job)) {
+                    let etype = entity_types[type_idx];
+                    let journal = journals[type_idx].as_mut().unwrap();
+                    if writer.is_none() {
+                        let out_file =
+                            journal.open_tape(base_path.join(format!("{}.riv", etype)))?;
+                        writer = Some(BufWriter::new(out_file));
+                    }
+
+                    match job {
+                        Job::Entity {
+                            id,
+                            patches,
+                            path_map,
+                            base,
+                            values,
+                        } => {
+                            progress_bar.set_message(format!("writing {} {}", etype, id));
+                            let out = writer.as_mut().unwrap();
+
+                            let mut last_position = out.stream_position()? as u32;
+                            let mut header_encoder = HeaderEncoder::new(
+                                base,
+                                checkpoint_every,
+                                path_map,
+                                last_position,
+                                Vec::new(),
+                            )?;
+
+                            for (time, patch) in patches {
+                                let start_pos = out.stream_position()? as u32;
+                                header_encoder.write_patch(time, start_pos - last_position)?;
+
+                                out.write_all(&patch)?;
+                                last_position = start_pos;
+                            }
+
+                            out.flush()?;
+                            journal.record(JournalEntry {
+                                id,
+                                end_position: out.stream_position()?,
+                                header: header_encoder.release(),
+                                values: Some(values),
+                                game: None,
+                                summary: None,
+                                texts: None,
+                            })?;
+
+                            progress_bar.inc(1);
+                        }
+                        Job::TypeDone(value_table) => {
+                            let mut out = writer.take().unwrap();
+                            out.flush()?;
+                            out.get_mut().sync_all()?;
+
+                            let entity_table_f =
+                                File::create(base_path.join(format!("{}.header.riv.zstd", etype)))?;
+                            let mut entity_table_writer = zstd::Encoder::new(entity_table_f, 21)?;
+                            journal.write_entity_table(&mut entity_table_writer)?;
+                            entity_table_writer.finish()?;
+
+                            // written even when empty, so every finished type has the same set of files
+                            let value_table = value_table.unwrap_or_default();
+                            let values_f =
+                                File::create(base_path.join(format!("{}.values.riv.zstd", etype)))?;
+                            value_table.write(values_f)?;
+
+                            journals[type_idx].take().unwrap().finish()?;
+
+                            let stats = value_table.stats();
+                            progress_bar.println(format!(
+                                "| {}: interned {} values, referenced {} times - {} bytes of values replaced by a {} byte table ({} bytes saved before compression)",
+                                etype,
+                                stats.values,
+                                stats.references,
+                                stats.inline_bytes,
+                                stats.table_bytes,
+                                stats.saved_bytes()
+                            ));
+                        }
+                    }
+                }
+            }
+
+            Ok(())
+        };
 
-        out.get_mut().sync_all().map_err(VCRError::IOError)?;
+        if let Err(e) = write() {
+            failure.set(e);
+        }
+        // stops the stages still sending, if writing is what failed
+        drop(compressed_rcv);
+    })
+    .unwrap();
+
+    if let Some(e) = failure.take() {
+        progress_bar.abandon_with_message(
+            "stopped - run again with --resume to pick up from the last entity written",
+        );
+        return Err(e);
     }
 
+    progress_bar.finish_with_message("done!");
+
     Ok(())
 }
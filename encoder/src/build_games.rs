@@ -1,5 +1,9 @@
 // this is a bit of a mess
 
+use ::encoder::fetch::Fetcher;
+use ::encoder::journal::{BuildJournal, JournalEntry};
+use ::encoder::pipeline::{Failure, Reorder};
+use anyhow::Context;
 use blaseball_vcr::encoder::*;
 use blaseball_vcr::*;
 use chrono::{DateTime, Utc};
@@ -8,14 +12,12 @@ use crossbeam::channel::bounded;
 use indicatif::{
     MultiProgress, MultiProgressAlignment, ProgressBar, ProgressDrawTarget, ProgressStyle,
 };
-use integer_encoding::VarIntWriter;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::Value as JSONValue;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs::File;
 use std::io::{BufWriter, Read, Seek, Write};
 use std::path::Path;
-use uuid::Uuid;
 
 #[derive(Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -48,16 +50,36 @@ struct ChroniclerGameParameters {
     count: Option<u32>,
 }
 
+/// A game in the date table: its id, start time and end time.
+type DateTableEntry = (String, Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// A game's start and end time.
+type GameTimes = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+/// Patches as they come out of `encode`, before compression.
+type EncodedPatches = Vec<(u32, Vec<Vec<u8>>)>;
+
+/// A game on its way through the pipeline: `P` is its patches, encoded and then compressed.
+struct EncodedGame<P> {
+    id: String,
+    patches: P,
+    path_map: BTreeMap<u16, String>,
+    base: JSONValue,
+    /// what interning the game's patches added to the value table
+    values: ValueTableDelta,
+    indexed: Option<(GameIndexEntry, GameSummary)>,
+    texts: Option<Vec<(u32, String)>>,
+}
+
 fn paged_get<T: DeserializeOwned>(
-    client: &reqwest::blocking::Client,
+    fetcher: &Fetcher,
     url: &str,
     mut parameters: ChroniclerGameParameters,
 ) -> anyhow::Result<Vec<T>> {
     let mut results: Vec<T> = Vec::new();
 
     loop {
-        let mut chron_response: ChroniclerV1Response<T> =
-            client.get(url).query(&parameters).send()?.json()?;
+        let mut chron_response: ChroniclerV1Response<T> = fetcher.get_json(url, &parameters)?;
         let res_len = chron_response.data.len() as u32;
         results.append(&mut chron_response.data);
 
@@ -73,126 +95,236 @@ fn paged_get<T: DeserializeOwned>(
     Ok(results)
 }
 
+fn fetch_game(
+    fetcher: &Fetcher,
+    id: String,
+    build_search: bool,
+) -> anyhow::Result<EncodedGame<EncodedPatches>> {
+    let mut entity_versions: Vec<(u32, JSONValue)> = paged_get::<GameUpdate>(
+        fetcher,
+        "https://api.sibr.dev/chronicler/v1/games/updates",
+        ChroniclerGameParameters {
+            next_page: None,
+            game: Some(id.to_owned()),
+            order: Some("asc".to_owned()),
+            count: Some(1000),
+        },
+    )
+    .with_context(|| format!("fetching updates for game {}", id))?
+    .into_iter()
+    .map(|e| (e.timestamp.timestamp() as u32, e.data))
+    .collect();
+
+    entity_versions.sort_by_key(|v| v.0);
+    let indexed = entity_versions.last().map(|(_, data)| {
+        (
+            GameIndexEntry::from_game(data),
+            GameSummary::from_game(&id, data),
+        )
+    });
+    let texts = if build_search {
+        Some(game_update_texts(&entity_versions))
+    } else {
+        None
+    };
+    let (patches, path_map, base) = encode(entity_versions, u16::MAX);
+
+    Ok(EncodedGame {
+        id,
+        patches,
+        path_map,
+        base,
+        values: ValueTableDelta::default(),
+        indexed,
+        texts,
+    })
+}
+
 pub fn main() -> VCRResult<()> {
+    let matches = clap_app!(build_games =>
+        (version: "1.0")
+        (author: "allie signet <allie@sibr.dev>")
+        (about: "blaseball.vcr game update encoder")
+        (@arg ZSTD_DICT: -d --dict [FILE] "set zstd dictionary to use")
+        (@arg COMPRESSION_LEVEL: -l --level [LEVEL] "set compression level")
+        (@arg THREADS: -t --threads [THREADS] "set amount of threads to use")
+        (@arg WHEE: --whee "show extra progress bars for patch compression")
+        (@arg RESUME: --resume "pick up an interrupted build where it left off")
+        (@arg SEARCH: --search "also build a full-text index over update text")
+        (@arg OUT: <FOLDER> "set output folder")
+    )
+    .get_matches();
+
+    let dict_path = matches.value_of("ZSTD_DICT").unwrap_or("nodict");
+    let compression_level = matches
+        .value_of("COMPRESSION_LEVEL")
+        .unwrap_or("19")
+        .parse::<i32>()
+        .unwrap();
+    let n_workers = matches
+        .value_of("THREADS")
+        .unwrap_or("2")
+        .parse::<i32>()
+        .unwrap();
+    let base_path = Path::new(matches.value_of("OUT").unwrap());
+    let main_path = base_path.join("game_updates.riv");
+    let date_table_path = base_path.join("game_updates.dates.riv.zstd");
+    let index_path = base_path.join("game_updates.index.riv.zstd");
+    let summaries_path = base_path.join("game_updates.summaries.riv.zstd");
+    let search_path = base_path.join("game_updates.search.riv.zstd");
+    let build_search = matches.is_present("SEARCH");
+    let header_path = base_path.join("game_updates.header.riv.zstd");
+    let values_path = base_path.join("game_updates.values.riv.zstd");
+    let journal_path = base_path.join("game_updates.journal");
+    let resume = matches.is_present("RESUME");
+
+    // the journal is only removed once every output has been written
+    if resume && !journal_path.exists() && header_path.exists() {
+        println!("| game_updates is already done, skipping");
+        return Ok(());
+    }
+
+    let mut journal = BuildJournal::open(journal_path, resume)?;
+
+    let mut value_table = ValueTable::new();
+    for entry in journal.entries() {
+        if let Some(delta) = &entry.values {
+            value_table.apply_delta(delta.clone());
+        }
+    }
+
+    println!(
+        "Set zstd dictionary to {} and compression level to {}",
+        dict_path, compression_level
+    );
+
+    let fetcher = Fetcher::from_env();
+
+    let mut dict_f = File::open(dict_path)?;
+    let mut dict: Vec<u8> = Vec::new();
+    dict_f.read_to_end(&mut dict)?;
+
+    let bars = MultiProgress::new();
+    bars.set_alignment(MultiProgressAlignment::Top);
+    bars.set_draw_target(ProgressDrawTarget::stderr_with_hz(10));
+
+    let spinny = bars.add(ProgressBar::new_spinner());
+    spinny.enable_steady_tick(std::time::Duration::from_millis(120));
+    spinny.set_style(
+        ProgressStyle::default_spinner()
+            .template("{spinner:.blue} {msg}")
+            .unwrap(),
+    );
+    spinny.set_message("fetching game list..");
+
+    let games: Vec<Game> = paged_get::<Game>(
+        &fetcher,
+        "https://api.sibr.dev/chronicler/v1/games",
+        ChroniclerGameParameters {
+            next_page: None,
+            game: None,
+            order: None,
+            count: None,
+        },
+    )?;
+
+    spinny.finish_and_clear();
+    bars.remove(&spinny);
+
+    // games are written in list order, so whatever's in the journal is always a prefix of the list
+    let completed = journal.completed();
+    let pending: Vec<String> = games
+        .iter()
+        .map(|game| game.game_id.clone())
+        .filter(|id| !completed.contains(id))
+        .collect();
+    let pending_count = pending.len();
+
+    println!(
+        "| found {} entities, {} left to encode",
+        games.len(),
+        pending_count
+    );
+    let progress_bar = bars.add(ProgressBar::new(pending_count as u64));
+    progress_bar.set_style(
+        ProgressStyle::default_bar()
+            .template("{msg:.bold} {pos:>7}/{len:7} \n{percent:.bold}% {bar:70.green/white}")
+            .unwrap(),
+    );
+    progress_bar.tick();
+
+    let out_file = journal.open_tape(main_path)?;
+    let mut out = BufWriter::new(out_file);
+
+    // feeder -> fetch workers -> sequencer (value interning) -> compression workers -> writer
     let (snd1, rcv1) = bounded(1);
     let (snd2, rcv2) = bounded(1);
+    let (snd3, rcv3) = bounded(1);
+    let (snd4, rcv4) = bounded(1);
 
-    crossbeam::scope(|s| {
-        let matches = clap_app!(build_games =>
-            (version: "1.0")
-            (author: "allie signet <allie@sibr.dev>")
-            (about: "blaseball.vcr game update encoder")
-            (@arg ZSTD_DICT: -d --dict [FILE] "set zstd dictionary to use")
-            (@arg COMPRESSION_LEVEL: -l --level [LEVEL] "set compression level")
-            (@arg THREADS: -t --threads [THREADS] "set amount of threads to use")
-            (@arg WHEE: --whee "show extra progress bars for patch compression")
-            (@arg OUT: <FOLDER> "set output folder")
-        )
-        .get_matches();
-
-        let dict_path = matches.value_of("ZSTD_DICT").unwrap_or("nodict");
-        let compression_level = matches
-            .value_of("COMPRESSION_LEVEL")
-            .unwrap_or("19")
-            .parse::<i32>()
-            .unwrap();
-        let n_workers = matches
-            .value_of("THREADS")
-            .unwrap_or("2")
-            .parse::<i32>()
-            .unwrap();
-        let base_path = Path::new(matches.value_of("OUT").unwrap());
-        let main_path = base_path.join("game_updates.riv");
-        let date_table_path = base_path.join("game_updates.dates.riv.zstd");
-        let header_path = base_path.join("game_updates.header.riv.zstd");
-
-        println!(
-            "Set zstd dictionary to {} and compression level to {}",
-            dict_path, compression_level
-        );
+    let failure = Failure::default();
+    let failure = &failure;
 
-        let client = reqwest::blocking::Client::new();
+    let (value_table, written) = crossbeam::scope(|s| {
+        s.spawn(move |_| {
+            for (n, id) in pending.into_iter().enumerate() {
+                if failure.is_set() || snd1.send((n, id)).is_err() {
+                    break;
+                }
+            }
 
-        let mut dict_f = File::open(dict_path).unwrap();
-        let mut dict: Vec<u8> = Vec::new();
-        dict_f.read_to_end(&mut dict).unwrap();
+            drop(snd1);
+        });
 
-        let bars = MultiProgress::new();
-        bars.set_alignment(MultiProgressAlignment::Top);
-        bars.set_draw_target(ProgressDrawTarget::stderr_with_hz(10));
+        for _ in 0..n_workers {
+            let (sendr, recvr) = (snd2.clone(), rcv1.clone());
+            let fetcher = fetcher.clone();
 
-        let spinny = bars.add(ProgressBar::new_spinner());
-        spinny.enable_steady_tick(std::time::Duration::from_millis(120));
-        spinny.set_style(
-            ProgressStyle::default_spinner()
-                .template("{spinner:.blue} {msg}")
-                .unwrap(),
-        );
-        spinny.set_message("fetching game list..");
-
-        let games: Vec<Game> = paged_get::<Game>(
-            &client,
-            "https://api.sibr.dev/chronicler/v1/games",
-            ChroniclerGameParameters {
-                next_page: None,
-                game: None,
-                order: None,
-                count: None,
-            },
-        )
-        .unwrap()
-        .into_iter()
-        .collect();
+            s.spawn(move |_| {
+                for (n, id) in recvr.iter() {
+                    if failure.is_set() {
+                        break;
+                    }
+
+                    // errors go down the channel too, so they get reported in game order
+                    let game = fetch_game(&fetcher, id, build_search);
+                    if sendr.send((n, game)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
 
-        spinny.finish_and_clear();
-        bars.remove(&spinny);
+        drop(rcv1);
+        drop(snd2);
 
-        println!("| found {} entities", games.len());
-        let progress_bar = bars.add(ProgressBar::new(games.len() as u64));
-        progress_bar.set_style(
-            ProgressStyle::default_bar()
-                .template("{msg:.bold} {pos:>7}/{len:7} \n{percent:.bold}% {bar:70.green/white}")
-                .unwrap(),
-        );
-        progress_bar.tick();
-
-        let out_file = File::create(main_path).unwrap();
-        let mut out = BufWriter::new(out_file);
-
-        s.spawn(|_| {
-            let mut game_date_lookup_table: HashMap<
-                GameDate,
-                Vec<(String, Option<DateTime<Utc>>, Option<DateTime<Utc>>)>,
-            > = HashMap::new();
-
-            for game in games {
-                let game_date = game.data;
-                let id = game.game_id;
-
-                if let Some(date_idx) = game_date_lookup_table.get_mut(&game_date) {
-                    (*date_idx).push((id.to_owned(), game.start_time, game.end_time));
-                } else {
-                    game_date_lookup_table.insert(
-                        game_date,
-                        vec![(id.to_owned(), game.start_time, game.end_time)],
-                    );
+        // the value table has to be filled in game order for the output to be reproducible, so interning happens on a single thread, in sequence.
+        let interner = s.spawn(move |_| {
+            let mut reorder = Reorder::new();
+
+            'games: for (n, game) in rcv2.iter() {
+                for (n, game) in reorder.push(n, (n, game)) {
+                    let mut game = match game {
+                        Ok(game) => game,
+                        Err(e) => {
+                            failure.set(e);
+                            break 'games;
+                        }
+                    };
+                    game.patches = value_table.intern(game.patches);
+                    game.values = value_table.take_delta();
+                    if snd3.send((n, game)).is_err() {
+                        break 'games;
+                    }
                 }
-
-                snd1.send(id).unwrap();
             }
 
-            let date_table_f = File::create(date_table_path).unwrap();
-            let mut date_table_writer = zstd::Encoder::new(date_table_f, 21).unwrap();
-            date_table_writer
-                .write_all(&rmp_serde::to_vec(&game_date_lookup_table).unwrap())
-                .unwrap();
-            date_table_writer.finish().unwrap();
-
-            drop(snd1);
+            drop(snd3);
+            value_table
         });
 
         for threadn in 0..n_workers {
-            let (sendr, recvr) = (snd2.clone(), rcv1.clone());
+            let (sendr, recvr) = (snd4.clone(), rcv3.clone());
             let zstd_dict = dict.clone();
             let pb = bars.add(ProgressBar::new(0));
 
@@ -211,93 +343,206 @@ pub fn main() -> VCRResult<()> {
 
             s.spawn(move |_| {
                 let mut compressor = zstd::block::Compressor::with_dict(zstd_dict);
-                let loc_client = reqwest::blocking::Client::new();
-                for id in recvr.iter() {
-                    let mut entity_versions: Vec<(u32, JSONValue)> = paged_get::<GameUpdate>(
-                        &loc_client,
-                        "https://api.sibr.dev/chronicler/v1/games/updates",
-                        ChroniclerGameParameters {
-                            next_page: None,
-                            game: Some(id.to_owned()),
-                            order: Some("asc".to_owned()),
-                            count: Some(1000),
-                        },
-                    )
-                    .unwrap()
-                    .into_iter()
-                    .map(|e| (e.timestamp.timestamp() as u32, e.data))
-                    .collect();
-
-                    entity_versions.sort_by_key(|v| v.0);
-                    let (patches, path_map, base) = encode(entity_versions, u16::MAX);
-                    pb.set_length(patches.len() as u64);
-                    sendr
-                        .send((
-                            id,
-                            patches
-                                .into_iter()
-                                .map(|(t, v)| {
-                                    pb.inc(1);
-                                    (
-                                        t,
-                                        compressor
-                                            .compress(&v.concat(), compression_level)
-                                            .unwrap(),
-                                    )
-                                })
-                                .collect::<Vec<(u32, Vec<u8>)>>(),
-                            path_map,
-                            base,
-                        ))
-                        .unwrap();
+                for (n, game) in recvr.iter() {
+                    pb.set_length(game.patches.len() as u64);
+                    let patches = game
+                        .patches
+                        .into_iter()
+                        .map(|(t, v)| {
+                            pb.inc(1);
+                            compressor
+                                .compress(&v.concat(), compression_level)
+                                .map(|compressed| (t, compressed))
+                        })
+                        .collect::<Result<Vec<(u32, Vec<u8>)>, _>>();
                     pb.set_position(0);
+
+                    let patches = match patches {
+                        Ok(patches) => patches,
+                        Err(e) => {
+                            failure.set(e);
+                            break;
+                        }
+                    };
+
+                    let game = EncodedGame {
+                        patches,
+                        id: game.id,
+                        path_map: game.path_map,
+                        base: game.base,
+                        values: game.values,
+                        indexed: game.indexed,
+                        texts: game.texts,
+                    };
+                    if sendr.send((n, game)).is_err() {
+                        break;
+                    }
                 }
             });
         }
 
-        drop(snd2);
+        drop(rcv3);
+        drop(snd4);
+
+        // games are written in the order they were listed in, regardless of which worker finishes first, so that the output is reproducible.
+        let mut write = || -> VCRResult<usize> {
+            let mut reorder = Reorder::new();
+            let mut written = 0;
+
+            for (n, game) in rcv4.iter() {
+                for game in reorder.push(n, game) {
+                    progress_bar.set_message(format!("writing game {}", game.id));
+
+                    let mut last_position = out.stream_position()? as u32;
+                    let mut header_encoder = HeaderEncoder::new(
+                        game.base,
+                        u16::MAX,
+                        game.path_map,
+                        last_position,
+                        Vec::new(),
+                    )?;
+
+                    for (time, patch) in game.patches {
+                        let start_pos = out.stream_position()? as u32;
+                        header_encoder.write_patch(time, start_pos - last_position)?;
+
+                        out.write_all(&patch)?;
+                        last_position = start_pos;
+                    }
+
+                    out.flush()?;
+                    let (index_entry, summary) = match game.indexed {
+                        Some((index_entry, summary)) => (Some(index_entry), Some(summary)),
+                        None => (None, None),
+                    };
+                    journal.record(JournalEntry {
+                        id: game.id,
+                        end_position: out.stream_position()?,
+                        header: header_encoder.release(),
+                        values: Some(game.values),
+                        game: index_entry,
+                        summary,
+                        texts: game.texts,
+                    })?;
+
+                    written += 1;
+                    progress_bar.inc(1);
+                }
+            }
+
+            Ok(written)
+        };
+
+        let written = write().unwrap_or_else(|e| {
+            failure.set(e);
+            0
+        });
+        // stops the stages still sending, if writing is what failed
+        drop(rcv4);
+
+        (interner.join().unwrap(), written)
+    })
+    .unwrap();
+
+    if failure.is_set() || written != pending_count {
+        progress_bar.abandon_with_message(
+            "stopped - run again with --resume to pick up from the last game written",
+        );
+        return Err(failure.take().unwrap_or_else(|| {
+            VCRError::Other(anyhow::anyhow!(
+                "only {} of {} games were written",
+                written,
+                pending_count
+            ))
+        }));
+    }
+
+    progress_bar.finish_with_message("done!");
+    out.get_mut().sync_all()?;
 
-        let entity_table_f = File::create(header_path).unwrap();
-        let mut entity_table_writer = zstd::Encoder::new(entity_table_f, 21).unwrap();
-        entity_table_writer.long_distance_matching(true).unwrap();
+    let entity_table_f = File::create(header_path)?;
+    let mut entity_table_writer = zstd::Encoder::new(entity_table_f, 21)?;
+    entity_table_writer.long_distance_matching(true)?;
+    journal.write_entity_table(&mut entity_table_writer)?;
+    entity_table_writer.finish()?;
 
-        for (id, patches, path_map, base) in progress_bar.wrap_iter(rcv2.iter()) {
-            progress_bar.set_message(format!("writing game {}", id));
+    value_table.write(File::create(values_path)?)?;
 
-            let mut last_position = out.stream_position().unwrap() as u32;
-            let mut header_encoder =
-                HeaderEncoder::new(base, u16::MAX, path_map, last_position, Vec::new()).unwrap();
+    // only index games that actually made it into the tape
+    let completed = journal.completed();
+    let mut game_date_lookup_table: BTreeMap<GameDate, Vec<DateTableEntry>> = BTreeMap::new();
 
-            for (time, patch) in patches {
-                let start_pos = out.stream_position().map_err(VCRError::IOError).unwrap() as u32;
-                header_encoder
-                    .write_patch(time, start_pos - last_position)
-                    .unwrap();
+    for game in games.into_iter().filter(|g| completed.contains(&g.game_id)) {
+        game_date_lookup_table.entry(game.data).or_default().push((
+            game.game_id,
+            game.start_time,
+            game.end_time,
+        ));
+    }
+
+    let date_table_f = File::create(date_table_path)?;
+    let mut date_table_writer = zstd::Encoder::new(date_table_f, 21)?;
+    date_table_writer.write_all(&rmp_serde::to_vec(&game_date_lookup_table)?)?;
+    date_table_writer.finish()?;
+
+    // filter fields for every game, so /games doesn't have to decode them to filter
+    let mut times: BTreeMap<&String, GameTimes> = BTreeMap::new();
+    for (id, start_time, end_time) in game_date_lookup_table.values().flatten() {
+        times.insert(id, (*start_time, *end_time));
+    }
+
+    let index: BTreeMap<&String, GameIndexEntry> = journal
+        .entries()
+        .iter()
+        .filter_map(|entry| {
+            let mut game = entry.game.clone()?;
+            if let Some((start_time, end_time)) = times.get(&entry.id) {
+                game.start_time = *start_time;
+                game.end_time = *end_time;
+            }
+            Some((&entry.id, game))
+        })
+        .collect();
+
+    let index_f = File::create(index_path)?;
+    let mut index_writer = zstd::Encoder::new(index_f, 21)?;
+    index_writer.write_all(&rmp_serde::to_vec(&index)?)?;
+    index_writer.finish()?;
 
-                out.write_all(&patch).unwrap();
-                last_position = start_pos;
+    let summaries: BTreeMap<&String, &GameSummary> = journal
+        .entries()
+        .iter()
+        .filter_map(|entry| Some((&entry.id, entry.summary.as_ref()?)))
+        .collect();
+
+    let summaries_f = File::create(summaries_path)?;
+    let mut summaries_writer = zstd::Encoder::new(summaries_f, 21)?;
+    summaries_writer.write_all(&rmp_serde::to_vec(&summaries)?)?;
+    summaries_writer.finish()?;
+
+    if build_search {
+        let mut dates: BTreeMap<&String, &GameDate> = BTreeMap::new();
+        for (date, games) in &game_date_lookup_table {
+            for (id, _, _) in games {
+                dates.insert(id, date);
             }
+        }
 
-            let header = header_encoder.release();
-            entity_table_writer
-                .write_varint(header.len() as u32)
-                .unwrap();
-            entity_table_writer
-                .write_varint(out.stream_position().unwrap() as u32)
-                .unwrap();
-            entity_table_writer
-                .write_all(Uuid::parse_str(&id).unwrap().as_bytes())
-                .unwrap();
-            entity_table_writer.write_all(&header).unwrap();
-
-            out.flush().map_err(VCRError::IOError).unwrap();
+        let mut search_index = SearchIndex::new();
+        for entry in journal.entries() {
+            // games journaled by a build without --search have no texts to index
+            if let (Some(texts), Some(date)) = (&entry.texts, dates.get(&entry.id)) {
+                search_index.add_game(&entry.id, date.season as i64, date.day as i64, texts);
+            }
         }
 
-        progress_bar.finish_with_message("done!");
+        let search_f = File::create(search_path)?;
+        let mut search_writer = zstd::Encoder::new(search_f, 21)?;
+        search_writer.write_all(&rmp_serde::to_vec(&search_index)?)?;
+        search_writer.finish()?;
+    }
 
-        entity_table_writer.finish().unwrap();
-    })
-    .unwrap();
+    journal.finish()?;
 
     Ok(())
 }
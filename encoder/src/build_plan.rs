@@ -0,0 +1,85 @@
+use ::encoder::plan::BuildPlan;
+use clap::clap_app;
+use std::env;
+use std::path::PathBuf;
+use std::process::Command;
+
+pub fn main() -> anyhow::Result<()> {
+    let matches = clap_app!(build_plan =>
+        (version: "1.0")
+        (author: "allie signet <allie@sibr.dev>")
+        (about: "runs every encoder step in a TOML build plan")
+        (@arg FORCE: -f --force "rebuild every step, even if it looks up to date")
+        (@arg DRY_RUN: -n --("dry-run") "print the steps that would run without running them")
+        (@arg RESUME: --resume "pass --resume to steps that support it")
+        (@arg BIN_DIR: --bin [FOLDER] "folder containing the encoder binaries (default: next to this one)")
+        (@arg PLAN: [FILE] "build plan to run (default: ./build.toml)")
+    )
+    .get_matches();
+
+    let plan = BuildPlan::from_file(matches.value_of("PLAN").unwrap_or("./build.toml"))?;
+    let stamp_dir = plan.output.join(".plan");
+
+    let bin_dir = match matches.value_of("BIN_DIR") {
+        Some(dir) => PathBuf::from(dir),
+        None => env::current_exe()?
+            .parent()
+            .map(|p| p.to_path_buf())
+            .unwrap_or_default(),
+    };
+
+    let force = matches.is_present("FORCE");
+    let dry_run = matches.is_present("DRY_RUN");
+    let resume = matches.is_present("RESUME");
+
+    let steps = plan.steps();
+    let total = steps.len();
+
+    for (i, step) in steps.into_iter().enumerate() {
+        if !force && step.is_up_to_date(&stamp_dir) {
+            println!(
+                "[{}/{}] {} is up to date, skipping",
+                i + 1,
+                total,
+                step.name
+            );
+            continue;
+        }
+
+        let mut args = step.args.clone();
+        if resume && step.resumable {
+            args.insert(0, "--resume".to_owned());
+        }
+
+        println!(
+            "[{}/{}] {}: {} {}",
+            i + 1,
+            total,
+            step.name,
+            step.binary,
+            args.join(" ")
+        );
+
+        if dry_run {
+            continue;
+        }
+
+        for output in &step.outputs {
+            if let Some(parent) = output.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+        }
+
+        let status = Command::new(bin_dir.join(step.binary))
+            .args(&args)
+            .status()?;
+
+        if !status.success() {
+            anyhow::bail!("step {} failed ({})", step.name, status);
+        }
+
+        step.mark_done(&stamp_dir)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,131 @@
+use ::encoder::tapes::open_multi;
+use blaseball_vcr::encoder::*;
+use blaseball_vcr::*;
+use chrono::DateTime;
+use clap::clap_app;
+use indicatif::ProgressBar;
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+// precomputes every streamData frame from the other tapes in a folder, and stores them as the single entity of a `stream` tape.
+// the player reads frames from it when it's there instead of putting them together from a few dozen tapes every time.
+pub fn main() -> VCRResult<()> {
+    let matches = clap_app!(build_stream =>
+        (version: "1.0")
+        (author: "allie signet <allie@sibr.dev>")
+        (about: "blaseball.vcr stream frame encoder")
+        (@arg ZSTD_DICT: -d --dict [FILE] "set zstd dictionary to compress frames with")
+        (@arg COMPRESSION_LEVEL: -l --level [LEVEL] "set compression level")
+        (@arg CHECKPOINTS: -c --checkpoints [CHECKPOINTS] "make a checkpoint every n frames (default: 1000)")
+        (@arg DICTS: --dicts [FOLDER] "zstd dictionaries the other tapes were encoded with (default: ./zstd-dictionaries)")
+        (@arg AFTER: --after [TIME] "start from this RFC 3339 time instead of the first sim version")
+        (@arg BEFORE: --before [TIME] "stop at this RFC 3339 time instead of the last version of anything")
+        (@arg TAPES: [FOLDER] "tapes folder to read from and write to (default: ./tapes)")
+    )
+    .get_matches();
+
+    let compression_level = matches
+        .value_of("COMPRESSION_LEVEL")
+        .map(|v| v.parse::<i32>().unwrap())
+        .unwrap_or(19);
+
+    let checkpoint_every = matches
+        .value_of("CHECKPOINTS")
+        .map(|v| v.parse::<u16>().unwrap())
+        .unwrap_or(1000);
+
+    let parse_time = |name: &str| {
+        matches
+            .value_of(name)
+            .map(|v| DateTime::parse_from_rfc3339(v).unwrap().timestamp() as u32)
+    };
+    let after = parse_time("AFTER");
+    let before = parse_time("BEFORE").unwrap_or(u32::MAX);
+
+    let base_path = Path::new(matches.value_of("TAPES").unwrap_or("./tapes"));
+    let dicts = Path::new(matches.value_of("DICTS").unwrap_or("./zstd-dictionaries"));
+
+    let mut db = open_multi(base_path, dicts, 30)?;
+    // frames have to come from the other tapes, not from an earlier run of this
+    db.dbs.remove("stream");
+
+    let mut compressor = match matches.value_of("ZSTD_DICT") {
+        Some(path) => zstd::block::Compressor::with_dict(fs::read(path)?),
+        None => zstd::block::Compressor::new(),
+    };
+
+    let first_sim = db
+        .version_span("sim", "00000000-0000-0000-0000-000000000000", 0)?
+        .1
+        .ok_or(VCRError::EntityNotFound)?;
+    let mut at = after.map_or(first_sim, |after| after.max(first_sim));
+
+    let mut out = BufWriter::new(File::create(base_path.join("stream.riv"))?);
+    let mut builder = StreamBuilder::new(&db);
+    let mut encoder: Option<PatchEncoder> = None;
+    let mut value_table = ValueTable::new();
+    // time and start position of every patch, since the header can't be written until the path map is done
+    let mut patches: Vec<(u32, u32)> = Vec::new();
+
+    let progress_bar = ProgressBar::new_spinner();
+
+    // compresses a patch onto the end of the tape, returning where it starts
+    let mut write_patch = |out: &mut BufWriter<File>, patch: Vec<Vec<u8>>| {
+        let start_pos = out.stream_position()? as u32;
+        out.write_all(&compressor.compress(&patch.concat(), compression_level)?)?;
+        VCRResult::Ok(start_pos)
+    };
+
+    while at < before {
+        let frame = flatten_frame(&builder.frame(at)?);
+        let (time, mut patch) = encoder
+            .get_or_insert_with(|| PatchEncoder::new(&frame, checkpoint_every))
+            .push(at, frame);
+        value_table.intern_ops(&mut patch);
+        patches.push((time, write_patch(&mut out, patch)?));
+
+        progress_bar.set_message(format!("{} frames, at {}", patches.len(), at));
+        progress_bar.tick();
+
+        match builder.frame_span().1 {
+            Some(next) => at = next,
+            None => break,
+        }
+    }
+
+    let encoder = encoder.ok_or(VCRError::EntityNotFound)?;
+    let last_time = patches.last().map(|(time, _)| *time).unwrap();
+    let (_, patch) = encoder.last_version(last_time);
+    patches.push((last_time, write_patch(&mut out, patch)?));
+    out.flush()?;
+    let end_position = out.stream_position()? as u32;
+
+    let (path_map, base) = encoder.finish();
+    let mut last_position = 0;
+    let mut header_encoder =
+        HeaderEncoder::new(base, checkpoint_every, path_map, last_position, Vec::new())?;
+    for (time, start_pos) in patches.iter().copied() {
+        header_encoder.write_patch(time, start_pos - last_position)?;
+        last_position = start_pos;
+    }
+    let header = header_encoder.release();
+
+    // same layout as the entity tables build_entities writes, with the one entity in it
+    let entity_table_f = File::create(base_path.join("stream.header.riv.zstd"))?;
+    let mut entity_table_writer = zstd::Encoder::new(entity_table_f, 21)?;
+    write_entity_table_entry(
+        &mut entity_table_writer,
+        &Uuid::parse_str(STREAM_ID).unwrap(),
+        end_position,
+        &header,
+    )?;
+    entity_table_writer.finish()?;
+
+    value_table.write(File::create(base_path.join("stream.values.riv.zstd"))?)?;
+
+    progress_bar.finish_with_message(format!("done! {} frames", patches.len()));
+
+    Ok(())
+}
@@ -0,0 +1,174 @@
+use ::encoder::fetch::Fetcher;
+use anyhow::{anyhow, bail};
+use blaseball_vcr::*;
+use clap::clap_app;
+use integer_encoding::VarIntWriter;
+use serde_json::Value as JSONValue;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+/// How many records apart keyframes are, unless `--keyframe-every` says otherwise.
+const DEFAULT_KEYFRAME_EVERY: usize = 100;
+
+// encodes a leaderboard-like entity type (an id -> integer map, like tributes) as the changes between its versions.
+// writes {type}.riv, {type}.header.riv, {type}.keyframes.riv and {type}.counters.riv, the config the tape was built with.
+pub fn main() -> anyhow::Result<()> {
+    let matches = clap_app!(encode_counters =>
+        (version: "1.0")
+        (author: "allie signet <allie@sibr.dev>")
+        (about: "blaseball.vcr counter map encoder")
+        (@arg CONFIG: -c --config [FILE] "TOML file with the type's key kinds and shapes (default: the built-in one for the type)")
+        (@arg KEYFRAME_EVERY: -k --("keyframe-every") [N] "store every count every n records (default: 100)")
+        (@arg TYPE: <TYPE> "entity type")
+        (@arg OUT: [FOLDER] "output folder (default: ./tapes)")
+    )
+    .get_matches();
+
+    let e_type = matches.value_of("TYPE").unwrap();
+    let config: CounterMapConfig = match matches.value_of("CONFIG") {
+        Some(path) => toml::from_str(&fs::read_to_string(path)?)?,
+        None => CounterMapConfig::builtin(e_type)
+            .ok_or_else(|| anyhow!("{} has no built-in config, pass one with --config", e_type))?,
+    };
+    if config.kinds.len() > u8::MAX as usize {
+        bail!("counter maps can't have more than {} key kinds", u8::MAX);
+    }
+
+    let keyframe_every = matches
+        .value_of("KEYFRAME_EVERY")
+        .map(|n| n.parse::<usize>())
+        .transpose()?
+        .unwrap_or(DEFAULT_KEYFRAME_EVERY)
+        .max(1);
+    let base_path = Path::new(matches.value_of("OUT").unwrap_or("./tapes"));
+
+    let fetcher = Fetcher::from_env();
+
+    let mut ids: HashMap<Uuid, (u32, u8)> = HashMap::new();
+    let mut times: Vec<(u32, u32, u32)> = Vec::new();
+    let mut vals: HashMap<Uuid, u64> = HashMap::new();
+    let mut last_seen: Vec<Uuid> = Vec::new();
+    let mut keyframes: Vec<u32> = Vec::new();
+
+    let mut next_page: Option<String> = None;
+
+    let out_f = File::create(base_path.join(format!("{}.riv", e_type)))?;
+    let mut out_writer = BufWriter::new(out_f);
+
+    let mut page = 1;
+
+    loop {
+        println!("#{}", page);
+
+        let parameters = if let Some(ref page) = next_page {
+            vec![("type", e_type), ("count", "1000"), ("page", page)]
+        } else {
+            vec![("type", e_type), ("count", "1000")]
+        };
+
+        let versions: ChroniclerResponse<ChroniclerEntity<JSONValue>> =
+            fetcher.get_json("https://api.sibr.dev/chronicler/v2/versions", &parameters)?;
+
+        for version in versions.items {
+            let start_pos = u32::try_from(out_writer.stream_position()?)?;
+            let valid_from = version.valid_from.timestamp() as u32;
+            if let Some((last, _, _)) = times.last() {
+                if valid_from < *last {
+                    bail!(
+                        "{} versions went back in time ({} after {})",
+                        e_type,
+                        valid_from,
+                        last
+                    );
+                }
+            }
+
+            let counts = config.read_counts(&version.data, valid_from)?;
+            let mut seen_ids: Vec<Uuid> = Vec::with_capacity(counts.len());
+
+            // keyframes write every count, so readers can start replaying from them
+            let keyframe = times.len() % keyframe_every == 0;
+            if keyframe {
+                keyframes.push(times.len() as u32);
+            }
+
+            for (id, kind, n) in counts {
+                seen_ids.push(id);
+                let l = u32::try_from(ids.len() + 1)?;
+                let idx = *ids.entry(id).or_insert((l, kind));
+                // the header stores one kind per id, so an id can't have two
+                if idx.1 != kind {
+                    bail!("{} shows up as two kinds of key", id);
+                }
+
+                if keyframe || vals.get(&id) != Some(&n) {
+                    out_writer.write_varint(idx.0)?;
+                    out_writer.write_varint(n)?;
+                    vals.insert(id, n);
+                }
+            }
+
+            let removed = last_seen
+                .iter()
+                .filter(|i| !seen_ids.contains(i))
+                .copied()
+                .collect::<Vec<Uuid>>();
+            last_seen = seen_ids;
+
+            if !removed.is_empty() {
+                out_writer.write_varint(0)?;
+                out_writer.write_varint(removed.len() as u32)?;
+                for r in removed {
+                    out_writer.write_varint(ids[&r].0)?;
+                    // forget the count, so it gets written again if this id comes back
+                    vals.remove(&r);
+                }
+            }
+
+            let out_pos = u32::try_from(out_writer.stream_position()?)?;
+            times.push((valid_from, start_pos, out_pos - start_pos));
+        }
+
+        page += 1;
+
+        if let Some(page) = versions.next_page {
+            next_page = Some(page);
+        } else {
+            break;
+        }
+    }
+
+    out_writer.flush()?;
+
+    // write ids in index order so the header doesn't depend on hashmap iteration order
+    let mut ids: Vec<(Uuid, u32, u8)> = ids
+        .into_iter()
+        .map(|(id, (idx, kind))| (id, idx, kind))
+        .collect();
+    ids.sort_by_key(|(_, idx, _)| *idx);
+
+    let header_f = File::create(base_path.join(format!("{}.header.riv", e_type)))?;
+    let mut header_writer = BufWriter::new(header_f);
+    write_counters_header(&mut header_writer, &ids, &times)?;
+    header_writer.flush()?;
+
+    let keyframes: Vec<u8> = keyframes
+        .into_iter()
+        .flat_map(|k| k.to_be_bytes())
+        .collect();
+    fs::write(
+        base_path.join(format!("{}.keyframes.riv", e_type)),
+        keyframes,
+    )?;
+
+    fs::write(
+        base_path.join(format!("{}.counters.riv", e_type)),
+        serde_json::to_vec(&config)?,
+    )?;
+
+    Ok(())
+}
@@ -1,9 +1,9 @@
+use ::encoder::fetch::Fetcher;
 use blaseball_vcr::{
     site::{chron, chron::*, *},
     ChroniclerV1Response, VCRError,
 };
 
-use reqwest::blocking;
 use std::env;
 use std::fs::File;
 use std::io::BufWriter;
@@ -34,20 +34,15 @@ pub struct AssetConfig {
 }
 
 pub fn encode_resource<W: Write + Seek>(
+    fetcher: &Fetcher,
     steps: Vec<FileStep>,
     replaces: &[Replace],
     out: &mut W,
 ) -> anyhow::Result<EncodedResource> {
-    let client = reqwest::blocking::Client::new();
-
-    let mut basis: Vec<u8> = Vec::new();
-    let mut basis_response = client
-        .get(&format!(
-            "https://api.sibr.dev/chronicler/v1{}",
-            &steps[0].download_url
-        ))
-        .send()?;
-    basis_response.copy_to(&mut basis)?;
+    let basis: Vec<u8> = fetcher.get_bytes(&format!(
+        "https://api.sibr.dev/chronicler/v1{}",
+        &steps[0].download_url
+    ))?;
 
     let mut last: Vec<u8> = basis.clone();
 
@@ -68,13 +63,10 @@ pub fn encode_resource<W: Write + Seek>(
 
     for step in progress_bar.wrap_iter(steps.into_iter()) {
         let next: Vec<u8> = {
-            let mut basis = client
-                .get(&format!(
-                    "https://api.sibr.dev/chronicler/v1{}",
-                    &step.download_url
-                ))
-                .send()?
-                .text()?;
+            let mut basis = fetcher.get_text(&format!(
+                "https://api.sibr.dev/chronicler/v1{}",
+                &step.download_url
+            ))?;
             for r in replaces {
                 basis = basis.replace(&r.replace, &r.with);
             }
@@ -123,8 +115,11 @@ pub fn encode_resource<W: Write + Seek>(
 
 // usage: download_site_data <out folder> <optional toml file with replaces>
 fn main() -> anyhow::Result<()> {
-    let chron_res: ChroniclerV1Response<chron::SiteUpdate> =
-        blocking::get("https://api.sibr.dev/chronicler/v1/site/updates")?.json()?;
+    let fetcher = Fetcher::from_env();
+    let chron_res: ChroniclerV1Response<chron::SiteUpdate> = fetcher.get_json(
+        "https://api.sibr.dev/chronicler/v1/site/updates",
+        &[] as &[(&str, &str)],
+    )?;
     let all_steps = chron::updates_to_steps(chron_res.data);
     let args: Vec<String> = env::args().collect();
 
@@ -139,6 +134,8 @@ fn main() -> anyhow::Result<()> {
         }
     };
 
+    std::fs::create_dir_all(&args[1]).map_err(VCRError::IOError)?;
+
     for (name, steps) in all_steps {
         println!("Recording asset {}", name);
         let main_path = Path::new(&args[1]).join(&format!("{}.riv", name));
@@ -148,6 +145,7 @@ fn main() -> anyhow::Result<()> {
         let mut main_out = BufWriter::new(main_f);
 
         let header = encode_resource(
+            &fetcher,
             steps,
             replacer
                 .get(&name)
@@ -1,3 +1,4 @@
+use ::encoder::pipeline::Reorder;
 use blaseball_vcr::{
     feed::{CompactedFeedEvent, FeedEvent, MetaIndex},
     utils::encode_varint,
@@ -6,7 +7,7 @@ use blaseball_vcr::{
 use clap::clap_app;
 use crossbeam::channel::bounded;
 use indicatif::{BinaryBytes, MultiProgress, MultiProgressAlignment, ProgressBar, ProgressStyle};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::convert::TryInto;
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader, BufWriter, Read, Seek, Write};
@@ -15,6 +16,8 @@ use std::path::Path;
 macro_rules! encode_index {
     ($idx:expr) => {
         $idx.into_iter()
+            .collect::<BTreeMap<_, _>>()
+            .into_iter()
             .map(|(k, v)| {
                 let v_bytes = v
                     .into_iter()
@@ -72,6 +75,9 @@ fn main() {
         .value_of("EVENT_TYPES")
         .unwrap_or("")
         .split(',')
+        // no --types (or a trailing comma) leaves empty pieces, which just mean no index
+        .map(|s| s.trim())
+        .filter(|s| !s.is_empty())
         .map(|s| s.parse::<i16>().unwrap())
         .collect();
 
@@ -99,7 +105,7 @@ fn main() {
             let f = File::open(input_path).unwrap();
             let reader = BufReader::new(f);
 
-            for l in reader.lines() {
+            for (n, l) in reader.lines().enumerate() {
                 let event: FeedEvent = serde_json::from_str(&l.unwrap()).unwrap();
                 if event.season == 0 {
                     continue;
@@ -158,21 +164,24 @@ fn main() {
 
                 // println!("{:#?}",event.metadata);
 
-                snd1.send(CompactedFeedEvent {
-                    id: event.id,
-                    category: event.category,
-                    day: event.day.try_into().unwrap_or(255),
-                    created: event.created,
-                    description: event.description,
-                    player_tags: compact_player_tags,
-                    game_tags: compact_game_tags,
-                    team_tags: compact_team_tags,
-                    etype: event.etype,
-                    tournament: event.tournament,
-                    metadata: event.metadata,
-                    phase: event.phase,
-                    season: event.season,
-                })
+                snd1.send((
+                    n,
+                    CompactedFeedEvent {
+                        id: event.id,
+                        category: event.category,
+                        day: event.day.try_into().unwrap_or(255),
+                        created: event.created,
+                        description: event.description,
+                        player_tags: compact_player_tags,
+                        game_tags: compact_game_tags,
+                        team_tags: compact_team_tags,
+                        etype: event.etype,
+                        tournament: event.tournament,
+                        metadata: event.metadata,
+                        phase: event.phase,
+                        season: event.season,
+                    },
+                ))
                 .unwrap();
             }
 
@@ -196,11 +205,11 @@ fn main() {
                     zstd::block::Compressor::new()
                 };
                 // Receive until channel closes
-                for event in recvr.iter() {
+                for (n, event) in recvr.iter() {
                     let compressed_bytes = feed_compressor
                         .compress(&event.encode(), compression_level)
                         .unwrap();
-                    sendr.send((event, compressed_bytes)).unwrap();
+                    sendr.send((n, event, compressed_bytes)).unwrap();
                 }
             });
         }
@@ -246,7 +255,13 @@ fn main() {
         );
         // bars.set_draw_target(indicatif::ProgressDrawTarget::hidden());
 
-        for (i, (event, bytes)) in rcv2.iter().enumerate() {
+        // events are written in the same order as the input file no matter which worker compressed them, so builds are reproducible.
+        let mut reorder = Reorder::new();
+        let in_order = rcv2
+            .iter()
+            .flat_map(|(n, event, bytes)| reorder.push(n, (event, bytes)));
+
+        for (i, (event, bytes)) in in_order.enumerate() {
             feed_progress_bar.tick();
 
             let start_pos = out.stream_position().unwrap() as u32;
@@ -325,6 +340,8 @@ fn main() {
         let etype_idx_bytes = encode_index!(etype_idx);
 
         let phase_idx_bytes = phase_idx
+            .into_iter()
+            .collect::<BTreeMap<_, _>>()
             .into_iter()
             .flat_map(|(k, v)| {
                 let v_bytes = v
@@ -0,0 +1,202 @@
+use reqwest::blocking::{Client, Response};
+use reqwest::StatusCode;
+use serde::{de::DeserializeOwned, Serialize};
+use std::env;
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// How hard the encoders are allowed to lean on Chronicler.
+#[derive(Debug, Clone)]
+pub struct FetchConfig {
+    /// how many times a failed request is retried before giving up
+    pub retries: u32,
+    /// how long to wait before the first retry; doubles with every attempt
+    pub backoff: Duration,
+    pub max_backoff: Duration,
+    /// cap on requests started per second, across every clone of a `Fetcher`
+    pub requests_per_second: Option<f64>,
+    /// cap on requests in flight at once, across every clone of a `Fetcher`
+    pub concurrency: usize,
+}
+
+impl Default for FetchConfig {
+    fn default() -> FetchConfig {
+        FetchConfig {
+            retries: 5,
+            backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            requests_per_second: None,
+            concurrency: 8,
+        }
+    }
+}
+
+impl FetchConfig {
+    /// Reads overrides from `VCR_FETCH_RETRIES`, `VCR_FETCH_BACKOFF_MS`, `VCR_FETCH_MAX_BACKOFF_MS`, `VCR_FETCH_RPS` and `VCR_FETCH_CONCURRENCY`, falling back to the defaults.
+    pub fn from_env() -> FetchConfig {
+        fn var<T: std::str::FromStr>(name: &str) -> Option<T> {
+            env::var(name).ok().and_then(|v| v.parse().ok())
+        }
+
+        let default = FetchConfig::default();
+        FetchConfig {
+            retries: var("VCR_FETCH_RETRIES").unwrap_or(default.retries),
+            backoff: var("VCR_FETCH_BACKOFF_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.backoff),
+            max_backoff: var("VCR_FETCH_MAX_BACKOFF_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(default.max_backoff),
+            requests_per_second: var("VCR_FETCH_RPS").or(default.requests_per_second),
+            concurrency: var("VCR_FETCH_CONCURRENCY").unwrap_or(default.concurrency),
+        }
+    }
+}
+
+const NO_QUERY: &[(&str, &str)] = &[];
+
+struct Limits {
+    in_flight: Mutex<usize>,
+    slot_freed: Condvar,
+    next_start: Mutex<Instant>,
+}
+
+/// Releases a concurrency slot when dropped.
+struct Permit<'a> {
+    limits: &'a Limits,
+}
+
+impl Drop for Permit<'_> {
+    fn drop(&mut self) {
+        *self.limits.in_flight.lock().unwrap() -= 1;
+        self.limits.slot_freed.notify_one();
+    }
+}
+
+/// A blocking HTTP client that retries failed requests with exponential backoff and keeps to a rate and concurrency limit.
+/// Clones share the same limits, so one `Fetcher` can be handed out to every worker thread.
+#[derive(Clone)]
+pub struct Fetcher {
+    client: Client,
+    config: Arc<FetchConfig>,
+    limits: Arc<Limits>,
+}
+
+impl Fetcher {
+    pub fn new(config: FetchConfig) -> Fetcher {
+        Fetcher {
+            client: Client::new(),
+            config: Arc::new(config),
+            limits: Arc::new(Limits {
+                in_flight: Mutex::new(0),
+                slot_freed: Condvar::new(),
+                next_start: Mutex::new(Instant::now()),
+            }),
+        }
+    }
+
+    pub fn from_env() -> Fetcher {
+        Fetcher::new(FetchConfig::from_env())
+    }
+
+    pub fn config(&self) -> &FetchConfig {
+        &self.config
+    }
+
+    /// GETs `url` and deserializes the JSON response.
+    pub fn get_json<T: DeserializeOwned, Q: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        query: &Q,
+    ) -> anyhow::Result<T> {
+        self.request(url, query, |res| res.json())
+    }
+
+    /// GETs `url` and returns the response body.
+    pub fn get_bytes(&self, url: &str) -> anyhow::Result<Vec<u8>> {
+        self.request(url, NO_QUERY, |res| res.bytes().map(|b| b.to_vec()))
+    }
+
+    /// GETs `url` and returns the response body as text.
+    pub fn get_text(&self, url: &str) -> anyhow::Result<String> {
+        self.request(url, NO_QUERY, |res| res.text())
+    }
+
+    fn request<T, Q: Serialize + ?Sized>(
+        &self,
+        url: &str,
+        query: &Q,
+        read: impl Fn(Response) -> reqwest::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut attempt = 0;
+        loop {
+            let result = {
+                let _permit = self.acquire();
+                self.wait_for_rate_limit();
+
+                // the body is read while still holding the permit, and a body that gets cut off is retried like any other failure
+                match self.client.get(url).query(query).send() {
+                    Ok(res) if res.status().is_success() => read(res).map_err(anyhow::Error::from),
+                    Ok(res) if !is_retryable(res.status()) => {
+                        return Err(anyhow::anyhow!("GET {} failed: {}", url, res.status()))
+                    }
+                    Ok(res) => Err(anyhow::anyhow!("GET {} failed: {}", url, res.status())),
+                    Err(e) => Err(e.into()),
+                }
+            };
+
+            match result {
+                Ok(v) => return Ok(v),
+                Err(e) if attempt >= self.config.retries => return Err(e),
+                Err(_) => {
+                    thread::sleep(self.backoff(attempt));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.config
+            .backoff
+            .checked_mul(2_u32.saturating_pow(attempt))
+            .map_or(self.config.max_backoff, |d| d.min(self.config.max_backoff))
+    }
+
+    fn acquire(&self) -> Permit<'_> {
+        let mut in_flight = self.limits.in_flight.lock().unwrap();
+        while *in_flight >= self.config.concurrency.max(1) {
+            in_flight = self.limits.slot_freed.wait(in_flight).unwrap();
+        }
+
+        *in_flight += 1;
+        Permit {
+            limits: &self.limits,
+        }
+    }
+
+    fn wait_for_rate_limit(&self) {
+        let rps = match self.config.requests_per_second {
+            Some(rps) if rps > 0.0 => rps,
+            _ => return,
+        };
+
+        // reserve the next start slot, then sleep until it comes up
+        let start = {
+            let mut next_start = self.limits.next_start.lock().unwrap();
+            let start = (*next_start).max(Instant::now());
+            *next_start = start + Duration::from_secs_f64(1.0 / rps);
+            start
+        };
+
+        let now = Instant::now();
+        if start > now {
+            thread::sleep(start - now);
+        }
+    }
+}
+
+fn is_retryable(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
@@ -0,0 +1,191 @@
+use ::encoder::tapes::{counter_types, entity_types, open_counters, open_database};
+use blaseball_vcr::*;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use clap::clap_app;
+use serde_json::{json, Value as JSONValue};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// looks inside tapes, for when something decodes wrong
+
+fn timestamp(time: u32) -> DateTime<Utc> {
+    DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(time as i64, 0), Utc)
+}
+
+/// Turns a decoded patch into plain JSON Patch, with a root replacement written as `{"op": "replace", "path": ""}`.
+fn patch_ops(patch: Patch) -> JSONValue {
+    match patch {
+        Patch::Normal(ops) => json!(ops),
+        Patch::ReplaceRoot(value) => json!([{ "op": "replace", "path": "", "value": value }]),
+    }
+}
+
+fn print_json(value: &JSONValue) {
+    println!("{}", serde_json::to_string_pretty(value).unwrap());
+}
+
+/// Parses `[INDEX]...`, or returns `0..len` if none were given.
+fn indices(args: &clap::ArgMatches, len: usize) -> Vec<usize> {
+    match args.values_of("INDEX") {
+        Some(values) => values.map(|v| v.parse::<usize>().unwrap()).collect(),
+        None => (0..len).collect(),
+    }
+}
+
+pub fn main() -> VCRResult<()> {
+    let matches = clap_app!(inspect =>
+        (version: "1.0")
+        (author: "allie signet <allie@sibr.dev>")
+        (about: "blaseball.vcr tape inspector")
+        (@arg TAPES: -t --tapes [FOLDER] "set tapes folder (default: ./tapes)")
+        (@arg DICTS: -d --dicts [FOLDER] "set zstd dictionaries folder (default: ./zstd-dictionaries)")
+        (@subcommand types =>
+            (about: "list entity types and how many entities each has")
+        )
+        (@subcommand ids =>
+            (about: "list the ids in a tape, with their number of patches")
+            (@arg TYPE: <TYPE> "entity type")
+        )
+        (@subcommand entity =>
+            (about: "print an entity's header: patch table, checkpoint interval, path map and base")
+            (@arg TYPE: <TYPE> "entity type")
+            (@arg ID: <ID> "entity id")
+        )
+        (@subcommand patch =>
+            (about: "decode an entity's patches into JSON Patch ops")
+            (@arg TYPE: <TYPE> "entity type")
+            (@arg ID: <ID> "entity id")
+            (@arg INDEX: [INDEX] ... "patch indices to decode (default: all of them)")
+        )
+        (@subcommand counters =>
+            (about: "print a counter map tape's config, id table, keyframes and records, or decode some of its records")
+            (@arg TYPE: <TYPE> "counter map type, like tributes")
+            (@arg INDEX: [INDEX] ... "record indices to decode")
+        )
+    )
+    .get_matches();
+
+    let folder = Path::new(matches.value_of("TAPES").unwrap_or("./tapes"));
+    let dicts = Path::new(matches.value_of("DICTS").unwrap_or("./zstd-dictionaries"));
+
+    match matches.subcommand() {
+        ("types", Some(_)) => {
+            for e_type in entity_types(folder)? {
+                let db = open_database(folder, dicts, &e_type)?;
+                println!("{:>8} {}", db.entity_ids().count(), e_type);
+            }
+
+            for e_type in counter_types(folder)? {
+                let db = open_counters(folder, &e_type)?;
+                println!(
+                    "{:>8} {} ({} records)",
+                    db.ids().len(),
+                    e_type,
+                    db.times().len()
+                );
+            }
+        }
+        ("ids", Some(args)) => {
+            let db = open_database(folder, dicts, args.value_of("TYPE").unwrap())?;
+            let mut ids: Vec<&String> = db.entity_ids().collect();
+            ids.sort();
+
+            for id in ids {
+                let patches = db.entity_metadata(id).map_or(0, |m| m.patches.len());
+                println!("{} {:>8}", id, patches);
+            }
+        }
+        ("entity", Some(args)) => {
+            let db = open_database(folder, dicts, args.value_of("TYPE").unwrap())?;
+            let metadata = db
+                .entity_metadata(args.value_of("ID").unwrap())
+                .ok_or(VCRError::EntityNotFound)?;
+
+            let path_map: BTreeMap<&u16, &String> = metadata.path_map.iter().collect();
+            let patches: Vec<JSONValue> = metadata
+                .patches
+                .iter()
+                .enumerate()
+                .map(|(index, (time, offset, length))| {
+                    json!({
+                        "index": index,
+                        "time": time,
+                        "validFrom": timestamp(*time),
+                        "offset": offset,
+                        "length": length,
+                    })
+                })
+                .collect();
+
+            print_json(&json!({
+                "checkpointEvery": metadata.checkpoint_every,
+                "base": metadata.base,
+                "pathMap": path_map,
+                "patches": patches,
+            }));
+        }
+        ("patch", Some(args)) => {
+            let db = open_database(folder, dicts, args.value_of("TYPE").unwrap())?;
+            let id = args.value_of("ID").unwrap();
+            let metadata = db.entity_metadata(id).ok_or(VCRError::EntityNotFound)?;
+
+            for index in indices(args, metadata.patches.len()) {
+                let (time, patch) = db.get_patch(id, index)?;
+                print_json(&json!({
+                    "index": index,
+                    "time": time,
+                    "validFrom": timestamp(time),
+                    "ops": patch_ops(patch),
+                }));
+            }
+        }
+        ("counters", Some(args)) => {
+            let db = open_counters(folder, args.value_of("TYPE").unwrap())?;
+
+            if args.is_present("INDEX") {
+                for index in indices(args, 0) {
+                    let (time, _, _) = *db.times().get(index).ok_or(VCRError::InvalidPatchData)?;
+                    print_json(&json!({
+                        "index": index,
+                        "time": time,
+                        "validFrom": timestamp(time),
+                        "changes": db.record_changes(index)?,
+                    }));
+                }
+            } else {
+                let kinds = &db.config().kinds;
+                let ids: BTreeMap<&u32, JSONValue> = db
+                    .ids()
+                    .iter()
+                    .map(|(idx, (id, kind))| {
+                        (idx, json!({ "id": id, "kind": kinds[*kind as usize].name }))
+                    })
+                    .collect();
+                let records: Vec<JSONValue> = db
+                    .times()
+                    .iter()
+                    .enumerate()
+                    .map(|(index, (time, offset, length))| {
+                        json!({
+                            "index": index,
+                            "time": time,
+                            "validFrom": timestamp(*time),
+                            "offset": offset,
+                            "length": length,
+                        })
+                    })
+                    .collect();
+
+                print_json(&json!({
+                    "config": db.config(),
+                    "ids": ids,
+                    "keyframes": db.keyframes(),
+                    "records": records,
+                }));
+            }
+        }
+        _ => println!("{}", matches.usage()),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use blaseball_vcr::encoder::{write_entity_table_entry, ValueTableDelta};
+use blaseball_vcr::{GameIndexEntry, GameSummary, VCRResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// One entity that's been fully written to a tape's `.riv` file.
+#[derive(Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub id: String,
+    /// where the entity's bytes end in the `.riv` file
+    pub end_position: u64,
+    /// the entity's encoded header, as produced by `HeaderEncoder`
+    pub header: Vec<u8>,
+    /// what the entity added to its type's value table, if it has one
+    pub values: Option<ValueTableDelta>,
+    /// what the game's last version gets indexed as, for game tapes
+    #[serde(default)]
+    pub game: Option<GameIndexEntry>,
+    /// the game's summary, for game tapes
+    #[serde(default)]
+    pub summary: Option<GameSummary>,
+    /// the game's distinct update texts and when they showed up, when building a search index
+    #[serde(default)]
+    pub texts: Option<Vec<(u32, String)>>,
+}
+
+/// An append-only log of the entities a tape build has finished, kept next to the tape as `{type}.journal`.
+///
+/// Headers (and other indexes) only get written once a build is done, from the journal's entries, so a build that dies halfway can pick up where it left off: the `.riv` file is truncated to the end of the last journaled entity and everything after it is redone.
+/// The journal is deleted once the build finishes.
+pub struct BuildJournal {
+    path: PathBuf,
+    file: File,
+    entries: Vec<JournalEntry>,
+}
+
+impl BuildJournal {
+    /// Starts a new journal, throwing away any existing one.
+    pub fn create<P: AsRef<Path>>(path: P) -> VCRResult<BuildJournal> {
+        Ok(BuildJournal {
+            path: path.as_ref().to_path_buf(),
+            file: File::create(&path)?,
+            entries: Vec::new(),
+        })
+    }
+
+    /// Reads an existing journal back, or starts a new one if there isn't any. A record that was only partially written when the build died is dropped.
+    pub fn resume<P: AsRef<Path>>(path: P) -> VCRResult<BuildJournal> {
+        if !path.as_ref().exists() {
+            return BuildJournal::create(path);
+        }
+
+        let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let mut reader = Cursor::new(&bytes[..]);
+        let mut entries = Vec::new();
+        let mut valid_len = 0;
+        while (reader.position() as usize) < bytes.len() {
+            match rmp_serde::from_read::<_, JournalEntry>(&mut reader) {
+                Ok(entry) => {
+                    entries.push(entry);
+                    valid_len = reader.position();
+                }
+                Err(_) => break,
+            }
+        }
+
+        file.set_len(valid_len)?;
+        file.seek(SeekFrom::End(0))?;
+
+        Ok(BuildJournal {
+            path: path.as_ref().to_path_buf(),
+            file,
+            entries,
+        })
+    }
+
+    /// Opens a journal with `resume` or `create` depending on whether the build is being resumed.
+    pub fn open<P: AsRef<Path>>(path: P, resume: bool) -> VCRResult<BuildJournal> {
+        if resume {
+            BuildJournal::resume(path)
+        } else {
+            BuildJournal::create(path)
+        }
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Ids of every entity that's already been written.
+    pub fn completed(&self) -> HashSet<String> {
+        self.entries.iter().map(|e| e.id.clone()).collect()
+    }
+
+    /// Where the last complete entity ends; anything in the `.riv` file past this is garbage.
+    pub fn end_position(&self) -> u64 {
+        self.entries.last().map_or(0, |e| e.end_position)
+    }
+
+    /// Opens the `.riv` file this journal describes for writing, cut down to the end of the last complete entity.
+    pub fn open_tape<P: AsRef<Path>>(&self, path: P) -> VCRResult<File> {
+        let mut file = OpenOptions::new().write(true).create(true).open(path)?;
+        file.set_len(self.end_position())?;
+        file.seek(SeekFrom::End(0))?;
+        Ok(file)
+    }
+
+    /// Records an entity as done. Its bytes must already be flushed to the `.riv` file.
+    pub fn record(&mut self, entry: JournalEntry) -> VCRResult<()> {
+        rmp_serde::encode::write(&mut self.file, &entry)?;
+        self.file.flush()?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Writes the entity table (`{type}.header.riv.zstd`, uncompressed) for every journaled entity.
+    pub fn write_entity_table<W: Write>(&self, mut writer: W) -> VCRResult<()> {
+        for entry in &self.entries {
+            write_entity_table_entry(
+                &mut writer,
+                &Uuid::parse_str(&entry.id).map_err(anyhow::Error::from)?,
+                entry.end_position as u32,
+                &entry.header,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the journal once the build's outputs have all been written.
+    pub fn finish(self) -> VCRResult<()> {
+        drop(self.file);
+        fs::remove_file(self.path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,5 @@
+pub mod fetch;
+pub mod journal;
+pub mod pipeline;
+pub mod plan;
+pub mod tapes;
@@ -0,0 +1,71 @@
+use blaseball_vcr::VCRError;
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+/// Buffers out-of-order messages until the next one in sequence shows up, so that whatever a pool of workers produces gets written in the order it was queued in, and builds come out the same every time.
+pub struct Reorder<T> {
+    pending: BTreeMap<usize, T>,
+    next: usize,
+}
+
+impl<T> Reorder<T> {
+    pub fn new() -> Reorder<T> {
+        Reorder {
+            pending: BTreeMap::new(),
+            next: 0,
+        }
+    }
+
+    /// Takes message number `seq`, returning every message that's now ready, in order.
+    pub fn push(&mut self, seq: usize, item: T) -> Vec<T> {
+        self.pending.insert(seq, item);
+        let mut ready = Vec::new();
+        while let Some(item) = self.pending.remove(&self.next) {
+            ready.push(item);
+            self.next += 1;
+        }
+        ready
+    }
+}
+
+impl<T> Default for Reorder<T> {
+    fn default() -> Reorder<T> {
+        Reorder::new()
+    }
+}
+
+/// The first error any stage of a pipeline ran into. Stages stop once there is one, and the ones after them stop when their channels close, so the journal ends at the last entity that was fully written and `--resume` picks up from there.
+#[derive(Default)]
+pub struct Failure(Mutex<Option<VCRError>>);
+
+impl Failure {
+    pub fn set<E: Into<VCRError>>(&self, err: E) {
+        let mut failure = self.0.lock().unwrap();
+        if failure.is_none() {
+            *failure = Some(err.into());
+        }
+    }
+
+    pub fn is_set(&self) -> bool {
+        self.0.lock().unwrap().is_some()
+    }
+
+    pub fn take(&self) -> Option<VCRError> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn puts_messages_back_in_order() {
+        let mut reorder = Reorder::new();
+        assert!(reorder.push(2, 'c').is_empty());
+        assert!(reorder.push(1, 'b').is_empty());
+        assert_eq!(reorder.push(0, 'a'), vec!['a', 'b', 'c']);
+        assert_eq!(reorder.push(3, 'd'), vec!['d']);
+        assert!(reorder.push(5, 'f').is_empty());
+    }
+}
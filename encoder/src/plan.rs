@@ -0,0 +1,355 @@
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Everything needed to build a set of tapes, read from a TOML file (see `build.toml` in the repository root).
+#[derive(Debug, Deserialize)]
+pub struct BuildPlan {
+    /// folder the tapes get written to
+    #[serde(default = "default_output")]
+    pub output: PathBuf,
+    /// workers passed to every step that takes a thread count
+    pub threads: Option<usize>,
+    pub site_data: Option<SiteDataStep>,
+    #[serde(default)]
+    pub entities: Vec<EntityStep>,
+    pub games: Option<GamesStep>,
+    pub feed: Option<FeedStep>,
+    #[serde(default)]
+    pub counters: Vec<CountersStep>,
+    pub stream: Option<StreamStep>,
+}
+
+fn default_output() -> PathBuf {
+    PathBuf::from("./tapes")
+}
+
+/// How patches get compressed. zstd is the only codec tapes can be read back with at the moment.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Codec {
+    #[default]
+    Zstd,
+}
+
+/// Settings shared by every step that compresses patches.
+#[derive(Debug, Default, Deserialize)]
+pub struct Compression {
+    #[serde(default)]
+    pub codec: Codec,
+    pub dictionary: Option<PathBuf>,
+    #[serde(rename = "level")]
+    pub compression_level: Option<i32>,
+}
+
+/// A group of entity types encoded by one `build_entities` run.
+#[derive(Debug, Deserialize)]
+pub struct EntityStep {
+    pub types: Vec<String>,
+    /// make a checkpoint every n versions
+    pub checkpoint_every: Option<u16>,
+    #[serde(flatten)]
+    pub compression: Compression,
+    /// show extra progress bars
+    #[serde(default)]
+    pub whee: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GamesStep {
+    #[serde(flatten)]
+    pub compression: Compression,
+    /// also build a full-text index over update text
+    #[serde(default)]
+    pub search: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedStep {
+    /// feed dump in NDJSON format
+    pub input: PathBuf,
+    /// defaults to `{output}/feed`
+    pub output: Option<PathBuf>,
+    /// event types to build indexes for
+    #[serde(default)]
+    pub index_types: Vec<i16>,
+    #[serde(flatten)]
+    pub compression: Compression,
+}
+
+/// A counter map type (like tributes) encoded by `encode_counters`.
+#[derive(Debug, Deserialize)]
+pub struct CountersStep {
+    #[serde(rename = "type")]
+    pub e_type: String,
+    /// TOML file with the type's key kinds and shapes, for types without a built-in one
+    pub config: Option<PathBuf>,
+    /// store a full snapshot every n records (default 100)
+    pub keyframe_every: Option<usize>,
+}
+
+/// Precomputed `streamData` frames, built by `build_stream` from the tapes every other step wrote.
+#[derive(Debug, Deserialize)]
+pub struct StreamStep {
+    /// make a checkpoint every n frames (default 1000)
+    pub checkpoint_every: Option<u16>,
+    #[serde(flatten)]
+    pub compression: Compression,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SiteDataStep {
+    /// defaults to `{output}/site_data`
+    pub output: Option<PathBuf>,
+    /// TOML file with text replacements to apply to assets
+    pub replaces: Option<PathBuf>,
+}
+
+/// One invocation of an encoder binary.
+#[derive(Debug)]
+pub struct Step {
+    pub name: String,
+    /// name of the encoder binary to run
+    pub binary: &'static str,
+    pub args: Vec<String>,
+    /// files that, when changed, make the step's outputs stale
+    pub inputs: Vec<PathBuf>,
+    /// files the step is expected to produce
+    pub outputs: Vec<PathBuf>,
+    /// whether the binary understands `--resume`
+    pub resumable: bool,
+}
+
+impl BuildPlan {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> anyhow::Result<BuildPlan> {
+        let plan: BuildPlan = toml::from_str(&fs::read_to_string(path)?)?;
+        Ok(plan)
+    }
+
+    /// Turns the plan into the binary invocations that carry it out: site data first, then entities in the order they're listed, games, feed, counter maps, and the stream last, since it's built from everything else.
+    pub fn steps(&self) -> Vec<Step> {
+        let out = &self.output;
+        let mut steps = Vec::new();
+
+        if let Some(site_data) = &self.site_data {
+            let folder = site_data
+                .output
+                .clone()
+                .unwrap_or_else(|| out.join("site_data"));
+            let mut args = vec![path_arg(&folder)];
+            args.extend(site_data.replaces.iter().map(|p| path_arg(p)));
+
+            steps.push(Step {
+                name: "site_data".to_owned(),
+                binary: "download_site_data",
+                args,
+                inputs: site_data.replaces.iter().cloned().collect(),
+                outputs: vec![folder],
+                resumable: false,
+            });
+        }
+
+        for group in &self.entities {
+            let mut args = vec!["-o".to_owned(), path_arg(out)];
+            if let Some(threads) = self.threads {
+                args.extend(["-t".to_owned(), threads.to_string()]);
+            }
+            if let Some(checkpoint_every) = group.checkpoint_every {
+                args.extend(["-c".to_owned(), checkpoint_every.to_string()]);
+            }
+            if group.whee {
+                args.push("--whee".to_owned());
+            }
+            args.extend(group.compression.args());
+            args.extend(group.types.iter().cloned());
+
+            steps.push(Step {
+                name: format!("entities-{}", group.types.join("-")),
+                binary: "build_entities",
+                args,
+                inputs: group.compression.dictionary.iter().cloned().collect(),
+                outputs: group
+                    .types
+                    .iter()
+                    .flat_map(|etype| {
+                        [
+                            out.join(format!("{}.riv", etype)),
+                            out.join(format!("{}.header.riv.zstd", etype)),
+                            out.join(format!("{}.values.riv.zstd", etype)),
+                        ]
+                    })
+                    .collect(),
+                resumable: true,
+            });
+        }
+
+        if let Some(games) = &self.games {
+            let mut args = games.compression.args();
+            if let Some(threads) = self.threads {
+                args.extend(["-t".to_owned(), threads.to_string()]);
+            }
+            if games.search {
+                args.push("--search".to_owned());
+            }
+            args.push(path_arg(out));
+
+            let mut outputs = vec![
+                out.join("game_updates.riv"),
+                out.join("game_updates.header.riv.zstd"),
+                out.join("game_updates.values.riv.zstd"),
+                out.join("game_updates.dates.riv.zstd"),
+                out.join("game_updates.index.riv.zstd"),
+                out.join("game_updates.summaries.riv.zstd"),
+            ];
+            if games.search {
+                outputs.push(out.join("game_updates.search.riv.zstd"));
+            }
+
+            steps.push(Step {
+                name: "games".to_owned(),
+                binary: "build_games",
+                args,
+                inputs: games.compression.dictionary.iter().cloned().collect(),
+                outputs,
+                resumable: true,
+            });
+        }
+
+        if let Some(feed) = &self.feed {
+            let folder = feed.output.clone().unwrap_or_else(|| out.join("feed"));
+            let mut args = feed.compression.args();
+            if let Some(threads) = self.threads {
+                args.extend(["-t".to_owned(), threads.to_string()]);
+            }
+            if !feed.index_types.is_empty() {
+                let types: Vec<String> = feed.index_types.iter().map(|t| t.to_string()).collect();
+                args.extend(["--types".to_owned(), types.join(",")]);
+            }
+            args.extend([path_arg(&feed.input), path_arg(&folder)]);
+
+            let mut inputs = vec![feed.input.clone()];
+            inputs.extend(feed.compression.dictionary.iter().cloned());
+
+            steps.push(Step {
+                name: "feed".to_owned(),
+                binary: "encode_feed",
+                args,
+                inputs,
+                outputs: vec![folder.join("feed.riv"), folder.join("feed.fp")],
+                resumable: false,
+            });
+        }
+
+        for counters in &self.counters {
+            let e_type = &counters.e_type;
+            let mut args = Vec::new();
+            if let Some(config) = &counters.config {
+                args.extend(["-c".to_owned(), path_arg(config)]);
+            }
+            if let Some(keyframe_every) = counters.keyframe_every {
+                args.extend(["-k".to_owned(), keyframe_every.to_string()]);
+            }
+            args.extend([e_type.to_owned(), path_arg(out)]);
+
+            steps.push(Step {
+                name: format!("counters-{}", e_type),
+                binary: "encode_counters",
+                args,
+                inputs: counters.config.iter().cloned().collect(),
+                outputs: ["riv", "header.riv", "keyframes.riv", "counters.riv"]
+                    .iter()
+                    .map(|ext| out.join(format!("{}.{}", e_type, ext)))
+                    .collect(),
+                resumable: false,
+            });
+        }
+
+        if let Some(stream) = &self.stream {
+            let mut args = stream.compression.args();
+            if let Some(checkpoint_every) = stream.checkpoint_every {
+                args.extend(["-c".to_owned(), checkpoint_every.to_string()]);
+            }
+            args.push(path_arg(out));
+
+            // rebuilt whenever any of the tapes it's built from is
+            let mut inputs: Vec<PathBuf> = steps
+                .iter()
+                .flat_map(|step| step.outputs.iter().cloned())
+                .collect();
+            inputs.extend(stream.compression.dictionary.iter().cloned());
+
+            steps.push(Step {
+                name: "stream".to_owned(),
+                binary: "build_stream",
+                args,
+                inputs,
+                outputs: vec![
+                    out.join("stream.riv"),
+                    out.join("stream.header.riv.zstd"),
+                    out.join("stream.values.riv.zstd"),
+                ],
+                resumable: false,
+            });
+        }
+
+        steps
+    }
+}
+
+impl Compression {
+    fn args(&self) -> Vec<String> {
+        // zstd is all there is for now, so the codec doesn't need passing along
+        let mut args = Vec::new();
+        if let Some(dictionary) = &self.dictionary {
+            args.extend(["-d".to_owned(), path_arg(dictionary)]);
+        }
+        if let Some(level) = self.compression_level {
+            args.extend(["-l".to_owned(), level.to_string()]);
+        }
+        args
+    }
+}
+
+fn path_arg(path: &Path) -> String {
+    path.to_string_lossy().into_owned()
+}
+
+impl Step {
+    fn stamp_path(&self, stamp_dir: &Path) -> PathBuf {
+        stamp_dir.join(format!("{}.stamp", self.name))
+    }
+
+    fn stamp(&self) -> String {
+        format!("{} {}", self.binary, self.args.join(" "))
+    }
+
+    /// A step is up to date when it last finished with the same arguments, all of its outputs are still there, and none of its inputs have changed since.
+    pub fn is_up_to_date(&self, stamp_dir: &Path) -> bool {
+        let stamp_path = self.stamp_path(stamp_dir);
+        let stamped_at = match fs::read_to_string(&stamp_path) {
+            Ok(stamp) if stamp == self.stamp() => match modified(&stamp_path) {
+                Some(t) => t,
+                None => return false,
+            },
+            _ => return false,
+        };
+
+        self.outputs.iter().all(|p| p.exists())
+            && self
+                .inputs
+                .iter()
+                .all(|p| modified(p).map_or(false, |t| t <= stamped_at))
+    }
+
+    /// Records that the step finished successfully.
+    pub fn mark_done(&self, stamp_dir: &Path) -> anyhow::Result<()> {
+        fs::create_dir_all(stamp_dir)?;
+        fs::write(self.stamp_path(stamp_dir), self.stamp())?;
+        Ok(())
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,265 @@
+use ::encoder::tapes::{entity_types, open_database};
+use blaseball_vcr::*;
+use clap::clap_app;
+use indicatif::BinaryBytes;
+use integer_encoding::VarInt;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+
+// size and compression report for entity tapes, to help pick checkpoint intervals and dictionaries
+
+const OP_NAMES: [&str; 10] = [
+    "add",
+    "remove",
+    "replace",
+    "move",
+    "copy",
+    "test",
+    "replace root",
+    "array insert",
+    "array remove",
+    "array move",
+];
+
+#[derive(Default, Clone)]
+struct Stats {
+    entities: usize,
+    versions: usize,
+    header_bytes: usize,
+    compressed: u64,
+    decompressed: u64,
+    final_compressed: u64,
+    final_decompressed: u64,
+    ops: usize,
+    value_refs: usize,
+    op_codes: BTreeMap<u8, usize>,
+    paths: usize,
+    path_bytes: usize,
+}
+
+impl Stats {
+    fn add(&mut self, other: &Stats) {
+        self.entities += other.entities;
+        self.versions += other.versions;
+        self.header_bytes += other.header_bytes;
+        self.compressed += other.compressed;
+        self.decompressed += other.decompressed;
+        self.final_compressed += other.final_compressed;
+        self.final_decompressed += other.final_decompressed;
+        self.ops += other.ops;
+        self.value_refs += other.value_refs;
+        for (op, count) in &other.op_codes {
+            *self.op_codes.entry(*op).or_default() += count;
+        }
+        self.paths += other.paths;
+        self.path_bytes += other.path_bytes;
+    }
+
+    fn ops_per_patch(&self) -> f64 {
+        if self.versions == 0 {
+            0.0
+        } else {
+            self.ops as f64 / self.versions as f64
+        }
+    }
+
+    fn ratio(&self) -> f64 {
+        if self.compressed == 0 {
+            0.0
+        } else {
+            self.decompressed as f64 / self.compressed as f64
+        }
+    }
+}
+
+/// Re-encodes an entity's header to find out how many bytes it takes up in the header file (not counting its length, end position and id).
+fn header_len(metadata: &EntityData) -> VCRResult<usize> {
+    let start_pos = metadata.patches.first().map_or(0, |p| p.1);
+    let mut header = HeaderEncoder::new(
+        metadata.base.clone(),
+        metadata.checkpoint_every,
+        metadata
+            .path_map
+            .iter()
+            .map(|(k, v)| (*k, v.clone()))
+            .collect(),
+        start_pos,
+        Vec::new(),
+    )?;
+
+    let mut last_position = start_pos;
+    for (time, position, _) in &metadata.patches {
+        header.write_patch(*time, position - last_position)?;
+        last_position = *position;
+    }
+
+    Ok(header.release().len())
+}
+
+fn entity_stats(db: &Database, id: &str) -> VCRResult<Stats> {
+    let metadata = db.entity_metadata(id).ok_or(VCRError::EntityNotFound)?;
+    let mut stats = Stats {
+        entities: 1,
+        versions: metadata.patches.len().saturating_sub(1),
+        header_bytes: header_len(metadata)?,
+        paths: metadata.path_map.len(),
+        path_bytes: metadata
+            .path_map
+            .iter()
+            .map(|(id, path)| 1 + path.len() + id.required_space())
+            .sum(),
+        ..Stats::default()
+    };
+
+    for (index, (_, _, compressed_len)) in metadata.patches.iter().enumerate() {
+        let bytes = db.raw_patch(id, index)?;
+
+        if index + 1 == metadata.patches.len() {
+            stats.final_compressed += *compressed_len as u64;
+            stats.final_decompressed += bytes.len() as u64;
+            continue;
+        }
+
+        stats.compressed += *compressed_len as u64;
+        stats.decompressed += bytes.len() as u64;
+
+        for raw_op_code in scan_op_codes(&bytes)? {
+            stats.ops += 1;
+            if raw_op_code & VALUE_REF_FLAG != 0 {
+                stats.value_refs += 1;
+            }
+            *stats
+                .op_codes
+                .entry(raw_op_code & !VALUE_REF_FLAG)
+                .or_default() += 1;
+        }
+    }
+
+    Ok(stats)
+}
+
+fn print_stats(stats: &Stats) {
+    println!("| {} entities, {} versions", stats.entities, stats.versions);
+    println!(
+        "| headers: {} ({} of them path maps, {} paths)",
+        BinaryBytes(stats.header_bytes as u64),
+        BinaryBytes(stats.path_bytes as u64),
+        stats.paths
+    );
+    println!(
+        "| patches: {} compressed, {} decompressed ({:.2}x)",
+        BinaryBytes(stats.compressed),
+        BinaryBytes(stats.decompressed),
+        stats.ratio()
+    );
+    println!(
+        "| final versions: {} compressed, {} decompressed",
+        BinaryBytes(stats.final_compressed),
+        BinaryBytes(stats.final_decompressed)
+    );
+    println!(
+        "| {:.2} ops per patch, {} values by reference",
+        stats.ops_per_patch(),
+        stats.value_refs
+    );
+
+    let histogram: Vec<String> = stats
+        .op_codes
+        .iter()
+        .map(|(op, count)| {
+            let name = OP_NAMES.get(*op as usize).copied().unwrap_or("unknown");
+            format!("{} {}", name, count)
+        })
+        .collect();
+    println!("| ops: {}", histogram.join(", "));
+}
+
+fn decompressed_len(path: &Path) -> VCRResult<u64> {
+    let mut decoder = zstd::stream::Decoder::new(File::open(path)?)?;
+    let mut bytes = Vec::new();
+    decoder.read_to_end(&mut bytes)?;
+    Ok(bytes.len() as u64)
+}
+
+fn main() -> VCRResult<()> {
+    let matches = clap_app!(tape_stats =>
+        (version: "1.0")
+        (author: "allie signet <allie@sibr.dev>")
+        (about: "blaseball.vcr entity tape size report")
+        (@arg DICTS: -d --dicts [FOLDER] "set zstd dictionaries folder (default: ./zstd-dictionaries)")
+        (@arg TOP: -n --top [N] "how many of the largest entities to show per type (default: 10)")
+        (@arg TAPES: <FOLDER> "tapes folder")
+        (@arg TYPES: [TYPE] ... "entity types to report on (default: every tape in the folder)")
+    )
+    .get_matches();
+
+    let folder = Path::new(matches.value_of("TAPES").unwrap());
+    let dicts = Path::new(matches.value_of("DICTS").unwrap_or("./zstd-dictionaries"));
+    let top = matches
+        .value_of("TOP")
+        .map(|v| v.parse::<usize>().unwrap())
+        .unwrap_or(10);
+
+    let entity_types: Vec<String> = match matches.values_of("TYPES") {
+        Some(types) => types.map(|t| t.to_owned()).collect(),
+        None => entity_types(folder)?,
+    };
+
+    let mut totals = Stats::default();
+
+    for e_type in entity_types {
+        let db = open_database(folder, dicts, &e_type)?;
+
+        let mut ids: Vec<&String> = db.entity_ids().collect();
+        ids.sort();
+
+        let mut type_stats = Stats::default();
+        let mut entities: Vec<(&String, Stats)> = Vec::with_capacity(ids.len());
+        for id in ids {
+            let stats = entity_stats(&db, id)?;
+            type_stats.add(&stats);
+            entities.push((id, stats));
+        }
+
+        let header_path = folder.join(format!("{}.header.riv.zstd", e_type));
+        println!("== {}", e_type);
+        println!(
+            "| header file: {} compressed, {} decompressed",
+            BinaryBytes(fs::metadata(&header_path)?.len()),
+            BinaryBytes(decompressed_len(&header_path)?)
+        );
+        if !db.value_table().is_empty() {
+            println!("| value table: {} values", db.value_table().len());
+        }
+        print_stats(&type_stats);
+
+        entities.sort_by(|a, b| b.1.compressed.cmp(&a.1.compressed).then(a.0.cmp(b.0)));
+        println!("| largest entities:");
+        println!(
+            "|   {:<36} {:>8} {:>10} {:>12} {:>12} {:>9} {:>6}",
+            "id", "versions", "header", "compressed", "decompressed", "ops/patch", "paths"
+        );
+        for (id, stats) in entities.iter().take(top) {
+            println!(
+                "|   {:<36} {:>8} {:>10} {:>12} {:>12} {:>9.2} {:>6}",
+                id,
+                stats.versions,
+                BinaryBytes(stats.header_bytes as u64).to_string(),
+                BinaryBytes(stats.compressed).to_string(),
+                BinaryBytes(stats.decompressed).to_string(),
+                stats.ops_per_patch(),
+                stats.paths
+            );
+        }
+        println!();
+
+        totals.add(&type_stats);
+    }
+
+    println!("== total");
+    print_stats(&totals);
+
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use blaseball_vcr::{
+    CounterMapConfig, CounterMapDatabase, Database, MultiDatabase, VCRError, VCRResult,
+};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Opens the `{type}` tape in a tapes folder, along with its value table and `{type}.dict` from `dicts` if they exist.
+pub fn open_database(folder: &Path, dicts: &Path, e_type: &str) -> VCRResult<Database> {
+    let dict_path = dicts.join(format!("{}.dict", e_type));
+    let mut db = Database::from_files(
+        folder.join(format!("{}.header.riv.zstd", e_type)),
+        folder.join(format!("{}.riv", e_type)),
+        Some(dict_path).filter(|p| p.exists()),
+        1,
+    )?;
+
+    let values_path = folder.join(format!("{}.values.riv.zstd", e_type));
+    if values_path.exists() {
+        db.set_value_table(File::open(values_path)?)?;
+    }
+
+    Ok(db)
+}
+
+/// Opens the `{type}` counter map tape in a tapes folder, with the config it was encoded with (or the built-in one for the type) and its keyframes.
+pub fn open_counters(folder: &Path, e_type: &str) -> VCRResult<CounterMapDatabase> {
+    let config_path = folder.join(format!("{}.counters.riv", e_type));
+    let config = if config_path.exists() {
+        serde_json::from_reader(File::open(config_path)?)?
+    } else {
+        CounterMapConfig::builtin(e_type).ok_or(VCRError::EntityTypeNotFound)?
+    };
+
+    let mut db = CounterMapDatabase::from_files(
+        folder.join(format!("{}.header.riv", e_type)),
+        folder.join(format!("{}.riv", e_type)),
+        config,
+    )?;
+
+    let keyframes_path = folder.join(format!("{}.keyframes.riv", e_type));
+    if keyframes_path.exists() {
+        db.set_keyframes(File::open(keyframes_path)?)?;
+    }
+
+    Ok(db)
+}
+
+/// Opens every tape in a tapes folder together, the way the player does, with any `{type}.dict` in `dicts`.
+pub fn open_multi(folder: &Path, dicts: &Path, cache_size: usize) -> VCRResult<MultiDatabase> {
+    let mut dict_paths: HashMap<String, PathBuf> = HashMap::new();
+    if dicts.exists() {
+        for entry in fs::read_dir(dicts)? {
+            let path = entry?.path();
+            if let Some(e_type) = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .and_then(|name| name.strip_suffix(".dict"))
+            {
+                dict_paths.insert(e_type.to_owned(), path.clone());
+            }
+        }
+    }
+
+    MultiDatabase::from_folder(folder.to_path_buf(), dict_paths, cache_size)
+}
+
+/// Every entity type with a tape in the folder, sorted by name. Counter maps (like tributes) aren't included, since they're stored differently.
+pub fn entity_types(folder: &Path) -> VCRResult<Vec<String>> {
+    let mut types: Vec<String> = fs::read_dir(folder)?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.strip_suffix(".header.riv.zstd").map(|t| t.to_owned())
+        })
+        .collect();
+    types.sort();
+    Ok(types)
+}
+
+/// Every counter map type with a tape in the folder, sorted by name.
+pub fn counter_types(folder: &Path) -> VCRResult<Vec<String>> {
+    let mut types: Vec<String> = fs::read_dir(folder)?
+        .filter_map(|entry| {
+            let name = entry.ok()?.file_name().into_string().ok()?;
+            name.strip_suffix(".header.riv").map(|t| t.to_owned())
+        })
+        .collect();
+    types.sort();
+    Ok(types)
+}
@@ -1,3 +1,4 @@
+use ::encoder::fetch::Fetcher;
 use blaseball_vcr::encoder::*;
 use blaseball_vcr::*;
 use indicatif::{ProgressBar, ProgressStyle};
@@ -23,8 +24,8 @@ struct ChroniclerParameters {
     count: u32,
 }
 
-async fn paged_get(
-    client: &reqwest::Client,
+fn paged_get(
+    fetcher: &Fetcher,
     url: &str,
     mut parameters: ChroniclerParameters,
 ) -> anyhow::Result<Vec<ChroniclerEntity<JSONValue>>> {
@@ -40,13 +41,8 @@ async fn paged_get(
     );
     loop {
         spinny.set_message(format!("downloading entities - page {}", page));
-        let mut chron_response: ChroniclerResponse<ChroniclerEntity<JSONValue>> = client
-            .get(url)
-            .query(&parameters)
-            .send()
-            .await?
-            .json()
-            .await?;
+        let mut chron_response: ChroniclerResponse<ChroniclerEntity<JSONValue>> =
+            fetcher.get_json(url, &parameters)?;
         results.append(&mut chron_response.items);
 
         if let Some(next_page) = chron_response.next_page {
@@ -61,16 +57,15 @@ async fn paged_get(
     Ok(results)
 }
 
-#[tokio::main]
-pub async fn main() -> anyhow::Result<()> {
-    let client = reqwest::Client::new();
+pub fn main() -> anyhow::Result<()> {
+    let fetcher = Fetcher::from_env();
     let mut entity_types: Vec<String> = env::args().skip(1).collect();
     let checkpoint_every = entity_types.remove(0).parse::<u16>().unwrap_or(u16::MAX);
 
     for etype in entity_types {
         println!("-> Fetching list of entities of type {}", etype);
         let entity_ids: Vec<String> = paged_get(
-            &client,
+            &fetcher,
             "https://api.sibr.dev/chronicler/v2/entities",
             ChroniclerParameters {
                 next_page: None,
@@ -79,8 +74,7 @@ pub async fn main() -> anyhow::Result<()> {
                 order: None,
                 count: 1000,
             },
-        )
-        .await?
+        )?
         .into_iter()
         .map(|e| e.entity_id)
         .collect();
@@ -104,7 +98,7 @@ pub async fn main() -> anyhow::Result<()> {
             entity_id_bar.set_message(format!("encoding {}", id));
 
             let mut entity_versions: Vec<(u32, JSONValue)> = paged_get(
-                &client,
+                &fetcher,
                 "https://api.sibr.dev/chronicler/v2/versions",
                 ChroniclerParameters {
                     next_page: None,
@@ -113,8 +107,7 @@ pub async fn main() -> anyhow::Result<()> {
                     order: Some("asc".to_owned()),
                     count: 1000,
                 },
-            )
-            .await?
+            )?
             .into_iter()
             .map(|e| (e.valid_from.timestamp() as u32, e.data))
             .collect();
@@ -0,0 +1,183 @@
+use encoder::fetch::{FetchConfig, Fetcher};
+use serde_json::{json, Value as JSONValue};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A tiny HTTP server that answers every request with whatever `respond` returns for the request's number.
+struct FakeServer {
+    url: String,
+    requests: Arc<AtomicUsize>,
+    max_in_flight: Arc<AtomicUsize>,
+}
+
+impl FakeServer {
+    fn start<F>(delay: Duration, respond: F) -> FakeServer
+    where
+        F: Fn(usize) -> (u16, String) + Send + Sync + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let respond = Arc::new(respond);
+
+        let (requests_c, max_c) = (requests.clone(), max_in_flight.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = stream.unwrap();
+                let (requests, in_flight, max_in_flight, respond) = (
+                    requests_c.clone(),
+                    in_flight.clone(),
+                    max_c.clone(),
+                    respond.clone(),
+                );
+
+                thread::spawn(move || {
+                    let n = requests.fetch_add(1, Ordering::SeqCst);
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+
+                    thread::sleep(delay);
+                    let (status, body) = respond(n);
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                    answer(stream, status, &body);
+                });
+            }
+        });
+
+        FakeServer {
+            url,
+            requests,
+            max_in_flight,
+        }
+    }
+
+    fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+fn answer(mut stream: TcpStream, status: u16, body: &str) {
+    let mut request = Vec::new();
+    let mut buf = [0; 1024];
+    while !request.ends_with(b"\r\n\r\n") {
+        let n = stream.read(&mut buf).unwrap();
+        if n == 0 {
+            return;
+        }
+        request.extend_from_slice(&buf[..n]);
+    }
+
+    let response = format!(
+        "HTTP/1.1 {} Whatever\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).unwrap();
+}
+
+fn config() -> FetchConfig {
+    FetchConfig {
+        retries: 3,
+        backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(50),
+        requests_per_second: None,
+        concurrency: 8,
+    }
+}
+
+#[test]
+fn retries_server_errors() {
+    let server = FakeServer::start(Duration::ZERO, |n| match n {
+        0 => (502, "bad gateway".to_owned()),
+        1 => (429, "slow down".to_owned()),
+        _ => (200, json!({ "ok": true }).to_string()),
+    });
+
+    let fetcher = Fetcher::new(config());
+    let res: JSONValue = fetcher.get_json(&server.url, &[("page", "1")]).unwrap();
+
+    assert_eq!(res, json!({ "ok": true }));
+    assert_eq!(server.requests(), 3);
+}
+
+#[test]
+fn gives_up_after_retries() {
+    let server = FakeServer::start(Duration::ZERO, |_| (503, "unavailable".to_owned()));
+
+    let fetcher = Fetcher::new(config());
+    assert!(fetcher.get_bytes(&server.url).is_err());
+    assert_eq!(server.requests(), 4);
+}
+
+#[test]
+fn does_not_retry_client_errors() {
+    let server = FakeServer::start(Duration::ZERO, |_| (404, "not found".to_owned()));
+
+    let fetcher = Fetcher::new(config());
+    assert!(fetcher.get_bytes(&server.url).is_err());
+    assert_eq!(server.requests(), 1);
+}
+
+#[test]
+fn retries_bad_bodies() {
+    let server = FakeServer::start(Duration::ZERO, |n| match n {
+        0 => (200, "{\"truncat".to_owned()),
+        _ => (200, json!([1, 2, 3]).to_string()),
+    });
+
+    let fetcher = Fetcher::new(config());
+    let res: Vec<u32> = fetcher.get_json(&server.url, &[("page", "1")]).unwrap();
+
+    assert_eq!(res, vec![1, 2, 3]);
+    assert_eq!(server.requests(), 2);
+}
+
+#[test]
+fn limits_concurrency() {
+    let server = FakeServer::start(Duration::from_millis(50), |_| (200, "{}".to_owned()));
+
+    let fetcher = Fetcher::new(FetchConfig {
+        concurrency: 2,
+        ..config()
+    });
+
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let (fetcher, url) = (fetcher.clone(), server.url.clone());
+            thread::spawn(move || fetcher.get_text(&url).unwrap())
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap();
+    }
+
+    assert_eq!(server.requests(), 8);
+    assert!(server.max_in_flight.load(Ordering::SeqCst) <= 2);
+}
+
+#[test]
+fn limits_request_rate() {
+    let server = FakeServer::start(Duration::ZERO, |_| (200, "{}".to_owned()));
+
+    let fetcher = Fetcher::new(FetchConfig {
+        requests_per_second: Some(20.0),
+        ..config()
+    });
+
+    let start = Instant::now();
+    for _ in 0..6 {
+        fetcher.get_text(&server.url).unwrap();
+    }
+
+    // the first request goes out right away, the other five wait 50ms each
+    assert!(start.elapsed() >= Duration::from_millis(250));
+    assert_eq!(server.requests(), 6);
+}
@@ -0,0 +1,62 @@
+use super::parse_time;
+use crate::types::StreamEventsReq;
+use blaseball_vcr::{MultiDatabase, StreamBuilder, VCRResult};
+use rocket::futures::{future, Stream};
+use rocket::response::stream::{Event, EventStream};
+use rocket::tokio::task;
+use rocket::tokio::time::{sleep_until, Instant};
+use rocket::{get, State};
+use std::time::Duration;
+
+/// How slow and how fast a replay can run. Much slower than this, and the time until the last frame gets too long for a `Duration`.
+const MIN_SPEED: f64 = 0.001;
+const MAX_SPEED: f64 = 100_000.0;
+
+/// Replays `streamData` the way the site sent it: as server-sent events, one per frame, each pushed when the replay clock gets to it. The clock starts at `start` (the first sim version, by default) and runs `speed` times as fast as real time.
+#[get("/events/streamData?<req..>")]
+pub fn stream_data<'a>(
+    req: StreamEventsReq,
+    db: &'a State<MultiDatabase>,
+) -> VCRResult<EventStream<impl Stream<Item = Event> + 'a>> {
+    let db: &MultiDatabase = db;
+    let first_sim = db
+        .version_span("sim", "00000000-0000-0000-0000-000000000000", 0)?
+        .1
+        .unwrap_or(0);
+    let start = parse_time(req.start.as_ref(), first_sim)?;
+    let speed = req
+        .speed
+        .filter(|speed| *speed > 0.0)
+        .map_or(1.0, |speed| speed.clamp(MIN_SPEED, MAX_SPEED));
+
+    Ok(EventStream! {
+        let started = Instant::now();
+        let mut builder = StreamBuilder::new(db);
+        let mut at = start;
+
+        loop {
+            // building a frame can mean decoding a lot of entities, which shouldn't hold up the other tasks on this worker
+            let frame = match task::block_in_place(|| builder.frame(at)) {
+                Ok(frame) => frame,
+                Err(e) => {
+                    yield Event::data(e.to_string()).event("error");
+                    break;
+                }
+            };
+            yield Event::json(&frame);
+
+            let next = match builder.frame_span().1 {
+                Some(next) => next,
+                None => break,
+            };
+            sleep_until(started + Duration::from_secs_f64((next - start) as f64 / speed)).await;
+
+            // building frames takes a while, so the clock can be past the next change by now
+            let clock = start.saturating_add((started.elapsed().as_secs_f64() * speed) as u32);
+            at = clock.max(next);
+        }
+
+        // nothing left to replay, or a frame couldn't be built. clients reconnect to closed streams, which would start the replay over, so this one stays open
+        future::pending::<()>().await;
+    })
+}
@@ -0,0 +1,94 @@
+use super::{parse_id, parse_time, v1::parse_uuids, JSONResponse};
+use crate::types::{GameSearchReq, GameSummaryReq, SummaryFormat};
+use blaseball_vcr::{
+    GameFilter, GameSummary, GameSummaryFilter, MultiDatabase, Play, SearchFilter, SearchHit,
+    VCRResult,
+};
+use rocket::{get, http::ContentType, serde::json::Json as RocketJson, State};
+use std::borrow::Cow;
+use uuid::Uuid;
+
+const CSV_HEADER: &str = "gameId,season,day,homeTeam,awayTeam,homeTeamName,awayTeamName,homeScore,awayScore,winner,loser,innings,shame,outcomes";
+
+/// Quotes a CSV field if it needs it.
+fn csv_field(field: &str) -> Cow<str> {
+    if field.contains(|c: char| matches!(c, ',' | '"' | '\n' | '\r')) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+fn csv_id(id: &Option<Uuid>) -> String {
+    id.map(|id| id.to_string()).unwrap_or_default()
+}
+
+/// One line per game, with outcomes joined by semicolons.
+fn csv(summaries: &[GameSummary]) -> String {
+    let mut out = String::from(CSV_HEADER);
+    out.push('\n');
+
+    for s in summaries {
+        let row = [
+            csv_field(&s.game_id).into_owned(),
+            s.season.to_string(),
+            s.day.to_string(),
+            csv_id(&s.home_team),
+            csv_id(&s.away_team),
+            csv_field(&s.home_team_name).into_owned(),
+            csv_field(&s.away_team_name).into_owned(),
+            s.home_score.to_string(),
+            s.away_score.to_string(),
+            csv_id(&s.winner),
+            csv_id(&s.loser),
+            s.innings.to_string(),
+            s.shame.to_string(),
+            csv_field(&s.outcomes.join("; ")).into_owned(),
+        ];
+        out.push_str(&row.join(","));
+        out.push('\n');
+    }
+
+    out
+}
+
+/// Season results: score, winner, loser, innings, shame and outcomes for every game, as JSON or CSV.
+#[get("/games/summary?<req..>")]
+pub fn summary(req: GameSummaryReq, db: &State<MultiDatabase>) -> VCRResult<(ContentType, String)> {
+    let summaries = db.game_summaries(&GameSummaryFilter {
+        season: req.season,
+        day: req.day,
+        team: req.team.as_deref().map(parse_id).transpose()?,
+    })?;
+
+    Ok(match req.format.unwrap_or(SummaryFormat::Json) {
+        SummaryFormat::Json => (ContentType::JSON, serde_json::to_string(&summaries)?),
+        SummaryFormat::Csv => (ContentType::CSV, csv(&summaries)),
+    })
+}
+
+/// One record per pitch or play in a game, in order.
+#[get("/games/<id>/plays")]
+pub fn plays(id: &str, db: &State<MultiDatabase>) -> JSONResponse<Vec<Play>> {
+    Ok(RocketJson(db.play_by_play(id)?))
+}
+
+/// Game updates whose text matches `q`, oldest first. Quoted parts of the query are phrases, and words ending in `*` match as prefixes.
+#[get("/games/search?<req..>")]
+pub fn search(req: GameSearchReq, db: &State<MultiDatabase>) -> JSONResponse<Vec<SearchHit>> {
+    let filter = SearchFilter {
+        season: req.season,
+        day: req.day,
+        after: Some(parse_time(req.after.as_ref(), 0)?),
+        before: Some(parse_time(req.before.as_ref(), u32::MAX)?),
+        game: GameFilter {
+            teams: req.team.as_deref().map(parse_uuids),
+            pitchers: req.pitcher.as_deref().map(parse_uuids),
+            ..Default::default()
+        },
+    };
+
+    let mut hits = db.search_game_updates(&req.query, &filter)?;
+    hits.truncate(req.count.unwrap_or(100));
+    Ok(RocketJson(hits))
+}
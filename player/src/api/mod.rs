@@ -1,12 +1,36 @@
+pub mod events;
 pub mod feed;
+pub mod games;
+pub mod stats;
+pub mod tributes;
 pub mod v1;
 pub mod v2;
 
-use blaseball_vcr::{ChroniclerEntity, ChroniclerResponse, ChroniclerV1Response, VCRResult};
+use blaseball_vcr::{
+    ChroniclerEntity, ChroniclerResponse, ChroniclerV1Response, VCRError, VCRResult,
+};
+use chrono::DateTime;
 use rocket::serde::json::Json as RocketJson;
 use serde_json::value::RawValue;
+use std::io;
+use uuid::Uuid;
 
 pub type JSONResponse<T> = VCRResult<RocketJson<T>>;
 pub type ChronV1Res<T> = JSONResponse<ChroniclerV1Response<T>>;
 pub type ChronV2Res<T> = JSONResponse<ChroniclerResponse<T>>;
 pub type RawChronEntity = ChroniclerEntity<Box<RawValue>>;
+
+/// Parses an entity ID from a path or query, treating a malformed one as not found.
+pub(crate) fn parse_id(id: &str) -> VCRResult<Uuid> {
+    Uuid::parse_str(id).map_err(|_| VCRError::EntityNotFound)
+}
+
+/// Parses an RFC 3339 timestamp into a UNIX one, or returns `default` if there's none.
+pub(crate) fn parse_time(time: Option<&String>, default: u32) -> VCRResult<u32> {
+    match time {
+        Some(time) => DateTime::parse_from_rfc3339(time)
+            .map(|t| t.timestamp() as u32)
+            .map_err(|e| VCRError::IOError(io::Error::new(io::ErrorKind::InvalidInput, e))),
+        None => Ok(default),
+    }
+}
@@ -0,0 +1,37 @@
+use super::{parse_id, JSONResponse};
+use crate::types::StatsReq;
+use blaseball_vcr::stats::{StatGrouping, StatRow, StatsCache};
+use blaseball_vcr::MultiDatabase;
+use rocket::{get, serde::json::Json as RocketJson, State};
+
+/// A player's batting and pitching stats, per season unless `group` says per game or day.
+#[get("/stats/player/<id>?<req..>")]
+pub fn player(
+    id: &str,
+    req: StatsReq,
+    db: &State<MultiDatabase>,
+    cache: &State<StatsCache>,
+) -> JSONResponse<Vec<StatRow>> {
+    Ok(RocketJson(cache.player_stats(
+        db,
+        &parse_id(id)?,
+        req.season,
+        req.group.unwrap_or(StatGrouping::Season),
+    )?))
+}
+
+/// Everything a team's players did for it, grouped the same way.
+#[get("/stats/team/<id>?<req..>")]
+pub fn team(
+    id: &str,
+    req: StatsReq,
+    db: &State<MultiDatabase>,
+    cache: &State<StatsCache>,
+) -> JSONResponse<Vec<StatRow>> {
+    Ok(RocketJson(cache.team_stats(
+        db,
+        &parse_id(id)?,
+        req.season,
+        req.group.unwrap_or(StatGrouping::Season),
+    )?))
+}
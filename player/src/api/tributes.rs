@@ -0,0 +1,31 @@
+use super::{parse_id, parse_time, JSONResponse};
+use crate::types::{TributesHistoryReq, TributesRankReq};
+use blaseball_vcr::{MultiDatabase, PeanutCount, TributeRank};
+use rocket::{get, serde::json::Json as RocketJson, State};
+
+#[get("/tributes/history?<req..>")]
+pub fn history(
+    req: TributesHistoryReq,
+    db: &State<MultiDatabase>,
+) -> JSONResponse<Vec<PeanutCount>> {
+    Ok(RocketJson(
+        db.counter_map("tributes")?
+            .history(
+                &parse_id(&req.id)?,
+                parse_time(req.after.as_ref(), 0)?,
+                parse_time(req.before.as_ref(), u32::MAX)?,
+            )?
+            .into_iter()
+            .map(PeanutCount::from)
+            .collect(),
+    ))
+}
+
+#[get("/tributes/rank?<req..>")]
+pub fn rank(req: TributesRankReq, db: &State<MultiDatabase>) -> JSONResponse<Option<TributeRank>> {
+    Ok(RocketJson(
+        db.counter_map("tributes")?
+            .rank(&parse_id(&req.id)?, parse_time(req.at.as_ref(), u32::MAX)?)?
+            .map(TributeRank::from),
+    ))
+}
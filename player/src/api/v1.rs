@@ -8,7 +8,8 @@ use chrono::{DateTime, TimeZone, Utc};
 use lru::LruCache;
 use rand::Rng;
 use rocket::{get, http::ContentType, serde::json::Json as RocketJson, State};
-use serde_json::{json, value::RawValue, Value as JSONValue};
+use serde_json::{json, value::RawValue};
+use uuid::Uuid;
 
 use crate::types::{UserAgent, V1GameUpdatesReq, V1GamesReq};
 
@@ -43,6 +44,13 @@ pub fn get_asset(
     ))
 }
 
+/// Parses a comma separated list of ids. Anything that isn't a UUID can't match a game, so it's left out.
+pub(crate) fn parse_uuids(ids: &str) -> Vec<Uuid> {
+    ids.split(',')
+        .filter_map(|id| Uuid::parse_str(id).ok())
+        .collect()
+}
+
 // this is not a place of honor
 #[get("/games?<req..>")]
 pub fn games(
@@ -75,92 +83,58 @@ pub fn games(
         let after = req.after.as_ref().map_or(Utc.timestamp(0, 0), |d| {
             DateTime::parse_from_rfc3339(d).unwrap().with_timezone(&Utc)
         });
-        let weathers = req.weather.as_ref().map(|w| {
-            w.split(',')
-                .map(|v| json!(v.parse::<i64>().unwrap()))
-                .collect::<Vec<JSONValue>>()
-        });
-        let teams = req
-            .team
-            .as_ref()
-            .map(|v| v.split(',').map(|t| json!(t)).collect::<Vec<JSONValue>>());
-        let pitchers = req
-            .pitcher
-            .as_ref()
-            .map(|v| v.split(',').map(|t| json!(t)).collect::<Vec<JSONValue>>());
-        let mut res = ChroniclerV1Response {
-            next_page: None,
-            data: db
-                .game_index
-                .iter()
-                .filter(|(date, _)| {
-                    (req.tournament.is_none()
-                        || req.tournament.as_ref() == date.tournament.as_ref())
-                        && req.day.as_ref().map_or(true, |d| d == &date.day)
-                        && req.season.as_ref().map_or(true, |s| s == &date.season)
-                })
-                .flat_map(|(_, v)| v)
-                .filter_map(|(id, start, end)| {
-                    if start.is_some() && (start.unwrap() < after || start.unwrap() > before) {
-                        return None;
-                    }
-
-                    match db.get_entity("game_updates", id, u32::MAX) {
-                        Ok(g) => {
-                            let game = g.data;
-                            if (req.started.is_none()
-                                || req.started.map(|v| json!(v)).as_ref() == game.get("gameStart"))
-                                && (req.finished.is_none()
-                                    || req.finished.map(|v| json!(v)).as_ref()
-                                        == game.get("finalized"))
-                                && (pitchers.is_none()
-                                    || pitchers
-                                        .as_ref()
-                                        .unwrap()
-                                        .contains(game.get("homePitcher").unwrap_or(&json!(null)))
-                                    || pitchers
-                                        .as_ref()
-                                        .unwrap()
-                                        .contains(game.get("awayPitcher").unwrap_or(&json!(""))))
-                                && (teams.is_none()
-                                    || teams
-                                        .as_ref()
-                                        .unwrap()
-                                        .contains(game.get("homeTeam").unwrap_or(&json!(null)))
-                                    || teams
-                                        .as_ref()
-                                        .unwrap()
-                                        .contains(game.get("awayTeam").unwrap_or(&json!(""))))
-                                && (weathers.is_none()
-                                    || weathers
-                                        .as_ref()
-                                        .unwrap()
-                                        .contains(game.get("weather").unwrap_or(&json!(null))))
-                            {
-                                Some(Ok(ChronV1Game {
-                                    game_id: id.to_owned(),
-                                    start_time: *start,
-                                    end_time: *end,
-                                    data: json!(game),
-                                }))
-                            } else {
-                                None
-                            }
-                        }
-                        Err(e) => Some(Err(e)),
-                    }
-                })
-                .collect::<VCRResult<Vec<ChronV1Game>>>()?,
+        let filter = GameFilter {
+            teams: req.team.as_deref().map(parse_uuids),
+            pitchers: req.pitcher.as_deref().map(parse_uuids),
+            weather: req
+                .weather
+                .as_ref()
+                .map(|w| w.split(',').map(|v| v.parse::<i64>().unwrap()).collect()),
+            started: req.started,
+            finished: req.finished,
         };
 
+        // filtering goes through the game index, so only the games that make it into the response get decoded
+        let mut games = db
+            .game_index
+            .iter()
+            .filter(|(date, _)| {
+                (req.tournament.is_none() || req.tournament.as_ref() == date.tournament.as_ref())
+                    && req.day.as_ref().map_or(true, |d| d == &date.day)
+                    && req.season.as_ref().map_or(true, |s| s == &date.season)
+            })
+            .flat_map(|(_, v)| v)
+            .filter(|(_, start, _)| start.map_or(true, |start| start >= after && start <= before))
+            .filter_map(|game| match db.game_matches(&game.0, &filter) {
+                Ok(true) => Some(Ok(game)),
+                Ok(false) => None,
+                Err(e) => Some(Err(e)),
+            })
+            .collect::<VCRResult<Vec<_>>>()?;
+
         if let Some(ord) = req.order {
-            res.data.sort_by_key(|v| v.start_time);
+            games.sort_by_key(|(_, start, _)| *start);
             if ord == Order::Desc {
-                res.data.reverse();
+                games.reverse();
             }
         }
 
-        res.data.truncate(req.count.unwrap_or(usize::MAX));
+        games.truncate(req.count.unwrap_or(usize::MAX));
+
+        let res = ChroniclerV1Response {
+            next_page: None,
+            data: games
+                .into_iter()
+                .map(|(id, start, end)| {
+                    Ok(ChronV1Game {
+                        game_id: id.to_owned(),
+                        start_time: *start,
+                        end_time: *end,
+                        data: db.get_entity("game_updates", id, u32::MAX)?.data,
+                    })
+                })
+                .collect::<VCRResult<Vec<ChronV1Game>>>()?,
+        };
 
         Ok(RocketJson(res))
     } else {
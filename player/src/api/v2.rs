@@ -21,67 +21,11 @@ pub fn versions(
     page_map: &State<Mutex<LruCache<String, InternalPaging<Box<RawValue>>>>>,
 ) -> ChronV2Res<RawChronEntity> {
     let res: ChroniclerResponse<RawChronEntity> = if req.entity_type.to_lowercase() == "stream" {
-        let start_time = req.after.as_ref().map_or(
-            req.before.as_ref().map_or(u32::MAX, |x| {
-                DateTime::parse_from_rfc3339(x).unwrap().timestamp() as u32
-            }) - ((req.count.unwrap_or(1) as u32) * step.0),
-            |y| DateTime::parse_from_rfc3339(y).unwrap().timestamp() as u32,
-        );
-
-        let step = if req.after.is_some() && (1596747150..1596747270).contains(&start_time) {
-            // grand unslam workaround
-            1
-        } else {
-            step.0
+        let results = match step.0 {
+            Some(step) => stepped_stream(&req, step, parallelize_stream_data.0, db)?,
+            None => changed_stream(&req, db)?,
         };
 
-        let end_time = req.before.map_or(
-            req.after.map_or(u32::MIN, |x| {
-                DateTime::parse_from_rfc3339(&x).unwrap().timestamp() as u32
-            }) + ((req.count.unwrap_or(1) as u32) * step),
-            |y| DateTime::parse_from_rfc3339(&y).unwrap().timestamp() as u32,
-        );
-
-        let results = if parallelize_stream_data.0 {
-            (start_time..end_time)
-                .into_par_iter()
-                .step_by(step as usize)
-                .map(|at| {
-                    Ok(ChroniclerEntity {
-                        entity_id: "00000000-0000-0000-0000-000000000000".to_owned(),
-                        valid_from: Utc.timestamp(at as i64, 0),
-                        valid_to: Some(Utc.timestamp((at + step) as i64, 0).to_rfc3339()),
-                        hash: String::new(),
-                        data: db.stream_data(at)?,
-                    })
-                })
-                .collect::<VCRResult<Vec<ChroniclerEntity<JSONValue>>>>()?
-        } else {
-            (start_time..end_time)
-                .into_iter()
-                .step_by(step as usize)
-                .map(|at| {
-                    Ok(ChroniclerEntity {
-                        entity_id: "00000000-0000-0000-0000-000000000000".to_owned(),
-                        valid_from: Utc.timestamp(at as i64, 0),
-                        valid_to: Some(Utc.timestamp((at + step) as i64, 0).to_rfc3339()),
-                        hash: String::new(),
-                        data: db.stream_data(at)?,
-                    })
-                })
-                .collect::<VCRResult<Vec<ChroniclerEntity<JSONValue>>>>()?
-        };
-
-        // for at in (start_time..end_time).into_iter().step_by(step as usize) {
-        //     results.push(ChroniclerEntity {
-        //         entity_id: "00000000-0000-0000-0000-000000000000".to_owned(),
-        //         valid_from: Utc.timestamp(at as i64, 0),
-        //         valid_to: Some(Utc.timestamp((at + step) as i64, 0).to_rfc3339()),
-        //         hash: String::new(),
-        //         data: db.stream_data(at)?,
-        //     });
-        // }
-
         ChroniclerResponse {
             next_page: None,
             items: blaseball_vcr::hash_entities(results)?,
@@ -180,6 +124,123 @@ pub fn versions(
     Ok(RocketJson(res))
 }
 
+/// Stream versions every `step` seconds, whether anything changed or not.
+fn stepped_stream(
+    req: &VersionsReq,
+    step: u32,
+    parallelize: bool,
+    db: &MultiDatabase,
+) -> VCRResult<Vec<ChroniclerEntity<JSONValue>>> {
+    let start_time = req.after.as_ref().map_or(
+        req.before.as_ref().map_or(u32::MAX, |x| {
+            DateTime::parse_from_rfc3339(x).unwrap().timestamp() as u32
+        }) - ((req.count.unwrap_or(1) as u32) * step),
+        |y| DateTime::parse_from_rfc3339(y).unwrap().timestamp() as u32,
+    );
+
+    let step = if req.after.is_some() && (1596747150..1596747270).contains(&start_time) {
+        // grand unslam workaround
+        1
+    } else {
+        step
+    };
+
+    let end_time = req.before.as_ref().map_or(
+        req.after.as_ref().map_or(u32::MIN, |x| {
+            DateTime::parse_from_rfc3339(x).unwrap().timestamp() as u32
+        }) + ((req.count.unwrap_or(1) as u32) * step),
+        |y| DateTime::parse_from_rfc3339(y).unwrap().timestamp() as u32,
+    );
+
+    Ok(if parallelize {
+        (start_time..end_time)
+            .into_par_iter()
+            .step_by(step as usize)
+            .map(|at| {
+                Ok(ChroniclerEntity {
+                    entity_id: "00000000-0000-0000-0000-000000000000".to_owned(),
+                    valid_from: Utc.timestamp(at as i64, 0),
+                    valid_to: Some(Utc.timestamp((at + step) as i64, 0).to_rfc3339()),
+                    hash: String::new(),
+                    data: db.stream_data(at)?,
+                })
+            })
+            .collect::<VCRResult<Vec<ChroniclerEntity<JSONValue>>>>()?
+    } else {
+        // frames in order only refetch what changed since the last one
+        let mut builder = StreamBuilder::new(db);
+        (start_time..end_time)
+            .into_iter()
+            .step_by(step as usize)
+            .map(|at| {
+                Ok(ChroniclerEntity {
+                    entity_id: "00000000-0000-0000-0000-000000000000".to_owned(),
+                    valid_from: Utc.timestamp(at as i64, 0),
+                    valid_to: Some(Utc.timestamp((at + step) as i64, 0).to_rfc3339()),
+                    hash: String::new(),
+                    data: builder.frame(at)?,
+                })
+            })
+            .collect::<VCRResult<Vec<ChroniclerEntity<JSONValue>>>>()?
+    })
+}
+
+fn stream_version(builder: &mut StreamBuilder, at: u32) -> VCRResult<ChroniclerEntity<JSONValue>> {
+    let data = builder.frame(at)?;
+    let (valid_from, valid_to) = builder.frame_span();
+
+    Ok(ChroniclerEntity {
+        entity_id: "00000000-0000-0000-0000-000000000000".to_owned(),
+        valid_from: Utc.timestamp(valid_from as i64, 0),
+        valid_to: valid_to.map(|t| Utc.timestamp(t as i64, 0).to_rfc3339()),
+        hash: String::new(),
+        data,
+    })
+}
+
+/// Stream versions at the points where anything in the frame changes, so each one lasts until the next. Going forward from `after` when it's set, or back from `before` (or the end of the tapes) otherwise.
+fn changed_stream(
+    req: &VersionsReq,
+    db: &MultiDatabase,
+) -> VCRResult<Vec<ChroniclerEntity<JSONValue>>> {
+    let parse = |time: &String| DateTime::parse_from_rfc3339(time).unwrap().timestamp() as u32;
+    let after = req.after.as_ref().map(parse);
+    let before = req.before.as_ref().map(parse);
+
+    let mut builder = StreamBuilder::new(db);
+    let mut results = Vec::new();
+
+    if let Some(after) = after {
+        // with both ends set, a page of the versions in between, like the other versions endpoints
+        let count = req.count.unwrap_or(if before.is_some() { 100 } else { 1 });
+        let before = before.unwrap_or(u32::MAX);
+        let mut at = after;
+
+        while results.len() < count && at < before {
+            results.push(stream_version(&mut builder, at)?);
+            match builder.frame_span().1 {
+                Some(next) => at = next,
+                None => break,
+            }
+        }
+    } else {
+        let count = req.count.unwrap_or(1);
+        let mut at = before.unwrap_or(u32::MAX);
+
+        while results.len() < count {
+            results.push(stream_version(&mut builder, at)?);
+            match builder.frame_span().0 {
+                0 => break,
+                from => at = from - 1,
+            }
+        }
+
+        results.reverse();
+    }
+
+    Ok(results)
+}
+
 #[get("/entities?<req..>")]
 pub fn entities(
     req: EntityReq,
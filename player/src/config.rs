@@ -0,0 +1,123 @@
+use blaseball_vcr::{archive::Archive, feed::FeedDatabase, MultiDatabase, VCRError, VCRResult};
+use rocket::figment::{
+    providers::{Env, Format, Toml},
+    Figment, Profile,
+};
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The `[vcr]` section of `Vcr.toml`.
+#[derive(serde::Deserialize, Debug)]
+pub struct VCRConfig {
+    pub archive: Option<String>,
+    pub tapes: Option<String>,
+    pub site_assets: Option<String>,
+    pub zstd_dictionaries: Option<String>,
+    pub feed: Option<FeedConfig>,
+    pub cached_page_capacity: Option<usize>,
+    pub entities_cache_size: Option<usize>,
+    pub time_responses: Option<bool>,
+    pub cors: Option<bool>,
+    pub stream_data_step: Option<u32>,
+    pub parallelize_stream_data: Option<bool>,
+    pub stats_cache: Option<String>,
+    #[cfg(feature = "gui")]
+    pub gui: Option<bool>,
+    #[cfg(feature = "gui")]
+    pub gui_title: Option<String>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+pub struct FeedConfig {
+    pub index: String,
+    pub path: String,
+    pub dict: String,
+    pub id_table: String,
+    pub tag_table: String,
+    pub cache_size: Option<usize>,
+}
+
+/// Moves into the folder the data lives in: `$APPDIR` when running from an AppImage, otherwise the closest folder above the executable that has a Vcr.toml.
+pub fn enter_vcr_dir() {
+    if let Some((_, path)) = std::env::vars().find(|(k, _)| k == "APPDIR") {
+        std::env::set_current_dir(path).unwrap();
+    } else {
+        // traverse from the directory where we live up until we find a Vcr.toml, then chdir there.
+        if let Ok(dir) = std::env::current_exe() {
+            if let Some(new_dir) = dir.ancestors().find(|d| d.join("Vcr.toml").exists()) {
+                std::env::set_current_dir(new_dir).unwrap();
+            }
+        }
+    };
+}
+
+/// Reads a Vcr.toml (and `VCR_` environment variables) on top of rocket's defaults, for the profile in `VCR_PROFILE`.
+pub fn vcr_figment<P: AsRef<Path>>(path: P) -> Figment {
+    Figment::from(rocket::Config::default())
+        .merge(Toml::file(path).nested())
+        .merge(Env::prefixed("VCR_"))
+        .select(Profile::from_env_or("VCR_PROFILE", "default"))
+}
+
+impl VCRConfig {
+    /// Every `{type}.dict` in the dictionaries folder, by entity type.
+    pub fn dictionaries(&self) -> VCRResult<HashMap<String, PathBuf>> {
+        let dicts_folder = match &self.zstd_dictionaries {
+            Some(folder) => folder,
+            None => return Ok(HashMap::new()),
+        };
+
+        Ok(std::fs::read_dir(dicts_folder)?
+            .map(|res| res.map(|e| e.path()))
+            .collect::<Result<Vec<PathBuf>, io::Error>>()?
+            .into_iter()
+            .filter(|path| path.extension().map_or(false, |ext| ext == "dict"))
+            .filter_map(|path| Some((path.file_stem()?.to_string_lossy().to_string(), path)))
+            .collect())
+    }
+
+    pub fn open_archive(&self) -> VCRResult<Option<Archive>> {
+        self.archive.as_ref().map(Archive::open).transpose()
+    }
+
+    /// Reads the entity tapes, from the archive if there is one and from the tapes folder otherwise.
+    pub fn load_database(&self, archive: Option<&Archive>) -> VCRResult<MultiDatabase> {
+        let cache_size = self.entities_cache_size.unwrap_or(30);
+        if let Some(archive) = archive {
+            MultiDatabase::from_archive(archive, cache_size)
+        } else {
+            MultiDatabase::from_folder(
+                PathBuf::from(self.tapes.as_ref().ok_or_else(|| {
+                    VCRError::IOError(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        "missing tapes folder in vcr config!",
+                    ))
+                })?),
+                self.dictionaries()?,
+                cache_size,
+            )
+        }
+    }
+
+    /// Reads the feed, if the archive has one or it's set up in the config.
+    pub fn load_feed(&self, archive: Option<&Archive>) -> VCRResult<Option<FeedDatabase>> {
+        let cache_size = self.feed.as_ref().and_then(|f| f.cache_size).unwrap_or(50);
+
+        if let Some(archive) = archive.filter(|a| a.contains("feed/feed.riv")) {
+            FeedDatabase::from_archive(archive, cache_size).map(Some)
+        } else if let Some(feed_config) = &self.feed {
+            FeedDatabase::from_files(
+                &feed_config.index,
+                &feed_config.path,
+                &feed_config.dict,
+                &feed_config.id_table,
+                &feed_config.tag_table,
+                cache_size,
+            )
+            .map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+}
@@ -3,6 +3,8 @@
 mod api;
 pub use api::*;
 
+pub mod config;
+
 pub mod types;
 pub use types::*;
 
@@ -0,0 +1,269 @@
+use blaseball_vcr::{InternalPaging, Order, VCRError, VCRResult};
+use clap::{clap_app, ArgMatches};
+use lru::LruCache;
+use rocket::State;
+use serde::Serialize;
+use serde_json::value::RawValue;
+use std::io::{self, Write};
+use std::sync::Mutex;
+
+use player::{config::*, feed, types::*, v1, v2};
+
+// runs the same queries as the HTTP API straight off the tapes, without starting the server
+
+type PageCache = Mutex<LruCache<String, InternalPaging<Box<RawValue>>>>;
+
+/// Prints either one pretty JSON document or one compact line per item.
+struct Output {
+    ndjson: bool,
+}
+
+impl Output {
+    fn items<T: Serialize>(&self, items: &[T]) -> VCRResult<()> {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+
+        if self.ndjson {
+            for item in items {
+                serde_json::to_writer(&mut out, item)?;
+                writeln!(out)?;
+            }
+        } else {
+            serde_json::to_writer_pretty(&mut out, items)?;
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn order(args: &ArgMatches) -> Option<Order> {
+    args.value_of("ORDER").map(|o| match o {
+        "desc" => Order::Desc,
+        _ => Order::Asc,
+    })
+}
+
+fn string(args: &ArgMatches, name: &str) -> Option<String> {
+    args.value_of(name).map(|v| v.to_owned())
+}
+
+fn parse<T: std::str::FromStr>(args: &ArgMatches, name: &str) -> Option<T> {
+    args.value_of(name).map(|v| {
+        v.parse::<T>().unwrap_or_else(|_| {
+            eprintln!("invalid value for --{}: {}", name.to_lowercase(), v);
+            std::process::exit(1)
+        })
+    })
+}
+
+/// Calls a paginated endpoint until it stops handing out page tokens, collecting every item.
+fn all_pages<T, F>(mut fetch: F) -> VCRResult<Vec<T>>
+where
+    F: FnMut(Option<String>) -> VCRResult<(Option<String>, Vec<T>)>,
+{
+    let mut items = Vec::new();
+    let mut page = None;
+
+    loop {
+        let (next_page, mut results) = fetch(page)?;
+        items.append(&mut results);
+
+        match next_page {
+            Some(token) => page = Some(token),
+            None => break,
+        }
+    }
+
+    Ok(items)
+}
+
+fn main() -> VCRResult<()> {
+    let matches = clap_app!(vcr_query =>
+        (version: "1.0")
+        (author: "allie signet <allie@sibr.dev>")
+        (about: "query blaseball.vcr tapes from the command line, with the same parameters as the HTTP API")
+        (@arg CONFIG: -c --config [FILE] "Vcr.toml to read (default: the one the player would use)")
+        (@arg NDJSON: --ndjson "print one item per line instead of a single JSON array")
+        (@subcommand entity =>
+            (about: "like /vcr/v2/entities")
+            (@arg TYPE: --("type") <TYPE> "entity type")
+            (@arg ID: --id [IDS] "comma separated entity ids")
+            (@arg AT: --at [TIME] "RFC 3339 timestamp")
+            (@arg COUNT: --count [N] "page size")
+            (@arg ORDER: --order [ORDER] possible_value[asc desc] "id order")
+        )
+        (@subcommand versions =>
+            (about: "like /vcr/v2/versions")
+            (@arg TYPE: --("type") <TYPE> "entity type")
+            (@arg ID: --id [IDS] "comma separated entity ids")
+            (@arg BEFORE: --before [TIME] "RFC 3339 timestamp")
+            (@arg AFTER: --after [TIME] "RFC 3339 timestamp")
+            (@arg COUNT: --count [N] "page size")
+            (@arg ORDER: --order [ORDER] possible_value[asc desc] "version order")
+        )
+        (@subcommand stream =>
+            (about: "like /vcr/v2/versions?type=Stream")
+            (@arg BEFORE: --before [TIME] "RFC 3339 timestamp")
+            (@arg AFTER: --after [TIME] "RFC 3339 timestamp")
+            (@arg COUNT: --count [N] "number of versions")
+        )
+        (@subcommand games =>
+            (about: "like /vcr/v1/games")
+            (@arg AFTER: --after [TIME] "RFC 3339 timestamp")
+            (@arg BEFORE: --before [TIME] "RFC 3339 timestamp")
+            (@arg COUNT: --count [N] "maximum number of games")
+            (@arg DAY: --day [DAY] "day (zero-indexed)")
+            (@arg SEASON: --season [SEASON] "season (zero-indexed)")
+            (@arg FINISHED: --finished [BOOL] "only finished (or unfinished) games")
+            (@arg ORDER: --order [ORDER] possible_value[asc desc] "start time order")
+            (@arg PITCHER: --pitcher [IDS] "comma separated pitcher ids")
+            (@arg STARTED: --started [BOOL] "only started (or unstarted) games")
+            (@arg TEAM: --team [IDS] "comma separated team ids")
+            (@arg TOURNAMENT: --tournament [N] "tournament")
+            (@arg WEATHER: --weather [IDS] "comma separated weather ids")
+        )
+        (@subcommand feed =>
+            (about: "like /vcr/feed/<kind>")
+            (@arg KIND: <KIND> possible_value[global player team game] "feed kind")
+            (@arg ID: --id [ID] "player, team or game id")
+            (@arg TIME: --time [MILLIS] "UNIX timestamp in milliseconds")
+            (@arg START: --start [TIME] "RFC 3339 timestamp")
+            (@arg LIMIT: --limit [N] "maximum number of events")
+            (@arg PHASE: --phase [PHASE] "phase")
+            (@arg SEASON: --season [SEASON] "season")
+            (@arg CATEGORY: --category [CATEGORY] "event category")
+            (@arg TYPE: --("type") [TYPE] "event type")
+        )
+    )
+    .get_matches();
+
+    let config_path = match matches.value_of("CONFIG") {
+        Some(path) => path.to_owned(),
+        None => {
+            enter_vcr_dir();
+            "Vcr.toml".to_owned()
+        }
+    };
+
+    let config: VCRConfig = vcr_figment(&config_path)
+        .extract_inner("vcr")
+        .expect("missing vcr config!");
+    let output = Output {
+        ndjson: matches.is_present("NDJSON"),
+    };
+
+    let archive = config.open_archive()?;
+
+    match matches.subcommand() {
+        ("feed", Some(args)) => {
+            let feed_db = config
+                .load_feed(archive.as_ref())?
+                .ok_or(VCRError::EntityTypeNotFound)?;
+
+            let events = feed::feed(
+                args.value_of("KIND").unwrap(),
+                State::from(&feed_db),
+                FeedReq {
+                    id: string(args, "ID"),
+                    time: parse(args, "TIME"),
+                    start: string(args, "START"),
+                    limit: parse(args, "LIMIT"),
+                    phase: parse(args, "PHASE"),
+                    season: parse(args, "SEASON"),
+                    category: parse(args, "CATEGORY"),
+                    etype: parse(args, "TYPE"),
+                },
+            )?
+            .into_inner();
+
+            output.items(&events)?;
+        }
+        (subcommand, Some(args)) => {
+            let db = config.load_database(archive.as_ref())?;
+            let page_cache: PageCache =
+                Mutex::new(LruCache::new(config.cached_page_capacity.unwrap_or(20)));
+
+            match subcommand {
+                "entity" => {
+                    let items = all_pages(|page| {
+                        let res = v2::entities(
+                            EntityReq {
+                                entity_type: string(args, "TYPE").unwrap(),
+                                ids: string(args, "ID"),
+                                at: string(args, "AT"),
+                                count: parse(args, "COUNT"),
+                                page,
+                                order: order(args),
+                            },
+                            State::from(&db),
+                            State::from(&page_cache),
+                        )?
+                        .into_inner();
+                        Ok((res.next_page, res.items))
+                    })?;
+
+                    output.items(&items)?;
+                }
+                "versions" | "stream" => {
+                    let step = StreamDataStep(config.stream_data_step);
+                    let parallelize =
+                        ParallelizeStreamData(config.parallelize_stream_data.unwrap_or(false));
+
+                    let items = all_pages(|page| {
+                        let res = v2::versions(
+                            VersionsReq {
+                                entity_type: if subcommand == "stream" {
+                                    "Stream".to_owned()
+                                } else {
+                                    string(args, "TYPE").unwrap()
+                                },
+                                ids: string(args, "ID"),
+                                before: string(args, "BEFORE"),
+                                after: string(args, "AFTER"),
+                                count: parse(args, "COUNT"),
+                                order: order(args),
+                                page,
+                            },
+                            State::from(&step),
+                            State::from(&parallelize),
+                            State::from(&db),
+                            State::from(&page_cache),
+                        )?
+                        .into_inner();
+                        Ok((res.next_page, res.items))
+                    })?;
+
+                    output.items(&items)?;
+                }
+                "games" => {
+                    let games = v1::games(
+                        Some(V1GamesReq {
+                            after: string(args, "AFTER"),
+                            before: string(args, "BEFORE"),
+                            count: parse(args, "COUNT"),
+                            day: parse(args, "DAY"),
+                            season: parse(args, "SEASON"),
+                            finished: parse(args, "FINISHED"),
+                            order: order(args),
+                            pitcher: string(args, "PITCHER"),
+                            started: parse(args, "STARTED"),
+                            team: string(args, "TEAM"),
+                            tournament: parse(args, "TOURNAMENT"),
+                            weather: string(args, "WEATHER"),
+                        }),
+                        UserAgent(None),
+                        State::from(&db),
+                    )?
+                    .into_inner();
+
+                    output.items(&games.data)?;
+                }
+                _ => println!("{}", matches.usage()),
+            }
+        }
+        _ => println!("{}", matches.usage()),
+    }
+
+    Ok(())
+}
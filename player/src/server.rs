@@ -1,22 +1,18 @@
 use blaseball_vcr::site::manager::ResourceManager;
-use blaseball_vcr::{feed::FeedDatabase, InternalPaging, MultiDatabase};
+use blaseball_vcr::stats::StatsCache;
+use blaseball_vcr::InternalPaging;
 use lru::LruCache;
-use rocket::figment::{
-    providers::{Env, Format, Toml},
-    Figment, Profile,
-};
+use rocket::figment::Figment;
 use rocket::{
     get,
     http::{uri::Origin, ContentType, Status},
     response::Redirect,
     routes, State,
 };
-use std::collections::HashMap;
 use std::io::Write;
-use std::path::PathBuf;
 use std::sync::{mpsc, Mutex};
 
-use player::{types::*, v1, v2, RunState};
+use player::{config::*, events, games, stats, tributes, types::*, v1, v2, RunState};
 
 use serde_json::value::RawValue;
 
@@ -64,7 +60,7 @@ async fn build_rocket(figment: Figment) -> rocket::Rocket<rocket::Build> {
 
 #[cfg(feature = "bundle_before")]
 async fn build_rocket(figment: Figment) -> rocket::Rocket<rocket::Build> {
-    use rocket::figment::{providers::Serialized, util::map};
+    use rocket::figment::{providers::Serialized, util::map, Profile};
 
     let profile = Profile::from_env_or("VCR_PROFILE", "default");
     let figment = figment
@@ -101,78 +97,12 @@ async fn spinny(formatting: &str, msg: &str) {
 
 #[rocket::main]
 async fn main() -> Result<(), rocket::Error> {
-    #[derive(serde::Deserialize, Debug)]
-    struct VCRConfig {
-        tapes: String,
-        site_assets: String,
-        zstd_dictionaries: Option<String>,
-        feed: Option<FeedConfig>,
-        cached_page_capacity: Option<usize>,
-        entities_cache_size: Option<usize>,
-        time_responses: Option<bool>,
-        cors: Option<bool>,
-        stream_data_step: Option<u32>,
-        parallelize_stream_data: Option<bool>,
-        #[cfg(feature = "gui")]
-        gui: Option<bool>,
-        #[cfg(feature = "gui")]
-        gui_title: Option<String>,
-    }
-
-    #[derive(serde::Deserialize, Debug)]
-    struct FeedConfig {
-        index: String,
-        path: String,
-        dict: String,
-        id_table: String,
-        tag_table: String,
-        cache_size: Option<usize>,
-    }
+    enter_vcr_dir();
 
-    if let Some((_, path)) = std::env::vars().find(|(k, _)| k == "APPDIR") {
-        std::env::set_current_dir(path).unwrap();
-    } else {
-        // traverse from the directory where we live up until we find a Vcr.toml, then chdir there.
-        if let Ok(dir) = std::env::current_exe() {
-            if let Some(new_dir) = dir.ancestors().find(|d| d.join("Vcr.toml").exists()) {
-                std::env::set_current_dir(new_dir).unwrap();
-            }
-        }
-    };
-
-    let figment = Figment::from(rocket::Config::default())
-        .merge(Toml::file("Vcr.toml").nested())
-        .merge(Env::prefixed("VCR_"))
-        .select(Profile::from_env_or("VCR_PROFILE", "default"));
+    let figment = vcr_figment("Vcr.toml");
     let config: VCRConfig = figment.extract_inner("vcr").expect("missing vcr config!");
     let mut rocket = build_rocket(figment).await;
 
-    let dicts = if let Some(dicts_folder) = config.zstd_dictionaries {
-        std::fs::read_dir(dicts_folder)
-            .unwrap()
-            .map(|res| res.map(|e| e.path()))
-            .collect::<Result<Vec<PathBuf>, std::io::Error>>()
-            .unwrap()
-            .into_iter()
-            .filter_map(|path| {
-                if let Some(ext) = path.extension() {
-                    if ext == "dict" {
-                        Some((
-                            path.file_stem().unwrap().to_string_lossy().to_string(),
-                            path,
-                        ))
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            })
-            .collect::<HashMap<String, PathBuf>>()
-    } else {
-        HashMap::new()
-    };
-
     let (state_tx, state_rx) = mpsc::channel();
 
     let ui_handle;
@@ -192,41 +122,47 @@ async fn main() -> Result<(), rocket::Error> {
         ui_handle = rocket::tokio::task::spawn(async {});
     }
 
+    let archive = config.open_archive().expect("couldn't open .vcr archive");
+
     state_tx.send(RunState::ReadingEntities).unwrap();
     let blahaj = rocket::tokio::task::spawn(spinny("\x1b[1m", "reading entities database"));
-    let dbs = MultiDatabase::from_folder(
-        PathBuf::from(config.tapes),
-        dicts,
-        config.entities_cache_size.unwrap_or(30),
-    )
-    .unwrap();
+    let dbs = config.load_database(archive.as_ref()).unwrap();
     blahaj.abort();
 
     println!();
 
     state_tx.send(RunState::ReadingSiteAssets).unwrap();
     let blahaj = rocket::tokio::task::spawn(spinny("\x1b[1m", "reading site assets"));
-    let manager = ResourceManager::from_folder(&config.site_assets).unwrap();
+    let manager = if let Some(ref archive) = archive {
+        ResourceManager::from_archive(archive)
+    } else {
+        ResourceManager::from_folder(
+            config
+                .site_assets
+                .as_ref()
+                .expect("missing site assets folder in vcr config!"),
+        )
+    }
+    .unwrap();
     blahaj.abort();
     println!();
 
-    if let Some(feed_config) = config.feed {
+    if config.feed.is_some()
+        || archive
+            .as_ref()
+            .map_or(false, |a| a.contains("feed/feed.riv"))
+    {
         state_tx.send(RunState::ReadingFeed).unwrap();
         let blahaj = rocket::tokio::task::spawn(spinny("\x1b[1m", "reading feed data"));
-        let feed_db = FeedDatabase::from_files(
-            feed_config.index,
-            feed_config.path,
-            feed_config.dict,
-            feed_config.id_table,
-            feed_config.tag_table,
-            feed_config.cache_size.unwrap_or(50),
-        )
-        .unwrap();
+        let feed_db = config.load_feed(archive.as_ref()).unwrap();
         blahaj.abort();
         println!();
-        rocket = rocket
-            .manage(feed_db)
-            .mount("/vcr", routes![player::feed::feed]);
+
+        if let Some(feed_db) = feed_db {
+            rocket = rocket
+                .manage(feed_db)
+                .mount("/vcr", routes![player::feed::feed]);
+        }
     }
 
     if config.time_responses.unwrap_or(false) {
@@ -240,11 +176,15 @@ async fn main() -> Result<(), rocket::Error> {
     let cache: LruCache<String, InternalPaging<Box<RawValue>>> =
         LruCache::new(config.cached_page_capacity.unwrap_or(20));
 
+    let stats_cache =
+        StatsCache::new(config.stats_cache.as_ref()).expect("couldn't create stats cache folder");
+
     let rocket = rocket
         .manage(dbs)
         .manage(manager)
+        .manage(stats_cache)
         .manage(Mutex::new(cache))
-        .manage(StreamDataStep(config.stream_data_step.unwrap_or(5)))
+        .manage(StreamDataStep(config.stream_data_step))
         .manage(ParallelizeStreamData(
             config.parallelize_stream_data.unwrap_or(false),
         ))
@@ -255,7 +195,20 @@ async fn main() -> Result<(), rocket::Error> {
         )
         .mount(
             "/vcr",
-            routes![coffee, embed, cors_preflight, player::feed::library],
+            routes![
+                coffee,
+                embed,
+                cors_preflight,
+                player::feed::library,
+                events::stream_data,
+                games::summary,
+                games::search,
+                games::plays,
+                stats::player,
+                stats::team,
+                tributes::history,
+                tributes::rank
+            ],
         )
         .ignite()
         .await?;
@@ -3,8 +3,9 @@ mod requests;
 pub use fairings::*;
 pub use requests::*;
 
+/// Seconds between stream versions, or `None` for a version whenever something in the stream changes.
 #[derive(Debug)]
-pub struct StreamDataStep(pub u32);
+pub struct StreamDataStep(pub Option<u32>);
 
 #[derive(Debug)]
 pub struct ParallelizeStreamData(pub bool);
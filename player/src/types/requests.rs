@@ -1,5 +1,5 @@
-use blaseball_vcr::Order;
-use rocket::FromForm;
+use blaseball_vcr::{stats::StatGrouping, Order};
+use rocket::{FromForm, FromFormField};
 
 #[derive(FromForm)]
 pub struct EntityReq {
@@ -67,3 +67,57 @@ pub struct FeedReq {
     #[field(name = "type")]
     pub etype: Option<i16>,
 }
+
+#[derive(Debug, FromForm)]
+pub struct TributesHistoryReq {
+    pub id: String,
+    pub after: Option<String>,
+    pub before: Option<String>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct TributesRankReq {
+    pub id: String,
+    pub at: Option<String>,
+}
+
+#[derive(Debug, Copy, Clone, FromFormField, PartialEq)]
+pub enum SummaryFormat {
+    #[field(value = "json")]
+    Json,
+    #[field(value = "csv")]
+    Csv,
+}
+
+#[derive(Debug, FromForm)]
+pub struct GameSummaryReq {
+    pub season: Option<i32>,
+    pub day: Option<i32>,
+    pub team: Option<String>,
+    pub format: Option<SummaryFormat>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct GameSearchReq {
+    #[field(name = "q")]
+    pub query: String,
+    pub season: Option<i32>,
+    pub day: Option<i32>,
+    pub after: Option<String>,
+    pub before: Option<String>,
+    pub team: Option<String>,
+    pub pitcher: Option<String>,
+    pub count: Option<usize>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct StatsReq {
+    pub season: Option<i32>,
+    pub group: Option<StatGrouping>,
+}
+
+#[derive(Debug, FromForm)]
+pub struct StreamEventsReq {
+    pub start: Option<String>,
+    pub speed: Option<f64>,
+}
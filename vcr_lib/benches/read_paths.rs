@@ -0,0 +1,165 @@
+mod synthetic;
+
+use blaseball_vcr::StreamBuilder;
+use chrono::{TimeZone, Utc};
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use synthetic::*;
+
+// cold benchmarks open a fresh database (with an empty cache) for every iteration, outside of the timed part.
+// warm ones reuse a database that already answered the same query.
+
+const CACHE_SIZE: usize = 50;
+
+fn get_entity(c: &mut Criterion, tapes: &Tapes) {
+    let mut group = c.benchmark_group("get_entity");
+    let id = &tapes.players[0];
+
+    for (tape, folder) in [
+        ("checkpointed", tapes.tapes()),
+        ("no_checkpoints", tapes.no_checkpoints()),
+    ] {
+        let warm = tapes.open_database(&folder, "player", CACHE_SIZE);
+
+        // the last version is stored whole, so "late" stops one short of it
+        for (position, at) in [
+            ("early", START + 50 * STEP),
+            ("middle", START + PLAYER_VERSIONS / 2 * STEP),
+            ("late", START + (PLAYER_VERSIONS - 2) * STEP),
+        ] {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}/cold", tape), position),
+                &at,
+                |b, &at| {
+                    b.iter_batched_ref(
+                        || tapes.open_database(&folder, "player", CACHE_SIZE),
+                        |db| db.get_entity(id, at).unwrap(),
+                        BatchSize::PerIteration,
+                    )
+                },
+            );
+
+            warm.get_entity(id, at).unwrap();
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}/warm", tape), position),
+                &at,
+                |b, &at| b.iter(|| warm.get_entity(id, at).unwrap()),
+            );
+        }
+    }
+
+    group.finish();
+}
+
+fn get_entity_versions(c: &mut Criterion, tapes: &Tapes) {
+    let mut group = c.benchmark_group("get_entity_versions");
+    let id = &tapes.players[0];
+
+    for (tape, folder) in [
+        ("checkpointed", tapes.tapes()),
+        ("no_checkpoints", tapes.no_checkpoints()),
+    ] {
+        let db = tapes.open_database(&folder, "player", CACHE_SIZE);
+        let end = START + PLAYER_VERSIONS * STEP;
+
+        for (range, (before, after)) in [("all", (u32::MAX, 0)), ("last_hour", (end, end - 3600))] {
+            group.bench_function(BenchmarkId::new(tape, range), |b| {
+                b.iter(|| db.get_entity_versions(id, before, after).unwrap())
+            });
+        }
+    }
+
+    group.finish();
+}
+
+fn stream_data(c: &mut Criterion, tapes: &Tapes) {
+    let mut group = c.benchmark_group("stream_data");
+    group.sample_size(20);
+
+    // halfway through a day, while games are running
+    let at = START + 3 * DAY_LENGTH + DAY_LENGTH / 2;
+
+    group.bench_function("cold", |b| {
+        b.iter_batched_ref(
+            || tapes.open_multi(CACHE_SIZE),
+            |db| db.stream_data(at).unwrap(),
+            BatchSize::PerIteration,
+        )
+    });
+
+    let warm = tapes.open_multi(CACHE_SIZE);
+    warm.stream_data(at).unwrap();
+    group.bench_function("warm", |b| b.iter(|| warm.stream_data(at).unwrap()));
+
+    // a minute of frames at the player's default 5 second step, like a page of /vcr/v2/versions?type=Stream
+    group.bench_function("frames", |b| {
+        b.iter(|| {
+            (0..12)
+                .map(|i| warm.stream_data(at + i * 5).unwrap())
+                .collect::<Vec<_>>()
+        })
+    });
+
+    // the same minute, building each frame from the one before
+    group.bench_function("frames_incremental", |b| {
+        b.iter(|| {
+            let mut builder = StreamBuilder::new(&warm);
+            (0..12)
+                .map(|i| builder.frame(at + i * 5).unwrap())
+                .collect::<Vec<_>>()
+        })
+    });
+
+    group.finish();
+}
+
+fn events_before(c: &mut Criterion, tapes: &Tapes) {
+    let mut group = c.benchmark_group("events_before");
+    let time = Utc.timestamp((START + DAYS * DAY_LENGTH / 2) as i64, 0);
+
+    for count in [10, 100] {
+        group.bench_with_input(BenchmarkId::new("cold", count), &count, |b, &count| {
+            b.iter_batched_ref(
+                || tapes.open_feed(CACHE_SIZE.max(count)),
+                |feed| feed.events_before(time, count, -3).unwrap(),
+                BatchSize::PerIteration,
+            )
+        });
+
+        let warm = tapes.open_feed(CACHE_SIZE.max(count));
+        warm.events_before(time, count, -3).unwrap();
+        group.bench_with_input(BenchmarkId::new("warm", count), &count, |b, &count| {
+            b.iter(|| warm.events_before(time, count, -3).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+fn get_resource(c: &mut Criterion, tapes: &Tapes) {
+    let mut group = c.benchmark_group("get_resource");
+    let site = tapes.open_site();
+
+    // every revision is rebuilt from the basis, so later ones apply more deltas
+    for revision in [0, SITE_REVISIONS / 2, SITE_REVISIONS - 1] {
+        group.bench_with_input(
+            BenchmarkId::new("mainjs", revision),
+            &revision,
+            |b, &revision| b.iter(|| site.get_resource("mainjs", revision).unwrap()),
+        );
+    }
+
+    group.finish();
+}
+
+fn read_paths(c: &mut Criterion) {
+    let tapes = Tapes::generate();
+
+    get_entity(c, &tapes);
+    get_entity_versions(c, &tapes);
+    stream_data(c, &tapes);
+    events_before(c, &tapes);
+    get_resource(c, &tapes);
+}
+
+criterion_group!(benches, read_paths);
+criterion_main!(benches);
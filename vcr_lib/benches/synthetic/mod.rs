@@ -0,0 +1,799 @@
+// a small, made up tape set, written in the same formats the encoders use so benchmarks don't need any downloaded data
+
+use blaseball_vcr::encoder::{encode, write_entity_table_entry};
+use blaseball_vcr::feed::{CompactedFeedEvent, FeedDatabase, MetaIndex};
+use blaseball_vcr::site::{manager::ResourceManager, EncodedResource, PatchData};
+use blaseball_vcr::*;
+use chrono::{DateTime, TimeZone, Utc};
+use integer_encoding::VarIntWriter;
+use serde_json::{json, Value as JSONValue};
+use sha2::{Digest, Sha224};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use tempfile::TempDir;
+use uuid::Uuid;
+
+pub const SIM_ID: &str = "00000000-0000-0000-0000-000000000000";
+
+/// When the synthetic timeline starts.
+pub const START: u32 = 1_600_000_000;
+/// Every day of the season takes an hour, with one round of games.
+pub const DAY_LENGTH: u32 = 3600;
+pub const DAYS: u32 = 10;
+pub const TEAMS: usize = 24;
+/// Seconds between versions of a game or a player.
+pub const STEP: u32 = 30;
+
+pub const PLAYERS: usize = 50;
+pub const PLAYER_VERSIONS: u32 = 1000;
+
+pub const FEED_EVENTS: u32 = 18_000;
+pub const SITE_REVISIONS: u16 = 20;
+
+/// Deterministic xorshift, so every run benchmarks the same tapes.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    fn below(&mut self, n: u64) -> u64 {
+        self.next() % n
+    }
+
+    fn float(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+fn id(kind: u8, n: usize) -> String {
+    Uuid::from_u128(((kind as u128) << 96) | n as u128).to_string()
+}
+
+fn team_id(n: usize) -> String {
+    id(1, n)
+}
+
+fn player_id(n: usize) -> String {
+    id(2, n)
+}
+
+fn game_id(day: u32, n: usize) -> String {
+    id(3, day as usize * TEAMS + n)
+}
+
+fn timestamp(time: u32) -> DateTime<Utc> {
+    Utc.timestamp(time as i64, 0)
+}
+
+type Versions = Vec<(u32, JSONValue)>;
+
+/// Encodes entities the way build_entities does: one zstd frame per patch, and a zstd compressed header table.
+fn write_tape(
+    folder: &Path,
+    e_type: &str,
+    entities: Vec<(String, Versions)>,
+    checkpoint_every: u16,
+) {
+    let mut out = BufWriter::new(File::create(folder.join(format!("{}.riv", e_type))).unwrap());
+    let mut header = zstd::Encoder::new(
+        File::create(folder.join(format!("{}.header.riv.zstd", e_type))).unwrap(),
+        3,
+    )
+    .unwrap();
+    let mut compressor = zstd::block::Compressor::new();
+    let mut position: u32 = 0;
+
+    for (id, versions) in entities {
+        let (patches, path_map, base) = encode(versions, checkpoint_every);
+        let mut header_encoder =
+            HeaderEncoder::new(base, checkpoint_every, path_map, position, Vec::new()).unwrap();
+        let mut last_position = position;
+
+        for (time, patch) in patches {
+            header_encoder
+                .write_patch(time, position - last_position)
+                .unwrap();
+
+            let bytes = compressor.compress(&patch.concat(), 3).unwrap();
+            out.write_all(&bytes).unwrap();
+            last_position = position;
+            position += bytes.len() as u32;
+        }
+
+        write_entity_table_entry(
+            &mut header,
+            &Uuid::parse_str(&id).unwrap(),
+            position,
+            &header_encoder.release(),
+        )
+        .unwrap();
+    }
+
+    out.flush().unwrap();
+    header.finish().unwrap();
+}
+
+/// An entity that never changes.
+fn constant(id: &str, value: JSONValue) -> (String, Versions) {
+    (id.to_owned(), vec![(START, value)])
+}
+
+fn players(rng: &mut Rng) -> Vec<(String, Versions)> {
+    (0..PLAYERS)
+        .map(|n| {
+            let mut player = json!({
+                "id": player_id(n),
+                "name": format!("Player {}", n),
+                "leagueTeamId": team_id(n % TEAMS),
+                "buoyancy": rng.float(),
+                "divinity": rng.float(),
+                "martyrdom": rng.float(),
+                "moxie": rng.float(),
+                "musclitude": rng.float(),
+                "patheticism": rng.float(),
+                "thwackability": rng.float(),
+                "tragicness": rng.float(),
+                "ruthlessness": rng.float(),
+                "overpowerment": rng.float(),
+                "unthwackability": rng.float(),
+                "shakespearianism": rng.float(),
+                "suppression": rng.float(),
+                "coldness": rng.float(),
+                "deceased": false,
+                "peanutAllergy": false,
+                "consecutiveHits": 0,
+                "permAttr": [],
+                "seasAttr": [],
+                "items": [
+                    { "name": "Bat", "durability": 3, "health": 3 },
+                    { "name": "Cap", "durability": 2, "health": 2 }
+                ]
+            });
+
+            let versions = (0..PLAYER_VERSIONS)
+                .map(|v| {
+                    match rng.below(10) {
+                        0 => player["permAttr"]
+                            .as_array_mut()
+                            .unwrap()
+                            .push(json!(format!("MOD_{}", rng.below(40)))),
+                        1 => {
+                            let attrs = player["seasAttr"].as_array_mut().unwrap();
+                            if attrs.is_empty() {
+                                attrs.push(json!("OVERPERFORMING"));
+                            } else {
+                                attrs.clear();
+                            }
+                        }
+                        2 => {
+                            let item = rng.below(2) as usize;
+                            player["items"][item]["health"] = json!(rng.below(4));
+                        }
+                        _ => {
+                            player["consecutiveHits"] = json!(rng.below(6));
+                        }
+                    }
+
+                    let stat =
+                        ["buoyancy", "moxie", "thwackability", "coldness"][rng.below(4) as usize];
+                    player[stat] = json!(rng.float());
+
+                    (START + v * STEP, player.clone())
+                })
+                .collect();
+
+            (player_id(n), versions)
+        })
+        .collect()
+}
+
+fn teams(rng: &mut Rng) -> Vec<(String, Versions)> {
+    (0..TEAMS)
+        .map(|n| {
+            let mut team = json!({
+                "id": team_id(n),
+                "fullName": format!("Team {}", n),
+                "nickname": format!("{}s", n),
+                "lineup": (0..9).map(|p| player_id(n * 14 + p)).collect::<Vec<String>>(),
+                "rotation": (9..14).map(|p| player_id(n * 14 + p)).collect::<Vec<String>>(),
+                "permAttr": [],
+                "seasAttr": [],
+                "eDensity": 0.0,
+                "level": 0
+            });
+
+            let versions = (0..DAYS)
+                .map(|day| {
+                    team["eDensity"] = json!(rng.float() * 1000.0);
+                    if rng.below(4) == 0 {
+                        let lineup = team["lineup"].as_array_mut().unwrap();
+                        let a = rng.below(9) as usize;
+                        let b = rng.below(9) as usize;
+                        lineup.swap(a, b);
+                    }
+
+                    (START + day * DAY_LENGTH, team.clone())
+                })
+                .collect();
+
+            (team_id(n), versions)
+        })
+        .collect()
+}
+
+/// Every day, each team plays one game, updated every `STEP` seconds for most of the hour.
+fn games(
+    rng: &mut Rng,
+) -> (
+    Vec<(String, Versions)>,
+    HashMap<GameDate, Vec<(String, Option<DateTime<Utc>>, Option<DateTime<Utc>>)>>,
+) {
+    let mut games = Vec::new();
+    let mut index = HashMap::new();
+
+    for day in 0..DAYS {
+        let start = START + day * DAY_LENGTH;
+        let updates = (DAY_LENGTH - 600) / STEP;
+        let mut dates = Vec::new();
+
+        for n in 0..TEAMS / 2 {
+            let id = game_id(day, n);
+            let mut game = json!({
+                "id": id,
+                "season": 11,
+                "day": day,
+                "awayTeam": team_id(n * 2),
+                "homeTeam": team_id(n * 2 + 1),
+                "awayOdds": 0.5,
+                "homeOdds": 0.5,
+                "awayScore": 0,
+                "homeScore": 0,
+                "inning": 0,
+                "topOfInning": true,
+                "halfInningOuts": 0,
+                "atBatBalls": 0,
+                "atBatStrikes": 0,
+                "baseRunners": [],
+                "basesOccupied": [],
+                "gameStart": false,
+                "gameComplete": false,
+                "lastUpdate": ""
+            });
+
+            let versions = (0..updates)
+                .map(|u| {
+                    game["gameStart"] = json!(true);
+                    game["inning"] = json!(u * 9 / updates);
+                    game["topOfInning"] = json!(u % 2 == 0);
+                    game["atBatBalls"] = json!(rng.below(4));
+                    game["atBatStrikes"] = json!(rng.below(3));
+                    match rng.below(6) {
+                        0 => {
+                            let side = if u % 2 == 0 { "awayScore" } else { "homeScore" };
+                            game[side] = json!(game[side].as_u64().unwrap() + 1);
+                            game["lastUpdate"] =
+                                json!(format!("Player {} hits a home run!", rng.below(300)));
+                        }
+                        1 => {
+                            game["baseRunners"]
+                                .as_array_mut()
+                                .unwrap()
+                                .push(json!(player_id(rng.below(300) as usize)));
+                            game["basesOccupied"]
+                                .as_array_mut()
+                                .unwrap()
+                                .push(json!(rng.below(3)));
+                            game["lastUpdate"] = json!("Single!");
+                        }
+                        2 => {
+                            game["baseRunners"] = json!([]);
+                            game["basesOccupied"] = json!([]);
+                            game["lastUpdate"] = json!("Inning is now over.");
+                        }
+                        _ => {
+                            game["lastUpdate"] = json!(format!(
+                                "Strike, swinging. {}-{}",
+                                game["atBatBalls"], game["atBatStrikes"]
+                            ));
+                        }
+                    }
+                    game["gameComplete"] = json!(u + 1 == updates);
+
+                    (start + u * STEP, game.clone())
+                })
+                .collect();
+
+            dates.push((
+                id.clone(),
+                Some(timestamp(start)),
+                Some(timestamp(start + updates * STEP)),
+            ));
+            games.push((id, versions));
+        }
+
+        index.insert(
+            GameDate {
+                day: day as i32,
+                season: 11,
+                tournament: None,
+            },
+            dates,
+        );
+    }
+
+    (games, index)
+}
+
+/// The entity types stream data is put together from.
+fn write_league(folder: &Path, rng: &mut Rng) {
+    let standings_id = id(4, 0);
+    let tiebreakers_id = id(5, 0);
+    let league_id = id(6, 0);
+    let subleague_ids = [id(7, 0), id(7, 1)];
+    let division_ids = [id(8, 0), id(8, 1), id(8, 2), id(8, 3)];
+
+    write_tape(
+        folder,
+        "sim",
+        vec![(
+            SIM_ID.to_owned(),
+            (0..DAYS)
+                .map(|day| {
+                    (
+                        START + day * DAY_LENGTH,
+                        json!({
+                            "id": SIM_ID,
+                            "season": 11,
+                            "day": day,
+                            "phase": 2,
+                            "playoffs": null,
+                            "nextPhaseTime": timestamp(START + (day + 1) * DAY_LENGTH)
+                        }),
+                    )
+                })
+                .collect(),
+        )],
+        100,
+    );
+
+    write_tape(
+        folder,
+        "season",
+        vec![constant(
+            &id(9, 0),
+            json!({ "id": id(9, 0), "seasonNumber": 11, "standings": standings_id }),
+        )],
+        100,
+    );
+
+    let mut wins: HashMap<String, u32> = HashMap::new();
+    write_tape(
+        folder,
+        "standings",
+        vec![(
+            standings_id.clone(),
+            (0..DAYS)
+                .map(|day| {
+                    for n in 0..TEAMS / 2 {
+                        let winner = team_id(n * 2 + rng.below(2) as usize);
+                        *wins.entry(winner).or_default() += 1;
+                    }
+
+                    (
+                        START + day * DAY_LENGTH,
+                        json!({ "id": standings_id, "wins": wins, "gamesPlayed": day }),
+                    )
+                })
+                .collect(),
+        )],
+        100,
+    );
+
+    write_tape(
+        folder,
+        "league",
+        vec![constant(
+            &league_id,
+            json!({
+                "id": league_id,
+                "name": "Internet League Blaseball",
+                "subleagues": subleague_ids,
+                "tiebreakers": tiebreakers_id
+            }),
+        )],
+        100,
+    );
+
+    write_tape(
+        folder,
+        "subleague",
+        subleague_ids
+            .iter()
+            .enumerate()
+            .map(|(n, id)| {
+                constant(
+                    id,
+                    json!({ "id": id, "name": format!("Subleague {}", n), "divisions": division_ids[n * 2..n * 2 + 2] }),
+                )
+            })
+            .collect(),
+        100,
+    );
+
+    write_tape(
+        folder,
+        "division",
+        division_ids
+            .iter()
+            .enumerate()
+            .map(|(n, id)| {
+                constant(
+                    id,
+                    json!({
+                        "id": id,
+                        "name": format!("Division {}", n),
+                        "teams": (n * 6..n * 6 + 6).map(team_id).collect::<Vec<String>>()
+                    }),
+                )
+            })
+            .collect(),
+        100,
+    );
+
+    let mut order: Vec<String> = (0..TEAMS).map(team_id).collect();
+    write_tape(
+        folder,
+        "tiebreakers",
+        vec![(
+            tiebreakers_id.clone(),
+            (0..DAYS)
+                .map(|day| {
+                    let (a, b) = (rng.below(TEAMS as u64), rng.below(TEAMS as u64));
+                    order.swap(a as usize, b as usize);
+                    (
+                        START + day * DAY_LENGTH,
+                        json!({ "id": tiebreakers_id, "order": order }),
+                    )
+                })
+                .collect(),
+        )],
+        100,
+    );
+
+    write_tape(folder, "team", teams(rng), 100);
+
+    write_tape(
+        folder,
+        "stadium",
+        (0..TEAMS)
+            .map(|n| {
+                let id = id(10, n);
+                constant(
+                    &id,
+                    json!({ "id": id, "teamId": team_id(n), "name": format!("Stadium {}", n), "mods": [] }),
+                )
+            })
+            .collect(),
+        100,
+    );
+
+    write_tape(
+        folder,
+        "bossfight",
+        vec![constant(
+            &id(11, 0),
+            json!({ "id": id(11, 0), "homeTeam": team_id(0), "awayTeam": team_id(1), "homeHp": "1000", "awayHp": "1000" }),
+        )],
+        100,
+    );
+
+    write_tape(
+        folder,
+        "temporal",
+        vec![constant(
+            SIM_ID,
+            json!({ "id": SIM_ID, "doc": { "alpha": 0, "beta": 0, "gamma": 0, "epsilon": false, "zeta": "" } }),
+        )],
+        100,
+    );
+
+    write_tape(
+        folder,
+        "sunsun",
+        vec![constant(
+            SIM_ID,
+            json!({ "id": SIM_ID, "current": 0, "maximum": 1000, "recharge": 1 }),
+        )],
+        100,
+    );
+
+    write_tape(
+        folder,
+        "communitychestprogress",
+        vec![constant(
+            SIM_ID,
+            json!({ "id": SIM_ID, "progress": "0.5", "chests": 0 }),
+        )],
+        100,
+    );
+}
+
+/// One tributes record a day, with peanut counts for every player.
+fn write_tributes(folder: &Path, rng: &mut Rng) {
+    let mut out = BufWriter::new(File::create(folder.join("tributes.riv")).unwrap());
+    let mut position: u32 = 0;
+    let mut times: Vec<(u32, u32, u32)> = Vec::new();
+
+    for day in 0..DAYS {
+        let mut record: Vec<u8> = Vec::new();
+        for n in 0..PLAYERS {
+            record.write_varint((n + 1) as u32).unwrap();
+            record.write_varint(rng.below(100_000)).unwrap();
+        }
+
+        out.write_all(&record).unwrap();
+        times.push((START + day * DAY_LENGTH, position, record.len() as u32));
+        position += record.len() as u32;
+    }
+
+    let ids: Vec<(Uuid, u32, u8)> = (0..PLAYERS)
+        .map(|n| {
+            (
+                Uuid::parse_str(&player_id(n)).unwrap(),
+                (n + 1) as u32,
+                TRIBUTE_PLAYER,
+            )
+        })
+        .collect();
+
+    let header = File::create(folder.join("tributes.header.riv")).unwrap();
+    write_counters_header(BufWriter::new(header), &ids, &times).unwrap();
+    out.flush().unwrap();
+}
+
+/// Play by play events every few seconds, compressed with a dictionary trained on them like the real feed.
+fn write_feed(folder: &Path, rng: &mut Rng) {
+    let mut meta = MetaIndex::default();
+    for n in 0..TEAMS {
+        let id = Uuid::parse_str(&team_id(n)).unwrap();
+        meta.team_tags.insert(n as u8, id);
+        meta.reverse_team_tags.insert(id, n as u8);
+    }
+
+    let events: Vec<Vec<u8>> = (0..FEED_EVENTS)
+        .map(|n| {
+            let time = START + n * (DAYS * DAY_LENGTH / FEED_EVENTS);
+            let day = (time - START) / DAY_LENGTH;
+            let game = rng.below(TEAMS as u64 / 2) as usize;
+            let game_tag = (day as usize * TEAMS / 2 + game) as u16;
+
+            let game_uuid = Uuid::parse_str(&game_id(day, game)).unwrap();
+            meta.game_tags.insert(game_tag, game_uuid);
+            meta.reverse_game_tags.insert(game_uuid, game_tag);
+
+            CompactedFeedEvent {
+                id: Uuid::nil(),
+                created: timestamp(time),
+                category: 0,
+                day: day as u8,
+                description: format!("Top of {}, Team {} batting.", rng.below(9) + 1, game * 2),
+                player_tags: vec![],
+                game_tags: vec![game_tag],
+                team_tags: vec![(game * 2) as u8, (game * 2 + 1) as u8],
+                etype: 2,
+                tournament: -1,
+                metadata: json!({ "play": rng.below(300), "subPlay": -1 }),
+                season: 11,
+                phase: 2,
+            }
+            .encode()
+        })
+        .collect();
+
+    let dictionary = zstd::dict::from_samples(&events, 16 * 1024).unwrap();
+    fs::write(folder.join("feed.dict"), &dictionary).unwrap();
+
+    let mut compressor = zstd::block::Compressor::with_dict(dictionary);
+    let mut out = BufWriter::new(File::create(folder.join("feed.riv")).unwrap());
+    let mut positions =
+        zstd::Encoder::new(File::create(folder.join("feed.fp")).unwrap(), 3).unwrap();
+    let mut position: u32 = 0;
+    let mut last_position: u32 = 0;
+
+    for (n, event) in events.iter().enumerate() {
+        let time = START + n as u32 * (DAYS * DAY_LENGTH / FEED_EVENTS);
+        let bytes = compressor.compress(event, 3).unwrap();
+        out.write_all(&bytes).unwrap();
+
+        positions
+            .write_all(&((position - last_position) as u16).to_be_bytes())
+            .unwrap();
+        positions.write_all(&time.to_be_bytes()).unwrap();
+
+        last_position = position;
+        position += bytes.len() as u32;
+    }
+
+    out.flush().unwrap();
+    positions.finish().unwrap();
+
+    fs::write(
+        folder.join("id_lookup.bin"),
+        rmp_serde::to_vec(&meta).unwrap(),
+    )
+    .unwrap();
+
+    // the tag indexes aren't on any of the benchmarked paths, so they're left empty
+    let mut tag_indexes =
+        zstd::Encoder::new(File::create(folder.join("tag_indexes.fp")).unwrap(), 3).unwrap();
+    for _ in 0..5 {
+        tag_indexes.write_all(&0u32.to_be_bytes()).unwrap();
+    }
+    tag_indexes.finish().unwrap();
+}
+
+/// A ~100KiB script with a few lines changed in every revision, diffed like download_site_data does.
+fn write_site(folder: &Path, rng: &mut Rng) {
+    let mut lines: Vec<String> = (0..3000)
+        .map(|n| format!("var blaseball_{} = \"{:x}\";", n, rng.next()))
+        .collect();
+
+    let revisions: Vec<Vec<u8>> = (0..SITE_REVISIONS)
+        .map(|_| {
+            for _ in 0..20 {
+                let line = rng.below(lines.len() as u64) as usize;
+                lines[line] = format!("var blaseball_{} = \"{:x}\";", line, rng.next());
+            }
+            lines.join("\n").into_bytes()
+        })
+        .collect();
+
+    let mut out = BufWriter::new(File::create(folder.join("mainjs.riv")).unwrap());
+    let mut compressor = zstd::block::Compressor::new();
+    let mut last = revisions[0].clone();
+    let mut deltas = Vec::new();
+    let mut paths = Vec::new();
+    let mut position: u32 = 0;
+
+    for (idx, next) in revisions.iter().enumerate() {
+        let mut delta = Vec::new();
+        bsdiff::diff::diff(&last, next, &mut delta).unwrap();
+        let compressed = compressor.compress(&delta, 11).unwrap();
+        out.write_all(&compressed).unwrap();
+
+        deltas.push(PatchData {
+            offset: position,
+            compressed_patch_length: compressed.len() as u32,
+            uncompressed_patch_length: delta.len() as u32,
+            original_length: next.len() as u32,
+            hash: format!("{:x}", Sha224::digest(next)),
+        });
+        paths.push((
+            timestamp(START + idx as u32 * DAY_LENGTH),
+            "/main.js".to_owned(),
+            idx as u16,
+        ));
+
+        position += compressed.len() as u32;
+        last = next.clone();
+    }
+
+    out.flush().unwrap();
+
+    let header = EncodedResource {
+        paths,
+        basis: revisions[0].clone(),
+        deltas,
+    };
+    rmp_serde::encode::write(
+        &mut File::create(folder.join("mainjs.header.riv")).unwrap(),
+        &header,
+    )
+    .unwrap();
+}
+
+/// The synthetic tape set, in a temporary folder that's deleted when this is dropped.
+pub struct Tapes {
+    dir: TempDir,
+    pub players: Vec<String>,
+}
+
+impl Tapes {
+    pub fn generate() -> Tapes {
+        let dir = tempfile::tempdir().unwrap();
+        let tapes = Tapes {
+            dir,
+            players: (0..PLAYERS).map(player_id).collect(),
+        };
+
+        for folder in [
+            tapes.tapes(),
+            tapes.no_checkpoints(),
+            tapes.feed(),
+            tapes.site_data(),
+        ] {
+            fs::create_dir_all(folder).unwrap();
+        }
+
+        let mut rng = Rng(0x5EED_CAFE);
+
+        // the same players twice: checkpointed like build.toml does it, and as one long chain of patches
+        let players = players(&mut rng);
+        write_tape(&tapes.tapes(), "player", players.clone(), 100);
+        write_tape(&tapes.no_checkpoints(), "player", players, u16::MAX);
+
+        let (games, game_index) = games(&mut rng);
+        write_tape(&tapes.tapes(), "game_updates", games, 100);
+        let mut dates = zstd::Encoder::new(
+            File::create(tapes.tapes().join("game_updates.dates.riv.zstd")).unwrap(),
+            3,
+        )
+        .unwrap();
+        dates
+            .write_all(&rmp_serde::to_vec(&game_index).unwrap())
+            .unwrap();
+        dates.finish().unwrap();
+
+        write_league(&tapes.tapes(), &mut rng);
+        write_tributes(&tapes.tapes(), &mut rng);
+        write_feed(&tapes.feed(), &mut rng);
+        write_site(&tapes.site_data(), &mut rng);
+
+        tapes
+    }
+
+    pub fn tapes(&self) -> PathBuf {
+        self.dir.path().join("tapes")
+    }
+
+    /// Player tapes without checkpoints, kept out of `tapes()` so `MultiDatabase` doesn't see them.
+    pub fn no_checkpoints(&self) -> PathBuf {
+        self.tapes().join("no_checkpoints")
+    }
+
+    pub fn feed(&self) -> PathBuf {
+        self.tapes().join("feed")
+    }
+
+    pub fn site_data(&self) -> PathBuf {
+        self.tapes().join("site_data")
+    }
+
+    pub fn open_database(&self, folder: &Path, e_type: &str, cache_size: usize) -> Database {
+        Database::from_files(
+            folder.join(format!("{}.header.riv.zstd", e_type)),
+            folder.join(format!("{}.riv", e_type)),
+            None,
+            cache_size,
+        )
+        .unwrap()
+    }
+
+    pub fn open_multi(&self, cache_size: usize) -> MultiDatabase {
+        MultiDatabase::from_folder(self.tapes(), HashMap::new(), cache_size).unwrap()
+    }
+
+    pub fn open_feed(&self, cache_size: usize) -> FeedDatabase {
+        let folder = self.feed();
+        FeedDatabase::from_files(
+            folder.join("feed.fp"),
+            folder.join("feed.riv"),
+            folder.join("feed.dict"),
+            folder.join("id_lookup.bin"),
+            folder.join("tag_indexes.fp"),
+            cache_size,
+        )
+        .unwrap()
+    }
+
+    pub fn open_site(&self) -> ResourceManager {
+        ResourceManager::from_folder(self.site_data()).unwrap()
+    }
+}
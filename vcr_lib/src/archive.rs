@@ -0,0 +1,397 @@
+use crate::{VCRError, VCRResult};
+use memmap2::{Mmap, MmapOptions};
+use std::collections::BTreeMap;
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::{Deref, Range};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const ARCHIVE_MAGIC: &[u8; 4] = b"VCRA";
+const ARCHIVE_REVISION: u8 = 1;
+
+/// A cheaply cloneable, read-only view over (part of) a memory map. This lets databases read from either a standalone file or an entry inside a .vcr archive.
+#[derive(Clone)]
+pub struct MappedSlice {
+    map: Arc<Mmap>,
+    range: Range<usize>,
+}
+
+impl MappedSlice {
+    /// Maps a whole file.
+    pub fn from_file(file: &File) -> VCRResult<MappedSlice> {
+        MappedSlice::from_map(unsafe { MmapOptions::new().map(file)? })
+    }
+
+    /// Maps a whole file, eagerly reading it into memory.
+    pub fn from_file_populated(file: &File) -> VCRResult<MappedSlice> {
+        MappedSlice::from_map(unsafe { MmapOptions::new().populate().map(file)? })
+    }
+
+    fn from_map(map: Mmap) -> VCRResult<MappedSlice> {
+        let len = map.len();
+        Ok(MappedSlice {
+            map: Arc::new(map),
+            range: 0..len,
+        })
+    }
+}
+
+impl Deref for MappedSlice {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.map[self.range.clone()]
+    }
+}
+
+/// Somewhere tape files can be read from: a file on disk, or an entry in a .vcr archive.
+pub trait TapeSource {
+    /// Reads it from the start, for headers and tables.
+    fn open(&self) -> VCRResult<Box<dyn Read + '_>>;
+    /// Maps it, for tapes that get read in place.
+    fn map(&self) -> VCRResult<MappedSlice>;
+    /// Like `map`, but eagerly reads it into memory.
+    fn map_populated(&self) -> VCRResult<MappedSlice>;
+}
+
+impl TapeSource for PathBuf {
+    fn open(&self) -> VCRResult<Box<dyn Read + '_>> {
+        Ok(Box::new(BufReader::new(File::open(self)?)))
+    }
+
+    fn map(&self) -> VCRResult<MappedSlice> {
+        MappedSlice::from_file(&File::open(self)?)
+    }
+
+    fn map_populated(&self) -> VCRResult<MappedSlice> {
+        MappedSlice::from_file_populated(&File::open(self)?)
+    }
+}
+
+impl TapeSource for MappedSlice {
+    fn open(&self) -> VCRResult<Box<dyn Read + '_>> {
+        Ok(Box::new(&self[..]))
+    }
+
+    fn map(&self) -> VCRResult<MappedSlice> {
+        Ok(self.clone())
+    }
+
+    fn map_populated(&self) -> VCRResult<MappedSlice> {
+        Ok(self.clone())
+    }
+}
+
+/// A single-file archive bundling tapes, dictionaries, feed and site data.
+///
+/// The file starts with the `VCRA` magic, a revision byte and a table of contents (name, offset, length for every entry); the entries' bytes follow, stored as-is, so the whole archive can be memory mapped and read in place.
+/// Entries are named after the folder they were packed from: `tapes/`, `site_data/`, `dictionaries/` and `feed/`.
+pub struct Archive {
+    map: Arc<Mmap>,
+    entries: BTreeMap<String, Range<usize>>,
+}
+
+impl Archive {
+    pub fn open<P: AsRef<Path>>(path: P) -> VCRResult<Archive> {
+        let file = File::open(path)?;
+        let map = unsafe { MmapOptions::new().map(&file)? };
+
+        if map.len() < 9 || &map[0..4] != ARCHIVE_MAGIC || map[4] != ARCHIVE_REVISION {
+            return Err(VCRError::InvalidArchive);
+        }
+
+        let toc_len = u32::from_be_bytes(map[5..9].try_into().unwrap()) as usize;
+        let mut toc = map.get(9..9 + toc_len).ok_or(VCRError::InvalidArchive)?;
+        let mut entries = BTreeMap::new();
+
+        while !toc.is_empty() {
+            if toc.len() < 2 {
+                return Err(VCRError::InvalidArchive);
+            }
+
+            let name_len = u16::from_be_bytes(toc[0..2].try_into().unwrap()) as usize;
+            if toc.len() < 2 + name_len + 16 {
+                return Err(VCRError::InvalidArchive);
+            }
+
+            let name = String::from_utf8(toc[2..2 + name_len].to_vec())?;
+            let offset =
+                u64::from_be_bytes(toc[2 + name_len..10 + name_len].try_into().unwrap()) as usize;
+            let length =
+                u64::from_be_bytes(toc[10 + name_len..18 + name_len].try_into().unwrap()) as usize;
+
+            let end = offset
+                .checked_add(length)
+                .filter(|end| *end <= map.len())
+                .ok_or(VCRError::InvalidArchive)?;
+
+            entries.insert(name, offset..end);
+            toc = &toc[18 + name_len..];
+        }
+
+        Ok(Archive {
+            map: Arc::new(map),
+            entries,
+        })
+    }
+
+    /// Gets an entry by its full name (e.g. `tapes/player.riv`).
+    pub fn get(&self, name: &str) -> Option<MappedSlice> {
+        self.entries.get(name).map(|range| MappedSlice {
+            map: self.map.clone(),
+            range: range.clone(),
+        })
+    }
+
+    /// Like `get`, but errors out if the entry is missing.
+    pub fn entry(&self, name: &str) -> VCRResult<MappedSlice> {
+        self.get(name)
+            .ok_or_else(|| VCRError::MissingArchiveEntry(name.to_owned()))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.entries.contains_key(name)
+    }
+
+    /// Lists every entry in the archive along with its length.
+    pub fn entries(&self) -> Vec<(&str, usize)> {
+        self.entries
+            .iter()
+            .map(|(name, range)| (name.as_str(), range.len()))
+            .collect()
+    }
+
+    /// Gets all the entries directly inside a folder, keyed by their file name.
+    pub fn folder(&self, folder: &str) -> Vec<(String, MappedSlice)> {
+        let prefix = format!("{}/", folder.trim_end_matches('/'));
+        self.entries
+            .iter()
+            .filter_map(|(name, range)| {
+                let file_name = name.strip_prefix(&prefix)?;
+                if file_name.contains('/') {
+                    return None;
+                }
+
+                Some((
+                    file_name.to_owned(),
+                    MappedSlice {
+                        map: self.map.clone(),
+                        range: range.clone(),
+                    },
+                ))
+            })
+            .collect()
+    }
+
+    /// Writes every entry back out as a regular file under `folder`.
+    pub fn unpack<P: AsRef<Path>>(&self, folder: P) -> VCRResult<()> {
+        for (name, range) in &self.entries {
+            let path = folder.as_ref().join(name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+
+            let mut out = File::create(path)?;
+            out.write_all(&self.map[range.clone()])?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds a .vcr archive out of files on disk.
+#[derive(Default)]
+pub struct ArchiveWriter {
+    files: BTreeMap<String, PathBuf>,
+}
+
+impl ArchiveWriter {
+    pub fn new() -> ArchiveWriter {
+        ArchiveWriter::default()
+    }
+
+    pub fn add_file<P: AsRef<Path>>(&mut self, name: &str, path: P) {
+        self.files
+            .insert(name.to_owned(), path.as_ref().to_path_buf());
+    }
+
+    /// Adds every file directly inside `path` under the `folder` prefix. Subfolders are skipped.
+    pub fn add_folder<P: AsRef<Path>>(&mut self, folder: &str, path: P) -> VCRResult<()> {
+        for entry in fs::read_dir(path)? {
+            let entry_path = entry?.path();
+            if !entry_path.is_file() {
+                continue;
+            }
+
+            if let Some(file_name) = entry_path.file_name().and_then(|n| n.to_str()) {
+                let name = format!("{}/{}", folder.trim_end_matches('/'), file_name);
+                self.files.insert(name, entry_path);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn write<P: AsRef<Path>>(self, path: P) -> VCRResult<()> {
+        let lengths = self
+            .files
+            .values()
+            .map(|path| Ok(fs::metadata(path)?.len()))
+            .collect::<VCRResult<Vec<u64>>>()?;
+
+        // names are stored with a 16-bit length
+        if let Some(name) = self
+            .files
+            .keys()
+            .find(|name| name.len() > u16::MAX as usize)
+        {
+            return Err(VCRError::IOError(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("archive entry name too long: {}", name),
+            )));
+        }
+
+        let toc_len: usize = self.files.keys().map(|name| 2 + name.len() + 16).sum();
+        let mut offset = (9 + toc_len) as u64;
+
+        let mut toc: Vec<u8> = Vec::with_capacity(toc_len);
+        for (name, length) in self.files.keys().zip(lengths.iter()) {
+            toc.extend((name.len() as u16).to_be_bytes());
+            toc.extend(name.as_bytes());
+            toc.extend(offset.to_be_bytes());
+            toc.extend(length.to_be_bytes());
+            offset += length;
+        }
+
+        let mut out = BufWriter::new(File::create(path)?);
+        out.write_all(ARCHIVE_MAGIC)?;
+        out.write_all(&[ARCHIVE_REVISION])?;
+        out.write_all(&(toc.len() as u32).to_be_bytes())?;
+        out.write_all(&toc)?;
+
+        for (path, length) in self.files.values().zip(lengths.into_iter()) {
+            let mut file = File::open(path)?;
+            if io::copy(&mut file, &mut out)? != length {
+                return Err(VCRError::InvalidArchive);
+            }
+        }
+
+        out.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_file(path: PathBuf, bytes: &[u8]) -> PathBuf {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, bytes).unwrap();
+        path
+    }
+
+    /// An archive with a single entry whose offset and length are set by hand.
+    fn archive_with_entry(dir: &TempDir, offset: u64, length: u64) -> PathBuf {
+        let name = b"tapes/a.riv";
+        let mut bytes = ARCHIVE_MAGIC.to_vec();
+        bytes.push(ARCHIVE_REVISION);
+        bytes.extend(((2 + name.len() + 16) as u32).to_be_bytes());
+        bytes.extend((name.len() as u16).to_be_bytes());
+        bytes.extend(name);
+        bytes.extend(offset.to_be_bytes());
+        bytes.extend(length.to_be_bytes());
+        bytes.extend(b"data");
+        write_file(dir.path().join("crafted.vcr"), &bytes)
+    }
+
+    #[test]
+    fn round_trip() {
+        let dir = TempDir::new().unwrap();
+        let tapes = dir.path().join("tapes");
+        write_file(tapes.join("player.riv"), &[1, 2, 3, 4]);
+        write_file(tapes.join("player.header.riv.zstd"), &[]);
+        write_file(tapes.join("nested/skipped.riv"), &[5]);
+        let dict = write_file(dir.path().join("player.dict"), b"dictionary");
+
+        let mut writer = ArchiveWriter::new();
+        writer.add_folder("tapes", &tapes).unwrap();
+        writer.add_file("dictionaries/player.dict", &dict);
+        let path = dir.path().join("out.vcr");
+        writer.write(&path).unwrap();
+
+        let archive = Archive::open(&path).unwrap();
+        assert_eq!(
+            archive.entries(),
+            vec![
+                ("dictionaries/player.dict", 10),
+                ("tapes/player.header.riv.zstd", 0),
+                ("tapes/player.riv", 4),
+            ]
+        );
+        assert_eq!(
+            &archive.entry("tapes/player.riv").unwrap()[..],
+            &[1, 2, 3, 4]
+        );
+        assert_eq!(
+            &archive.entry("dictionaries/player.dict").unwrap()[..],
+            b"dictionary"
+        );
+        assert!(archive.get("tapes/nested/skipped.riv").is_none());
+        assert!(matches!(
+            archive.entry("tapes/team.riv"),
+            Err(VCRError::MissingArchiveEntry(_))
+        ));
+
+        let folder: Vec<String> = archive
+            .folder("tapes")
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(folder, vec!["player.header.riv.zstd", "player.riv"]);
+
+        let unpacked = dir.path().join("unpacked");
+        archive.unpack(&unpacked).unwrap();
+        assert_eq!(
+            fs::read(unpacked.join("tapes/player.riv")).unwrap(),
+            [1, 2, 3, 4]
+        );
+        assert_eq!(
+            fs::read(unpacked.join("dictionaries/player.dict")).unwrap(),
+            b"dictionary"
+        );
+    }
+
+    #[test]
+    fn entries_have_to_fit_in_the_file() {
+        let dir = TempDir::new().unwrap();
+        let header_len = 9 + 2 + 11 + 16;
+
+        let path = archive_with_entry(&dir, header_len, 4);
+        assert_eq!(
+            &Archive::open(&path).unwrap().entry("tapes/a.riv").unwrap()[..],
+            b"data"
+        );
+
+        for (offset, length) in [(header_len, 5), (u64::MAX - 1, 10), (4, u64::MAX)] {
+            let path = archive_with_entry(&dir, offset, length);
+            assert!(matches!(
+                Archive::open(&path),
+                Err(VCRError::InvalidArchive)
+            ));
+        }
+    }
+
+    #[test]
+    fn long_names_are_rejected() {
+        let dir = TempDir::new().unwrap();
+        let file = write_file(dir.path().join("a.riv"), &[1]);
+
+        let mut writer = ArchiveWriter::new();
+        writer.add_file(&"a".repeat(u16::MAX as usize + 1), &file);
+        assert!(writer.write(dir.path().join("out.vcr")).is_err());
+    }
+}
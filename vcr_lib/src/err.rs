@@ -17,6 +17,10 @@ pub enum VCRError {
     InvalidOpCode,
     #[error("data not indexed during tapes build")]
     IndexMissing,
+    #[error("not a valid .vcr archive")]
+    InvalidArchive,
+    #[error("archive entry {0} not found")]
+    MissingArchiveEntry(String),
     #[error(transparent)]
     MsgPackEncError(#[from] rmp_serde::encode::Error),
     #[error(transparent)]
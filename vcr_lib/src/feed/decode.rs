@@ -1,7 +1,7 @@
 use super::*;
+use crate::archive::{Archive, MappedSlice};
 use crate::{VCRError, VCRResult};
 use chrono::{DateTime, TimeZone, Utc};
-use memmap2::{Mmap, MmapOptions};
 use moka::sync::Cache;
 use rayon::prelude::*;
 use serde_json::Value as JSONValue;
@@ -53,7 +53,7 @@ pub struct FeedDatabase {
     offset_table: Vec<(DateTime<Utc>, (u32, u16))>,
     meta_index: MetaIndex,
     event_index: EventIndex,
-    reader: Mmap,
+    reader: MappedSlice,
     dictionary: DecoderDictionary<'static>,
     cache: Cache<u32, FeedEvent>,
 }
@@ -68,11 +68,48 @@ impl FeedDatabase {
         cache_size: usize,
     ) -> VCRResult<FeedDatabase> {
         let id_file = File::open(id_table_path)?;
-        let meta_idx: MetaIndex = rmp_serde::from_read(id_file)?;
-
         let idx_file = File::open(idx_file_path)?;
-        let idx_r = BufReader::new(idx_file);
-        let mut idx_decoder = zstd::Decoder::new(idx_r)?;
+        let position_index_file = File::open(position_index_path)?;
+
+        let mut dictionary_file = File::open(dict_file_path)?;
+        let mut dictionary: Vec<u8> = Vec::new();
+        dictionary_file.read_to_end(&mut dictionary)?;
+
+        let main_file = File::open(db_file_path)?;
+
+        FeedDatabase::from_parts(
+            BufReader::new(position_index_file),
+            MappedSlice::from_file(&main_file)?,
+            &dictionary,
+            id_file,
+            BufReader::new(idx_file),
+            cache_size,
+        )
+    }
+
+    /// Reads the feed stored in the `feed/` folder of a .vcr archive, using `dictionaries/feed.dict`.
+    pub fn from_archive(archive: &Archive, cache_size: usize) -> VCRResult<FeedDatabase> {
+        FeedDatabase::from_parts(
+            &archive.entry("feed/feed.fp")?[..],
+            archive.entry("feed/feed.riv")?,
+            &archive.entry("dictionaries/feed.dict")?,
+            &archive.entry("feed/id_lookup.bin")?[..],
+            &archive.entry("feed/tag_indexes.fp")?[..],
+            cache_size,
+        )
+    }
+
+    pub fn from_parts<P: Read, I: Read, T: Read>(
+        position_index: P,
+        reader: MappedSlice,
+        dictionary: &[u8],
+        id_table: I,
+        tag_index: T,
+        cache_size: usize,
+    ) -> VCRResult<FeedDatabase> {
+        let meta_idx: MetaIndex = rmp_serde::from_read(id_table)?;
+
+        let mut idx_decoder = zstd::Decoder::new(tag_index)?;
 
         let game_index = {
             let mut idx: HashMap<u16, Vec<(u32, (u32, u16))>> = HashMap::new();
@@ -219,24 +256,15 @@ impl FeedDatabase {
             game_index,
         };
 
-        let position_index_file = File::open(position_index_path)?;
-        let position_index_reader = BufReader::new(position_index_file);
-        let position_index_decompressor = zstd::stream::Decoder::new(position_index_reader)?;
+        let position_index_decompressor = zstd::stream::Decoder::new(position_index)?;
         let offset_table = make_offset_table(position_index_decompressor);
 
-        let mut dictionary_file = File::open(dict_file_path)?;
-        let mut dictionary: Vec<u8> = Vec::new();
-        dictionary_file.read_to_end(&mut dictionary)?;
-
-        let main_file = File::open(db_file_path)?;
-        let main_file_reader = unsafe { MmapOptions::new().map(&main_file)? };
-
         Ok(FeedDatabase {
             offset_table,
-            reader: main_file_reader,
+            reader,
             event_index: event_idx,
             meta_index: meta_idx,
-            dictionary: DecoderDictionary::copy(&dictionary),
+            dictionary: DecoderDictionary::copy(dictionary),
             cache: Cache::new(cache_size),
         })
     }
@@ -6,6 +6,7 @@ pub use decode::*;
 pub use desc::*;
 pub use event::*;
 
+use crate::utils::serialize_sorted;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use uuid::Uuid;
@@ -18,11 +19,17 @@ pub enum TagType {
 
 #[derive(Serialize, Deserialize, Debug, Default)]
 pub struct MetaIndex {
+    #[serde(serialize_with = "serialize_sorted")]
     pub player_tags: HashMap<u16, Uuid>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub game_tags: HashMap<u16, Uuid>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub team_tags: HashMap<u8, Uuid>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub reverse_player_tags: HashMap<Uuid, u16>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub reverse_game_tags: HashMap<Uuid, u16>,
+    #[serde(serialize_with = "serialize_sorted")]
     pub reverse_team_tags: HashMap<Uuid, u8>,
 }
 
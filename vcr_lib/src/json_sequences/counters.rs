@@ -0,0 +1,884 @@
+use crate::archive::MappedSlice;
+use crate::{hash_entities, read_u32, read_u8, ChronV2EndpointKind, InternalPaging};
+use crate::{ChroniclerEntity, VCRError, VCRResult};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use integer_encoding::{VarIntReader, VarIntWriter};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, value::RawValue, Map, Value as JSONValue};
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{BufReader, Read, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+/// Headers from revision 2 on start with these bytes, then the revision number.
+/// Revision 1 headers start with the length of their id table instead, which can't get anywhere near this.
+/// (the bytes are from back when tributes were the only tape in this format)
+pub const COUNTERS_MAGIC: [u8; 4] = *b"VTRB";
+pub const COUNTERS_REVISION: u8 = 2;
+
+type IdTable = HashMap<u32, (Uuid, u8)>;
+type RecordTable = Vec<(u32, u32, u32)>;
+
+/// Revision 1: 12 bit indices with the kind (0 or 1) in the top bit, and u16 record lengths.
+fn read_header_v1<R: Read>(ids_len: u32, mut reader: R) -> VCRResult<(IdTable, RecordTable)> {
+    let mut ids = HashMap::new();
+    let mut bytes: Vec<u8> = vec![0; ids_len as usize];
+    reader.read_exact(&mut bytes)?;
+
+    while !bytes.is_empty() {
+        let uuid = Uuid::from_slice(&bytes.drain(..16).collect::<Vec<u8>>()).unwrap();
+        let id_bytes =
+            u16::from_be_bytes(bytes.drain(..2).collect::<Vec<u8>>().try_into().unwrap());
+        let kind = ((id_bytes >> 15) & 0xFFF) as u8;
+        let idx = id_bytes & 0xFFF;
+
+        ids.insert(idx as u32, (uuid, kind));
+    }
+
+    let mut times = Vec::new();
+
+    loop {
+        let mut bytes: [u8; 10] = [0; 10];
+        if reader.read_exact(&mut bytes).is_err() {
+            break;
+        }
+
+        times.push((
+            u32::from_be_bytes(bytes[0..4].try_into().unwrap()),
+            u32::from_be_bytes(bytes[4..8].try_into().unwrap()),
+            u16::from_be_bytes(bytes[8..10].try_into().unwrap()) as u32,
+        ));
+    }
+
+    Ok((ids, times))
+}
+
+/// Revision 2: a varint count of ids, each one stored as its UUID, a kind byte and a varint index,
+/// then every record's time and offset with a varint length.
+fn read_header_v2<R: Read>(mut reader: R) -> VCRResult<(IdTable, RecordTable)> {
+    let count = reader.read_varint::<u32>()?;
+    let mut ids = HashMap::with_capacity(count as usize);
+
+    for _ in 0..count {
+        let mut uuid: [u8; 16] = [0; 16];
+        reader.read_exact(&mut uuid)?;
+        let kind = read_u8!(reader);
+        let idx = reader.read_varint::<u32>()?;
+
+        ids.insert(idx, (Uuid::from_bytes(uuid), kind));
+    }
+
+    let mut times = Vec::new();
+
+    loop {
+        let mut bytes: [u8; 8] = [0; 8];
+        if reader.read_exact(&mut bytes).is_err() {
+            break;
+        }
+
+        times.push((
+            u32::from_be_bytes(bytes[0..4].try_into().unwrap()),
+            u32::from_be_bytes(bytes[4..8].try_into().unwrap()),
+            reader.read_varint::<u32>()?,
+        ));
+    }
+
+    Ok((ids, times))
+}
+
+/// Writes a counter map header in the current revision.
+/// `ids` are (id, index, kind), `times` are the (time, start, length) of every record.
+pub fn write_counters_header<W: Write>(
+    mut writer: W,
+    ids: &[(Uuid, u32, u8)],
+    times: &[(u32, u32, u32)],
+) -> VCRResult<()> {
+    writer.write_all(&COUNTERS_MAGIC)?;
+    writer.write_all(&[COUNTERS_REVISION])?;
+
+    writer.write_varint(ids.len() as u32)?;
+    for (id, idx, kind) in ids {
+        writer.write_all(id.as_bytes())?;
+        writer.write_all(&[*kind])?;
+        writer.write_varint(*idx)?;
+    }
+
+    for (time, start, length) in times {
+        writer.write_all(&time.to_be_bytes())?;
+        writer.write_all(&start.to_be_bytes())?;
+        writer.write_varint(*length)?;
+    }
+
+    Ok(())
+}
+
+/// A kind of key in a counter map, like players or teams on the tributes leaderboard.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CounterKind {
+    pub name: String,
+    /// field the id is stored under in list entries, e.g. `playerId`
+    pub id_field: String,
+    /// field the count is stored under in list entries, e.g. `peanuts`
+    pub value_field: String,
+    /// key this kind's list goes under in grouped versions, e.g. `players`
+    pub group: String,
+}
+
+/// How a version's counts are laid out in JSON.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "layout", rename_all = "lowercase")]
+pub enum CounterShape {
+    /// A list of `{id_field: id, value_field: count}` objects for one kind, highest count first.
+    List { kind: u8 },
+    /// An object with a list like the above for every kind, under the kind's `group`. Groups for the kinds in `order` come first, in that order, then the rest in kind order.
+    Grouped {
+        #[serde(default)]
+        order: Vec<u8>,
+    },
+    /// An object from ids of one kind to their count.
+    Map { kind: u8 },
+}
+
+/// A shape, and the UNIX timestamp it's used from.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ShapeFrom {
+    #[serde(default)]
+    pub from: u32,
+    #[serde(flatten)]
+    pub shape: CounterShape,
+}
+
+/// Everything that makes a counter map tape into a particular entity type: its key kinds and the JSON shapes its versions take.
+/// Stored next to the tape as `{type}.counters.riv`, unless it's one of the `builtin` ones.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct CounterMapConfig {
+    pub kinds: Vec<CounterKind>,
+    /// sorted by `from`
+    pub shapes: Vec<ShapeFrom>,
+}
+
+impl CounterMapConfig {
+    /// Configs for the types that were encoded before tapes came with one.
+    pub fn builtin(e_type: &str) -> Option<CounterMapConfig> {
+        match e_type {
+            "tributes" => Some(crate::tributes_config()),
+            _ => None,
+        }
+    }
+
+    fn kind(&self, kind: u8) -> VCRResult<&CounterKind> {
+        self.kinds
+            .get(kind as usize)
+            .ok_or(VCRError::InvalidPatchData)
+    }
+
+    /// The shape versions take at a UNIX timestamp.
+    pub fn shape_at(&self, time: u32) -> VCRResult<&CounterShape> {
+        let index = self.shapes.partition_point(|s| s.from <= time);
+        self.shapes
+            .get(index.saturating_sub(1))
+            .map(|s| &s.shape)
+            .ok_or(VCRError::InvalidPatchData)
+    }
+
+    fn list(&self, vals: &HashMap<(Uuid, u8), u64>, kind: u8) -> VCRResult<JSONValue> {
+        let CounterKind {
+            id_field,
+            value_field,
+            ..
+        } = self.kind(kind)?;
+
+        let mut counts: Vec<(&Uuid, &u64)> = vals
+            .iter()
+            .filter(|((_, k), _)| *k == kind)
+            .map(|((id, _), v)| (id, v))
+            .collect();
+        // ties go by id, so the same counts always come out in the same order
+        counts.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+
+        Ok(JSONValue::Array(
+            counts
+                .into_iter()
+                .map(|(id, v)| json!({ id_field: id.to_string(), value_field: v }))
+                .collect(),
+        ))
+    }
+
+    /// Lays out counts the way versions from `time` look.
+    pub fn to_json(&self, vals: &HashMap<(Uuid, u8), u64>, time: u32) -> VCRResult<JSONValue> {
+        Ok(match self.shape_at(time)? {
+            CounterShape::List { kind } => self.list(vals, *kind)?,
+            CounterShape::Grouped { order } => {
+                let rest = (0..self.kinds.len() as u8).filter(|kind| !order.contains(kind));
+                let mut groups = Map::new();
+                for kind in order.iter().copied().chain(rest) {
+                    groups.insert(self.kind(kind)?.group.to_owned(), self.list(vals, kind)?);
+                }
+                JSONValue::Object(groups)
+            }
+            CounterShape::Map { kind } => {
+                let mut counts: Vec<(&Uuid, &u64)> = vals
+                    .iter()
+                    .filter(|((_, k), _)| k == kind)
+                    .map(|((id, _), v)| (id, v))
+                    .collect();
+                counts.sort();
+
+                JSONValue::Object(
+                    counts
+                        .into_iter()
+                        .map(|(id, v)| (id.to_string(), json!(v)))
+                        .collect(),
+                )
+            }
+        })
+    }
+
+    fn read_list(&self, list: &JSONValue, kind: u8) -> VCRResult<Vec<(Uuid, u8, u64)>> {
+        let CounterKind {
+            id_field,
+            value_field,
+            ..
+        } = self.kind(kind)?;
+
+        list.as_array()
+            .ok_or(VCRError::InvalidPatchData)?
+            .iter()
+            .map(|entry| {
+                let id = entry[id_field]
+                    .as_str()
+                    .and_then(|id| Uuid::parse_str(id).ok())
+                    .ok_or(VCRError::InvalidPatchData)?;
+                let count = entry[value_field]
+                    .as_u64()
+                    .ok_or(VCRError::InvalidPatchData)?;
+                Ok((id, kind, count))
+            })
+            .collect()
+    }
+
+    /// The reverse of `to_json`: reads the (id, kind, count) of every key out of a version from `time`.
+    pub fn read_counts(&self, data: &JSONValue, time: u32) -> VCRResult<Vec<(Uuid, u8, u64)>> {
+        match self.shape_at(time)? {
+            CounterShape::List { kind } => self.read_list(data, *kind),
+            CounterShape::Grouped { .. } => {
+                let mut counts = Vec::new();
+                for (kind, CounterKind { group, .. }) in self.kinds.iter().enumerate() {
+                    if let Some(list) = data.get(group) {
+                        counts.extend(self.read_list(list, kind as u8)?);
+                    }
+                }
+                Ok(counts)
+            }
+            CounterShape::Map { kind } => data
+                .as_object()
+                .ok_or(VCRError::InvalidPatchData)?
+                .iter()
+                .map(|(id, count)| {
+                    Ok((
+                        Uuid::parse_str(id).map_err(|_| VCRError::InvalidPatchData)?,
+                        *kind,
+                        count.as_u64().ok_or(VCRError::InvalidPatchData)?,
+                    ))
+                })
+                .collect(),
+        }
+    }
+}
+
+/// One change recorded in a counter map tape.
+#[derive(Serialize, Debug)]
+#[serde(tag = "op", rename_all = "camelCase")]
+pub enum CounterChange {
+    Set { id: Uuid, kind: u8, value: u64 },
+    Remove { id: Uuid, kind: u8 },
+}
+
+/// A key's count from `valid_from` on, or `None` while it's not in the map.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CounterValue {
+    pub valid_from: DateTime<Utc>,
+    pub value: Option<u64>,
+}
+
+/// Where a key sits among the keys of its kind, counting from 1, highest count first.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct CounterRank {
+    pub id: Uuid,
+    pub kind: u8,
+    pub value: u64,
+    pub rank: usize,
+    pub out_of: usize,
+    pub valid_from: DateTime<Utc>,
+}
+
+fn timestamp(time: u32) -> DateTime<Utc> {
+    DateTime::<Utc>::from_utc(NaiveDateTime::from_timestamp(time as i64, 0), Utc)
+}
+
+/// A map of ids to integer counts over time, stored as the changes between versions (with removals) and the occasional keyframe.
+pub struct CounterMapDatabase {
+    times: RecordTable,  // (time, start, length)
+    ids: IdTable,        // (id_number, (id, kind))
+    keyframes: Vec<u32>, // indices of records that store every count, not just changes
+    /// id:id_number, for looking ids up without going through every one. ids only ever have one kind.
+    indices: HashMap<Uuid, u32>,
+    config: CounterMapConfig,
+    reader: MappedSlice,
+}
+
+impl CounterMapDatabase {
+    pub fn from_files<P: AsRef<Path> + std::fmt::Debug>(
+        header_path: P,
+        db_path: P,
+        config: CounterMapConfig,
+    ) -> VCRResult<CounterMapDatabase> {
+        let header_f = File::open(header_path)?;
+        let main_file = File::open(db_path)?;
+
+        CounterMapDatabase::from_parts(
+            BufReader::new(header_f),
+            MappedSlice::from_file_populated(&main_file)?,
+            config,
+        )
+    }
+
+    pub fn from_parts<R: Read>(
+        mut header_reader: R,
+        reader: MappedSlice,
+        config: CounterMapConfig,
+    ) -> VCRResult<CounterMapDatabase> {
+        let first = read_u32!(header_reader).to_be_bytes();
+
+        let (ids, times) = if first == COUNTERS_MAGIC {
+            match read_u8!(header_reader) {
+                2 => read_header_v2(header_reader)?,
+                _ => return Err(VCRError::InvalidPatchData),
+            }
+        } else {
+            read_header_v1(u32::from_be_bytes(first), header_reader)?
+        };
+
+        if ids
+            .values()
+            .any(|(_, kind)| *kind as usize >= config.kinds.len())
+        {
+            return Err(VCRError::InvalidPatchData);
+        }
+
+        let indices = ids.iter().map(|(idx, (id, _))| (*id, *idx)).collect();
+
+        Ok(CounterMapDatabase {
+            times,
+            ids,
+            indices,
+            keyframes: Vec::new(),
+            config,
+            reader,
+        })
+    }
+
+    /// Loads the list of keyframe records, stored in `{type}.keyframes.riv`. Without it, every lookup replays from the first record.
+    pub fn set_keyframes<R: Read>(&mut self, mut reader: R) -> VCRResult<()> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        self.keyframes = bytes
+            .chunks_exact(4)
+            .map(|c| u32::from_be_bytes(c.try_into().unwrap()))
+            .collect();
+        Ok(())
+    }
+
+    pub fn config(&self) -> &CounterMapConfig {
+        &self.config
+    }
+
+    /// Every key in the tape, by the index records refer to them with.
+    pub fn ids(&self) -> &HashMap<u32, (Uuid, u8)> {
+        &self.ids
+    }
+
+    /// The time, offset and length of every record in the tape.
+    pub fn times(&self) -> &[(u32, u32, u32)] {
+        &self.times
+    }
+
+    /// Indices (in `times`) of the records that store every count instead of only what changed.
+    pub fn keyframes(&self) -> &[u32] {
+        &self.keyframes
+    }
+
+    /// Decodes the changes in a single record, by its index in `times`.
+    pub fn record_changes(&self, index: usize) -> VCRResult<Vec<CounterChange>> {
+        let (_, start, length) = self.times.get(index).ok_or(VCRError::InvalidPatchData)?;
+        let mut bytes = &self.reader[*start as usize..(*start as usize + *length as usize)];
+        let mut changes = Vec::new();
+
+        while !bytes.is_empty() {
+            let idx = bytes.read_varint::<u32>()?;
+            if idx == 0 {
+                let len = bytes.read_varint::<u32>()?;
+                for _ in 0..len {
+                    let ridx = bytes.read_varint::<u32>()?;
+                    let (id, kind) = *self.ids.get(&ridx).ok_or(VCRError::InvalidPatchData)?;
+                    changes.push(CounterChange::Remove { id, kind });
+                }
+            } else {
+                let value = bytes.read_varint::<u64>()?;
+                let (id, kind) = *self.ids.get(&idx).ok_or(VCRError::InvalidPatchData)?;
+                changes.push(CounterChange::Set { id, kind, value });
+            }
+        }
+
+        Ok(changes)
+    }
+
+    /// Applies the changes in the record at `index` on top of `vals`.
+    fn apply_record(&self, vals: &mut HashMap<(Uuid, u8), u64>, index: usize) -> VCRResult<()> {
+        let (_, start, length) = self.times[index];
+        let mut bytes = &self.reader[start as usize..(start as usize + length as usize)];
+
+        while !bytes.is_empty() {
+            let idx = bytes.read_varint::<u32>()?;
+            if idx == 0 {
+                let len = bytes.read_varint::<u32>()?;
+                for _ in 0..len {
+                    let ridx = bytes.read_varint::<u32>()?;
+                    vals.remove(self.ids.get(&ridx).ok_or(VCRError::InvalidPatchData)?);
+                }
+            } else {
+                let val = bytes.read_varint::<u64>()?;
+                vals.insert(*self.ids.get(&idx).ok_or(VCRError::InvalidPatchData)?, val);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts as of the record at `index`, replayed from the closest keyframe at or before it.
+    fn state_at(&self, index: usize) -> VCRResult<HashMap<(Uuid, u8), u64>> {
+        let keyframes_before = self.keyframes.partition_point(|k| *k as usize <= index);
+        let start = if keyframes_before == 0 {
+            0
+        } else {
+            self.keyframes[keyframes_before - 1] as usize
+        };
+
+        let mut vals = HashMap::new();
+        for i in start..=index {
+            self.apply_record(&mut vals, i)?;
+        }
+
+        Ok(vals)
+    }
+
+    /// Shapes counts into a version, following the config's shape for `time`.
+    fn version(
+        &self,
+        vals: &HashMap<(Uuid, u8), u64>,
+        time: u32,
+    ) -> VCRResult<ChroniclerEntity<JSONValue>> {
+        Ok(ChroniclerEntity {
+            data: self.config.to_json(vals, time)?,
+            entity_id: Uuid::nil().to_string(),
+            valid_from: timestamp(time),
+            valid_to: None,
+            hash: String::new(),
+        })
+    }
+
+    pub fn get_versions(
+        &self,
+        before: u32,
+        after: u32,
+    ) -> VCRResult<Vec<ChroniclerEntity<JSONValue>>> {
+        let first = self.times.partition_point(|(time, _, _)| *time <= after);
+        let end = self.times.partition_point(|(time, _, _)| *time <= before);
+        if first >= end {
+            return Ok(Vec::new());
+        }
+
+        let mut vals = if first == 0 {
+            HashMap::new()
+        } else {
+            self.state_at(first - 1)?
+        };
+
+        let mut versions: Vec<ChroniclerEntity<JSONValue>> = Vec::with_capacity(end - first);
+        for index in first..end {
+            self.apply_record(&mut vals, index)?;
+            versions.push(self.version(&vals, self.times[index].0)?);
+        }
+
+        Ok(versions)
+    }
+
+    /// The record that stands for the map at `at`: the first one at or after it, or the last one if there's none.
+    fn record_at(&self, at: u32) -> Option<usize> {
+        if self.times.is_empty() {
+            None
+        } else {
+            Some(
+                self.times
+                    .partition_point(|(time, _, _)| *time < at)
+                    .min(self.times.len() - 1),
+            )
+        }
+    }
+
+    fn id_index(&self, id: &Uuid) -> Option<u32> {
+        self.indices.get(id).copied()
+    }
+
+    /// What the record at `index` does to a single key: `Some(Some(count))` if it sets its count, `Some(None)` if it removes it and `None` if it leaves it alone.
+    fn record_change_for(&self, index: usize, target: u32) -> VCRResult<Option<Option<u64>>> {
+        let (_, start, length) = self.times[index];
+        let mut bytes = &self.reader[start as usize..(start as usize + length as usize)];
+        let mut change = None;
+
+        while !bytes.is_empty() {
+            let idx = bytes.read_varint::<u32>()?;
+            if idx == 0 {
+                let len = bytes.read_varint::<u32>()?;
+                for _ in 0..len {
+                    if bytes.read_varint::<u32>()? == target {
+                        change = Some(None);
+                    }
+                }
+            } else {
+                let val = bytes.read_varint::<u64>()?;
+                if idx == target {
+                    change = Some(Some(val));
+                }
+            }
+        }
+
+        Ok(change)
+    }
+
+    /// Gets the first version at or after `at` (or the last one, if there's none).
+    pub fn get_entity(&self, at: u32) -> VCRResult<ChroniclerEntity<JSONValue>> {
+        match self.record_at(at) {
+            Some(index) => self.version(&self.state_at(index)?, self.times[index].0),
+            None => self.version(&HashMap::new(), 0),
+        }
+    }
+
+    /// The count of a single key between two UNIX timestamps: its count at the first record in range, then every time it changes.
+    pub fn history(&self, id: &Uuid, after: u32, before: u32) -> VCRResult<Vec<CounterValue>> {
+        let idx = self.id_index(id).ok_or(VCRError::EntityNotFound)?;
+        let first = self.times.partition_point(|(time, _, _)| *time <= after);
+        let end = self.times.partition_point(|(time, _, _)| *time <= before);
+        if first >= end {
+            return Ok(Vec::new());
+        }
+
+        let mut last = if first == 0 {
+            None
+        } else {
+            self.state_at(first - 1)?.get(&self.ids[&idx]).copied()
+        };
+
+        let mut history = Vec::new();
+        for index in first..end {
+            let value = self.record_change_for(index, idx)?.unwrap_or(last);
+
+            if index == first || value != last {
+                history.push(CounterValue {
+                    valid_from: timestamp(self.times[index].0),
+                    value,
+                });
+            }
+
+            last = value;
+        }
+
+        Ok(history)
+    }
+
+    /// A key's rank among keys of the same kind at a UNIX timestamp, counting from 1. Ties share a rank. `None` if it wasn't in the map then, or `at` is before the first record.
+    pub fn rank(&self, id: &Uuid, at: u32) -> VCRResult<Option<CounterRank>> {
+        let (uuid, kind) = self.ids[&self.id_index(id).ok_or(VCRError::EntityNotFound)?];
+        // the map as it stood at `at`: the last record at or before it
+        let index = match self
+            .times
+            .partition_point(|(time, _, _)| *time <= at)
+            .checked_sub(1)
+        {
+            Some(index) => index,
+            None => return Ok(None),
+        };
+
+        let vals = self.state_at(index)?;
+        let value = match vals.get(&(uuid, kind)) {
+            Some(value) => *value,
+            None => return Ok(None),
+        };
+
+        let (ahead, out_of) = vals
+            .iter()
+            .filter(|((_, k), _)| *k == kind)
+            .fold((0, 0), |(ahead, out_of), (_, v)| {
+                (ahead + (*v > value) as usize, out_of + 1)
+            });
+
+        Ok(Some(CounterRank {
+            id: uuid,
+            kind,
+            value,
+            rank: ahead + 1,
+            out_of,
+            valid_from: timestamp(self.times[index].0),
+        }))
+    }
+
+    pub fn fetch_page(
+        &self,
+        page: &mut InternalPaging<Box<RawValue>>,
+        count: usize,
+    ) -> VCRResult<Vec<ChroniclerEntity<Box<RawValue>>>> {
+        page.remaining_ids = vec![];
+
+        if page.remaining_data.len() < count {
+            page.remaining_data = match page.kind {
+                ChronV2EndpointKind::Versions(before, after) => {
+                    self.get_versions(before, after).and_then(hash_entities)?
+                }
+                ChronV2EndpointKind::Entities(at) => {
+                    self.get_entity(at).and_then(|v| hash_entities(vec![v]))?
+                }
+            };
+        }
+
+        Ok(page
+            .remaining_data
+            .drain(..std::cmp::min(count, page.remaining_data.len()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_sequences::testing::{counter_tape, CounterVersion};
+    use crate::{tributes_config, TRIBUTE_PLAYER, TRIBUTE_TEAM};
+
+    fn counts(kind: u8, n: u128) -> HashMap<(Uuid, u8), u64> {
+        (0..n).map(|i| ((Uuid::from_u128(i), kind), 10)).collect()
+    }
+
+    #[test]
+    fn grouped_tributes_have_teams_first() {
+        let mut vals = counts(TRIBUTE_PLAYER, 3);
+        vals.insert((Uuid::from_u128(100), TRIBUTE_TEAM), 5);
+
+        let config = tributes_config();
+        let json = config.to_json(&vals, u32::MAX).unwrap();
+        let groups: Vec<&String> = json.as_object().unwrap().keys().collect();
+        assert_eq!(groups, vec!["teams", "players"]);
+    }
+
+    #[test]
+    fn same_counts_come_out_the_same() {
+        let mut config = tributes_config();
+        config.shapes.push(ShapeFrom {
+            from: u32::MAX,
+            shape: CounterShape::Map {
+                kind: TRIBUTE_PLAYER,
+            },
+        });
+
+        // every hashmap gets its own hash seed, so these iterate in different orders
+        let a = counts(TRIBUTE_PLAYER, 50);
+        let b = counts(TRIBUTE_PLAYER, 50);
+
+        for time in [0, u32::MAX] {
+            let a = serde_json::to_string(&config.to_json(&a, time).unwrap()).unwrap();
+            let b = serde_json::to_string(&config.to_json(&b, time).unwrap()).unwrap();
+            assert_eq!(a, b);
+        }
+    }
+
+    /// Players with counts that go up over time, where every third version leaves one of them out.
+    fn leaderboard(n: u32) -> Vec<CounterVersion> {
+        (0..n)
+            .map(|v| {
+                let counts = (1..=6u128)
+                    .filter(|id| v % 3 != 0 || *id != (v as u128 % 6) + 1)
+                    .map(|id| (id, TRIBUTE_PLAYER, (v as u64 / id as u64) * id as u64))
+                    .collect();
+                (1000 + v * 10, counts)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keyframes_replay_the_same_as_the_start() {
+        let versions = leaderboard(40);
+        let (plain, _) = counter_tape(&versions, 4, tributes_config());
+        let (mut keyed, keyframes) = counter_tape(&versions, 4, tributes_config());
+        keyed.set_keyframes(&keyframes[..]).unwrap();
+        assert_eq!(keyed.keyframes().len(), 10);
+
+        // every record, the times in between, and before and after the tape
+        for at in (990..1410).step_by(5) {
+            assert_eq!(
+                plain.get_entity(at).unwrap().data,
+                keyed.get_entity(at).unwrap().data,
+                "entity at {}",
+                at
+            );
+        }
+
+        let data = |versions: Vec<ChroniclerEntity<JSONValue>>| -> Vec<(DateTime<Utc>, JSONValue)> {
+            versions
+                .into_iter()
+                .map(|v| (v.valid_from, v.data))
+                .collect()
+        };
+        for (after, before) in [(0, u32::MAX), (1015, 1095), (1030, 1040), (1200, 1390)] {
+            let expected = data(plain.get_versions(before, after).unwrap());
+            assert!(!expected.is_empty());
+            assert_eq!(expected, data(keyed.get_versions(before, after).unwrap()));
+        }
+    }
+
+    #[test]
+    fn history_only_has_changes() {
+        let versions = leaderboard(12);
+        let (db, _) = counter_tape(&versions, 4, tributes_config());
+
+        // player 4's count goes up every fourth version, and it's left out of versions 3 and 9
+        let history: Vec<(i64, Option<u64>)> = db
+            .history(&Uuid::from_u128(4), 0, u32::MAX)
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.valid_from.timestamp(), v.value))
+            .collect();
+        assert_eq!(
+            history,
+            vec![
+                (1000, Some(0)),
+                (1030, None),
+                (1040, Some(4)),
+                (1080, Some(8)),
+                (1090, None),
+                (1100, Some(8)),
+            ]
+        );
+
+        // the first entry in range is always there, even when it's not a change
+        let history: Vec<(i64, Option<u64>)> = db
+            .history(&Uuid::from_u128(4), 1045, 1095)
+            .unwrap()
+            .into_iter()
+            .map(|v| (v.valid_from.timestamp(), v.value))
+            .collect();
+        assert_eq!(
+            history,
+            vec![(1050, Some(4)), (1080, Some(8)), (1090, None)]
+        );
+
+        assert!(db
+            .history(&Uuid::from_u128(4), 1200, 1300)
+            .unwrap()
+            .is_empty());
+        assert!(matches!(
+            db.history(&Uuid::from_u128(99), 0, u32::MAX),
+            Err(VCRError::EntityNotFound)
+        ));
+    }
+
+    #[test]
+    fn rank_is_from_the_last_record_before() {
+        let versions = vec![
+            (1000, vec![(1, TRIBUTE_PLAYER, 5), (2, TRIBUTE_PLAYER, 10)]),
+            (
+                1010,
+                vec![
+                    (1, TRIBUTE_PLAYER, 20),
+                    (2, TRIBUTE_PLAYER, 10),
+                    (3, TRIBUTE_PLAYER, 10),
+                ],
+            ),
+            (1020, vec![(2, TRIBUTE_PLAYER, 10)]),
+        ];
+        let (db, _) = counter_tape(&versions, 100, tributes_config());
+        let rank = |id: u128, at: u32| {
+            db.rank(&Uuid::from_u128(id), at)
+                .unwrap()
+                .map(|r| (r.rank, r.out_of, r.value, r.valid_from.timestamp()))
+        };
+
+        assert_eq!(rank(1, 999), None);
+        assert_eq!(rank(1, 1000), Some((2, 2, 5, 1000)));
+        // still the first record, even though the next one is closer
+        assert_eq!(rank(1, 1009), Some((2, 2, 5, 1000)));
+        assert_eq!(rank(1, 1010), Some((1, 3, 20, 1010)));
+        // ties share a rank
+        assert_eq!(rank(2, 1015), Some((2, 3, 10, 1010)));
+        assert_eq!(rank(3, 1015), Some((2, 3, 10, 1010)));
+        assert_eq!(rank(1, 1020), None);
+        assert_eq!(rank(2, u32::MAX), Some((1, 1, 10, 1020)));
+    }
+
+    fn header_ids(n: u32) -> Vec<(Uuid, u32, u8)> {
+        (1..=n)
+            .map(|idx| (Uuid::from_u128(idx as u128 * 7919), idx, (idx % 2) as u8))
+            .collect()
+    }
+
+    fn check_header((ids, times): (IdTable, RecordTable), expected: &[(Uuid, u32, u8)]) {
+        assert_eq!(ids.len(), expected.len());
+        for (id, idx, kind) in expected {
+            assert_eq!(ids[idx], (*id, *kind));
+        }
+        assert_eq!(times, vec![(1000, 0, 12), (1010, 12, 300), (1020, 312, 1)]);
+    }
+
+    #[test]
+    fn v1_headers_read_back() {
+        let ids = header_ids(10);
+        let mut id_bytes: Vec<u8> = Vec::new();
+        for (id, idx, kind) in &ids {
+            id_bytes.extend(id.as_bytes());
+            id_bytes.extend(((*kind as u16) << 15 | *idx as u16).to_be_bytes());
+        }
+
+        let mut header: Vec<u8> = Vec::new();
+        header.extend(&id_bytes);
+        for (time, start, length) in [(1000u32, 0u32, 12u16), (1010, 12, 300), (1020, 312, 1)] {
+            header.extend(time.to_be_bytes());
+            header.extend(start.to_be_bytes());
+            header.extend(length.to_be_bytes());
+        }
+
+        check_header(
+            read_header_v1(id_bytes.len() as u32, &header[..]).unwrap(),
+            &ids,
+        );
+    }
+
+    #[test]
+    fn v2_headers_read_back() {
+        // past what the 12 bit indices of revision 1 could hold
+        for n in [10, 5000] {
+            let ids = header_ids(n);
+            let mut header: Vec<u8> = Vec::new();
+            write_counters_header(
+                &mut header,
+                &ids,
+                &[(1000, 0, 12), (1010, 12, 300), (1020, 312, 1)],
+            )
+            .unwrap();
+
+            assert_eq!(header[..4], COUNTERS_MAGIC);
+            assert_eq!(header[4], COUNTERS_REVISION);
+            check_header(read_header_v2(&header[5..]).unwrap(), &ids);
+        }
+    }
+}
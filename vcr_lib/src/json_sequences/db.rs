@@ -1,13 +1,14 @@
+use super::op_layout;
+use crate::archive::{Archive, MappedSlice, TapeSource};
+use crate::utils::group_tape_files;
 use crate::*;
 
 use std::collections::HashMap;
-use std::ffi::OsStr;
 use std::fs::{read_dir, File};
 use std::io::{self, prelude::*};
 use std::path::{Path, PathBuf};
 
 use chrono::{DateTime, NaiveDateTime, Utc};
-use memmap2::{Mmap, MmapOptions};
 use serde_json::{json, value::RawValue, Value as JSONValue};
 use zstd::dict::DecoderDictionary;
 
@@ -22,16 +23,6 @@ use json_patch::{
     PatchOperation, PatchOperation::*, RemoveOperation, ReplaceOperation, TestOperation,
 };
 
-fn clamp(input: u32, min: u32, max: u32) -> u32 {
-    if input < min {
-        min
-    } else if input > max {
-        max
-    } else {
-        input
-    }
-}
-
 /// Serializes the data inside a list of ChroniclerEntity's, adding a SHA224 hash of the JSON string to the object.
 pub fn hash_entities(
     e: Vec<ChroniclerEntity<JSONValue>>,
@@ -57,9 +48,10 @@ pub fn hash_entities(
 
 /// A handle over a memory map of a VCR .riv file, a mapping of entity ids to positions in the file, a possible ZSTD dictionary, and a cache.
 pub struct Database {
-    reader: Mmap,
+    reader: MappedSlice,
     entities: HashMap<String, EntityData>,
     dictionary: Option<DecoderDictionary<'static>>,
+    values: Vec<JSONValue>,
     entity_cache: Cache<(String, usize), ChroniclerEntity<JSONValue>>,
 }
 
@@ -71,51 +63,99 @@ impl Database {
         cache_size: usize,
     ) -> VCRResult<Database> {
         let entities_lookup_f = File::open(entities_lookup_path)?;
-        let decompressor = zstd::stream::Decoder::new(entities_lookup_f)?;
         let db_f = File::open(db_path)?;
 
-        let compression_dict = if let Some(dict_f_path) = dict_path {
+        let dict = if let Some(dict_f_path) = dict_path {
             let mut dict_f = File::open(dict_f_path)?;
             let mut dict = Vec::new();
             dict_f.read_to_end(&mut dict)?;
-            Some(DecoderDictionary::copy(&dict))
+            Some(dict)
         } else {
             None
         };
 
+        Database::from_parts(
+            entities_lookup_f,
+            MappedSlice::from_file(&db_f)?,
+            dict.as_deref(),
+            cache_size,
+        )
+    }
+
+    /// Builds a database out of a (zstd compressed) header, the main tape data and an optional dictionary, wherever they were read from.
+    pub fn from_parts<R: Read>(
+        entities_lookup: R,
+        reader: MappedSlice,
+        dict: Option<&[u8]>,
+        cache_size: usize,
+    ) -> VCRResult<Database> {
+        let decompressor = zstd::stream::Decoder::new(entities_lookup)?;
+
         Ok(Database {
-            reader: unsafe { MmapOptions::new().map(&db_f)? },
+            reader,
             entities: decode_header(decompressor)?,
-            dictionary: compression_dict,
+            dictionary: dict.map(DecoderDictionary::copy),
+            values: Vec::new(),
             entity_cache: Cache::new(cache_size),
         })
     }
 
+    /// Loads the (zstd compressed) table of interned values that patches can refer to, stored in `{type}.values.riv.zstd`.
+    pub fn set_value_table<R: Read>(&mut self, reader: R) -> VCRResult<()> {
+        let decompressor = zstd::stream::Decoder::new(reader)?;
+        self.values = rmp_serde::from_read(decompressor)?;
+        Ok(())
+    }
+
     /// Gets the last version of an entity, which is serialized as a standalone MSGPack object to avoid the patch system.
     pub fn get_last_version(&self, entity: &str) -> VCRResult<(u32, JSONValue)> {
         let metadata = &self.entities.get(entity).ok_or(VCRError::EntityNotFound)?;
         let (time, patch_start, patch_len) =
             *metadata.patches.last().ok_or(VCRError::InvalidPatchData)?;
-        let e_bytes: Vec<u8> = if let Some(compress_dict) = &self.dictionary {
-            let mut decoder = zstd::stream::Decoder::with_prepared_dictionary(
-                &self.reader[(patch_start as usize)..(patch_start + patch_len) as usize],
-                compress_dict,
-            )?;
-            let mut res = Vec::with_capacity((patch_len) as usize * 10);
-            decoder.read_to_end(&mut res)?;
-            res
-        } else {
-            let mut decoder = zstd::stream::Decoder::new(
-                &self.reader[(patch_start as usize)..(patch_start + patch_len) as usize],
-            )?;
-            let mut res = Vec::with_capacity((patch_len) as usize * 10);
-            decoder.read_to_end(&mut res)?;
-            res
-        };
+        let e_bytes = self.decompress(patch_start, patch_len)?;
 
         Ok((time, rmp_serde::from_read_ref(&e_bytes)?))
     }
 
+    fn decompress(&self, patch_start: u32, patch_len: u32) -> VCRResult<Vec<u8>> {
+        let compressed = &self.reader[(patch_start as usize)..(patch_start + patch_len) as usize];
+        let mut res = Vec::with_capacity((patch_len) as usize * 10);
+        if let Some(compress_dict) = &self.dictionary {
+            zstd::stream::Decoder::with_prepared_dictionary(compressed, compress_dict)?
+                .read_to_end(&mut res)?;
+        } else {
+            zstd::stream::Decoder::new(compressed)?.read_to_end(&mut res)?;
+        }
+
+        Ok(res)
+    }
+
+    /// Ids of every entity in the tape.
+    pub fn entity_ids(&self) -> impl Iterator<Item = &String> {
+        self.entities.keys()
+    }
+
+    /// The header of an entity: its patch table, checkpoint interval, path map and base value.
+    pub fn entity_metadata(&self, entity: &str) -> Option<&EntityData> {
+        self.entities.get(entity)
+    }
+
+    /// Values that patches in this tape can refer to by id instead of storing inline.
+    pub fn value_table(&self) -> &[JSONValue] {
+        &self.values
+    }
+
+    /// Decompresses one of an entity's patches, by its index in the patch table, without decoding it.
+    /// The last patch of every entity is its final version, stored as standalone MSGPack instead of patch bytecode.
+    pub fn raw_patch(&self, entity: &str, index: usize) -> VCRResult<Vec<u8>> {
+        let metadata = &self.entities.get(entity).ok_or(VCRError::EntityNotFound)?;
+        let (_, patch_start, patch_len) = *metadata
+            .patches
+            .get(index)
+            .ok_or(VCRError::InvalidPatchData)?;
+        self.decompress(patch_start, patch_len)
+    }
+
     /// Gets the JSONPatch'es associated with a specific entity until a certain time.
     pub fn get_entity_data(
         &self,
@@ -156,102 +196,139 @@ impl Database {
         };
 
         for (time, patch_start, patch_len) in patch_list {
-            let mut e_bytes: Vec<u8> = if let Some(compress_dict) = &self.dictionary {
-                let mut decoder = zstd::stream::Decoder::with_prepared_dictionary(
-                    &self.reader[(patch_start as usize)..(patch_start + patch_len) as usize],
-                    compress_dict,
-                )?;
-                let mut res = Vec::with_capacity((patch_len) as usize * 10);
-                decoder.read_to_end(&mut res)?;
-                res
-            } else {
-                let mut decoder = zstd::stream::Decoder::new(
-                    &self.reader[(patch_start as usize)..(patch_start + patch_len) as usize],
-                )?;
-                let mut res = Vec::with_capacity((patch_len) as usize * 10);
-                decoder.read_to_end(&mut res)?;
-                res
-            };
+            let e_bytes = self.decompress(patch_start, patch_len)?;
+            patches.push((time, self.decode_patch(metadata, e_bytes)?));
+        }
+
+        patches.sort_by_key(|x| x.0);
+        Ok(patches)
+    }
 
-            let mut result = Patch::Normal(JSONPatch(vec![]));
-            let mut operations: Vec<PatchOperation> = Vec::new();
+    /// Decodes the bytecode of a (decompressed) patch into JSON Patch operations.
+    fn decode_patch(&self, metadata: &EntityData, mut e_bytes: Vec<u8>) -> VCRResult<Patch> {
+        let mut result = Patch::Normal(JSONPatch(vec![]));
+        let mut operations: Vec<PatchOperation> = Vec::new();
 
-            while e_bytes.len() > 1 {
-                let op_code = e_bytes.remove(0);
+        while e_bytes.len() > 1 {
+            let raw_op_code = e_bytes.remove(0);
+            let op_code = raw_op_code & !VALUE_REF_FLAG;
 
-                if op_code == 6 {
-                    let value_length = u16::from_be_bytes([e_bytes.remove(0), e_bytes.remove(0)]);
-                    let val_bytes: Vec<u8> = e_bytes.drain(..value_length as usize).collect();
-                    result = Patch::ReplaceRoot(rmp_serde::from_read_ref(&val_bytes)?);
-                    break;
-                } else {
-                    let paths = if op_code == 3 || op_code == 4 {
-                        vec![
-                            metadata
-                                .path_map
-                                .get(&u16::from_be_bytes([e_bytes.remove(0), e_bytes.remove(0)]))
-                                .ok_or(VCRError::PathResolutionError)?,
-                            metadata
-                                .path_map
-                                .get(&u16::from_be_bytes([e_bytes.remove(0), e_bytes.remove(0)]))
-                                .ok_or(VCRError::PathResolutionError)?,
-                        ]
-                    } else {
-                        vec![metadata
+            if op_code == 6 {
+                let value_length = u16::from_be_bytes([e_bytes.remove(0), e_bytes.remove(0)]);
+                let val_bytes: Vec<u8> = e_bytes.drain(..value_length as usize).collect();
+                result = Patch::ReplaceRoot(rmp_serde::from_read_ref(&val_bytes)?);
+                break;
+            } else {
+                let paths = if op_code == 3 || op_code == 4 {
+                    vec![
+                        metadata
                             .path_map
                             .get(&u16::from_be_bytes([e_bytes.remove(0), e_bytes.remove(0)]))
-                            .ok_or(VCRError::PathResolutionError)?]
-                    };
-
-                    let value_length = u16::from_be_bytes([e_bytes.remove(0), e_bytes.remove(0)]);
-
-                    let value: Option<JSONValue> = if value_length > 0 {
-                        let val_bytes: Vec<u8> = e_bytes.drain(..value_length as usize).collect();
-                        Some(rmp_serde::from_read_ref(&val_bytes)?)
-                    } else {
-                        None
-                    };
-
-                    operations.push(match op_code {
-                        0 => Add(AddOperation {
-                            path: paths[0].to_string(),
-                            value: value.ok_or(VCRError::InvalidPatchData)?,
-                        }),
-                        1 => Remove(RemoveOperation {
-                            path: paths[0].to_string(),
-                        }),
-                        2 => Replace(ReplaceOperation {
-                            path: paths[0].to_string(),
-                            value: value.ok_or(VCRError::InvalidPatchData)?,
-                        }),
-                        3 => Move(MoveOperation {
-                            path: paths[0].to_string(),
-                            from: paths[1].to_string(),
-                        }),
-                        4 => Copy(CopyOperation {
-                            path: paths[0].to_string(),
-                            from: paths[1].to_string(),
-                        }),
-                        5 => Test(TestOperation {
-                            path: paths[0].to_string(),
-                            value: value.ok_or(VCRError::InvalidPatchData)?,
-                        }),
-                        _ => return Err(VCRError::InvalidOpCode),
-                    });
+                            .ok_or(VCRError::PathResolutionError)?,
+                        metadata
+                            .path_map
+                            .get(&u16::from_be_bytes([e_bytes.remove(0), e_bytes.remove(0)]))
+                            .ok_or(VCRError::PathResolutionError)?,
+                    ]
+                } else {
+                    vec![metadata
+                        .path_map
+                        .get(&u16::from_be_bytes([e_bytes.remove(0), e_bytes.remove(0)]))
+                        .ok_or(VCRError::PathResolutionError)?]
+                };
+
+                // array ops (7-9) store indices into the array after its path, u16::MAX meaning "append"
+                let index_count = op_layout(op_code).1;
+                let mut indices: Vec<String> = Vec::with_capacity(index_count);
+                for _ in 0..index_count {
+                    indices.push(
+                        match u16::from_be_bytes([e_bytes.remove(0), e_bytes.remove(0)]) {
+                            u16::MAX => format!("{}/-", paths[0]),
+                            idx => format!("{}/{}", paths[0], idx),
+                        },
+                    );
                 }
+
+                let value_length = u16::from_be_bytes([e_bytes.remove(0), e_bytes.remove(0)]);
+
+                let value: Option<JSONValue> = if raw_op_code & VALUE_REF_FLAG != 0 {
+                    // value_length is actually an id into the value table
+                    Some(
+                        self.values
+                            .get(value_length as usize)
+                            .cloned()
+                            .ok_or(VCRError::InvalidPatchData)?,
+                    )
+                } else if value_length > 0 {
+                    let val_bytes: Vec<u8> = e_bytes.drain(..value_length as usize).collect();
+                    Some(rmp_serde::from_read_ref(&val_bytes)?)
+                } else {
+                    None
+                };
+
+                operations.push(match op_code {
+                    0 => Add(AddOperation {
+                        path: paths[0].to_string(),
+                        value: value.ok_or(VCRError::InvalidPatchData)?,
+                    }),
+                    1 => Remove(RemoveOperation {
+                        path: paths[0].to_string(),
+                    }),
+                    2 => Replace(ReplaceOperation {
+                        path: paths[0].to_string(),
+                        value: value.ok_or(VCRError::InvalidPatchData)?,
+                    }),
+                    3 => Move(MoveOperation {
+                        path: paths[0].to_string(),
+                        from: paths[1].to_string(),
+                    }),
+                    4 => Copy(CopyOperation {
+                        path: paths[0].to_string(),
+                        from: paths[1].to_string(),
+                    }),
+                    5 => Test(TestOperation {
+                        path: paths[0].to_string(),
+                        value: value.ok_or(VCRError::InvalidPatchData)?,
+                    }),
+                    7 => Add(AddOperation {
+                        path: indices[0].clone(),
+                        value: value.ok_or(VCRError::InvalidPatchData)?,
+                    }),
+                    8 => Remove(RemoveOperation {
+                        path: indices[0].clone(),
+                    }),
+                    9 => Move(MoveOperation {
+                        path: indices[1].clone(),
+                        from: indices[0].clone(),
+                    }),
+                    _ => return Err(VCRError::InvalidOpCode),
+                });
             }
+        }
 
-            patches.push((
+        Ok(match result {
+            Patch::Normal(_) => Patch::Normal(JSONPatch(operations)),
+            Patch::ReplaceRoot(v) => Patch::ReplaceRoot(v),
+        })
+    }
+
+    /// Gets a single decoded patch of an entity, by its index in the patch table. The last patch, which is the entity's final version, comes back as a `ReplaceRoot`.
+    pub fn get_patch(&self, entity: &str, index: usize) -> VCRResult<(u32, Patch)> {
+        let metadata = &self.entities.get(entity).ok_or(VCRError::EntityNotFound)?;
+        let (time, _, _) = *metadata
+            .patches
+            .get(index)
+            .ok_or(VCRError::InvalidPatchData)?;
+        let e_bytes = self.raw_patch(entity, index)?;
+
+        if index + 1 == metadata.patches.len() {
+            Ok((
                 time,
-                match result {
-                    Patch::Normal(_) => Patch::Normal(JSONPatch(operations)),
-                    Patch::ReplaceRoot(v) => Patch::ReplaceRoot(v),
-                },
-            ));
+                Patch::ReplaceRoot(rmp_serde::from_read_ref(&e_bytes)?),
+            ))
+        } else {
+            Ok((time, self.decode_patch(metadata, e_bytes)?))
         }
-
-        patches.sort_by_key(|x| x.0);
-        Ok(patches)
     }
 
     /// Gets all versions of an entity between two UNIX timestamps.
@@ -343,6 +420,21 @@ impl Database {
         .0
     }
 
+    /// When the version of an entity that's current at `at` became current (0 if it's from before the first patch) and when it stops being (`None` for the last one), as UNIX timestamps. Two times with the same span see the same version of the entity.
+    pub fn version_span(&self, entity: &str, at: u32) -> VCRResult<(u32, Option<u32>)> {
+        let patches = &self
+            .entities
+            .get(entity)
+            .ok_or(VCRError::EntityNotFound)?
+            .patches;
+        let applied = patches.partition_point(|(t, _, _)| *t <= at);
+
+        Ok((
+            applied.checked_sub(1).map_or(0, |i| patches[i].0),
+            patches.get(applied).map(|(t, _, _)| *t),
+        ))
+    }
+
     /// Gets an entity at a certain point in time.
     pub fn get_entity(&self, entity: &str, at: u32) -> VCRResult<ChroniclerEntity<JSONValue>> {
         let patch_idx = match self.entities[entity]
@@ -378,18 +470,30 @@ impl Database {
             .base
             .clone();
 
-        let mut patch_data_idx = 0;
+        let checkpoint_every = self.entities[entity].checkpoint_every;
+        let mut from_previous = false;
 
-        if self.entities[entity].checkpoint_every != u16::MAX && patch_idx > 0 {
+        // with the version right before this one cached, only this one's patch needs applying.
+        // checkpoints are patches from the base value, so they can't go on top of anything else.
+        if checkpoint_every != u16::MAX
+            && patch_idx > 0
+            && patch_idx % checkpoint_every as usize != 0
+        {
             if let Some(val) = self.entity_cache.get(&(entity.to_owned(), patch_idx - 1)) {
                 entity_value = val.data;
-                patch_data_idx = patch_idx - 1;
+                from_previous = true;
             }
         }
 
+        let patches = if from_previous {
+            self.get_entity_data(entity, at, false, patch_idx)?
+        } else {
+            self.get_entity_data(entity, at, true, 0)?
+        };
+
         let mut last_time = 0;
 
-        for (time, patch) in self.get_entity_data(entity, at, true, patch_data_idx)? {
+        for (time, patch) in patches {
             match patch {
                 Patch::ReplaceRoot(v) => {
                     entity_value = v.clone();
@@ -535,11 +639,14 @@ impl Database {
     }
 }
 
-/// A handle over a group of databases, including a special database for Tributes and an index over game times.
+/// A handle over a group of databases, including counter maps (like Tributes) and an index over game times.
 pub struct MultiDatabase {
     pub dbs: HashMap<String, Database>, // entity_type:db
     pub game_index: HashMap<GameDate, Vec<(String, Option<DateTime<Utc>>, Option<DateTime<Utc>>)>>,
-    pub tributes: TributesDatabase,
+    pub game_filter_index: HashMap<String, GameIndexEntry>, // game_id:entry
+    pub game_summary_index: HashMap<String, GameSummary>,   // game_id:summary
+    pub counters: HashMap<String, CounterMapDatabase>,      // entity_type:db
+    pub search_index: Option<SearchIndex>,
 }
 
 impl MultiDatabase {
@@ -549,93 +656,141 @@ impl MultiDatabase {
         dicts: HashMap<String, P>,
         cache_size: usize,
     ) -> VCRResult<MultiDatabase> {
-        let (mut header_paths, mut db_paths): (Vec<PathBuf>, Vec<PathBuf>) = read_dir(folder)?
+        let files: Vec<(String, PathBuf)> = read_dir(folder)?
             .map(|res| res.map(|e| e.path()))
             .collect::<Result<Vec<PathBuf>, io::Error>>()?
             .into_iter()
             .filter(|path| path.is_file())
-            .partition(|path| {
-                if let Some(name) = path.file_name() {
-                    name.to_str().unwrap().contains(".header.riv")
-                } else {
-                    false
-                }
-            });
+            .filter_map(|path| {
+                let name = path.file_name()?.to_str()?.to_owned();
+                Some((name, path))
+            })
+            .collect();
 
-        let game_index: HashMap<
-            GameDate,
-            Vec<(String, Option<DateTime<Utc>>, Option<DateTime<Utc>>)>,
-        > = if let Some(dates_pos) = db_paths.iter().position(|x| {
-            x.file_name()
-                .unwrap_or(OsStr::new(""))
-                .to_str()
-                .unwrap()
-                .contains(".dates.riv.")
-        }) {
-            let game_index_path = db_paths.remove(dates_pos);
-            let game_index_f = File::open(game_index_path)?;
-            let decompressor = zstd::stream::Decoder::new(game_index_f)?;
+        let dicts: HashMap<String, PathBuf> = dicts
+            .into_iter()
+            .map(|(e_type, path)| (e_type, path.as_ref().to_path_buf()))
+            .collect();
 
-            rmp_serde::from_read(decompressor)?
-        } else {
-            HashMap::new()
-        };
+        MultiDatabase::from_sources(files, &dicts, cache_size)
+    }
 
-        header_paths.sort();
-        db_paths.sort();
-        let entries: Vec<(String, PathBuf, PathBuf)> = header_paths
+    /// Reads every tape in the `tapes/` folder of a .vcr archive, using the dictionaries in its `dictionaries/` folder.
+    pub fn from_archive(archive: &Archive, cache_size: usize) -> VCRResult<MultiDatabase> {
+        let dicts: HashMap<String, MappedSlice> = archive
+            .folder("dictionaries")
             .into_iter()
-            .zip(db_paths.into_iter())
-            .map(|(header, main)| {
-                let e_type = main
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .split_once('.')
-                    .unwrap()
-                    .0
-                    .to_owned();
-                (e_type, header, main)
+            .filter_map(|(name, dict)| {
+                name.strip_suffix(".dict")
+                    .map(|e_type| (e_type.to_owned(), dict))
             })
             .collect();
 
+        MultiDatabase::from_sources(archive.folder("tapes"), &dicts, cache_size)
+    }
+
+    /// Reads every tape in a list of (file name, file), with zstd dictionaries by entity type.
+    pub fn from_sources<S: TapeSource>(
+        files: Vec<(String, S)>,
+        dicts: &HashMap<String, S>,
+        cache_size: usize,
+    ) -> VCRResult<MultiDatabase> {
         let mut dbs: HashMap<String, Database> = HashMap::new();
-        let mut tributes: Option<TributesDatabase> = None;
+        let mut game_index: HashMap<
+            GameDate,
+            Vec<(String, Option<DateTime<Utc>>, Option<DateTime<Utc>>)>,
+        > = HashMap::new();
+        let mut game_filter_index: HashMap<String, GameIndexEntry> = HashMap::new();
+        let mut game_summary_index: HashMap<String, GameSummary> = HashMap::new();
+        let mut counters: HashMap<String, CounterMapDatabase> = HashMap::new();
+        let mut search_index: Option<SearchIndex> = None;
+
+        for (e_type, tape) in group_tape_files(files) {
+            if let Some(dates) = tape.tables.get("dates") {
+                let decompressor = zstd::stream::Decoder::new(dates.open()?)?;
+                game_index = rmp_serde::from_read(decompressor)?;
+            }
+
+            if let Some(index) = tape.tables.get("index") {
+                let decompressor = zstd::stream::Decoder::new(index.open()?)?;
+                game_filter_index = rmp_serde::from_read(decompressor)?;
+            }
+
+            if let Some(summaries) = tape.tables.get("summaries") {
+                let decompressor = zstd::stream::Decoder::new(summaries.open()?)?;
+                game_summary_index = rmp_serde::from_read(decompressor)?;
+            }
+
+            if let Some(search) = tape.tables.get("search") {
+                let decompressor = zstd::stream::Decoder::new(search.open()?)?;
+                search_index = Some(rmp_serde::from_read(decompressor)?);
+            }
+
+            let (lookup, main) = match (tape.header, tape.main) {
+                (Some(header), Some(main)) => (header, main),
+                _ => continue,
+            };
+
+            let counter_config = match tape.tables.get("counters") {
+                Some(config) => Some(serde_json::from_reader(config.open()?)?),
+                None => CounterMapConfig::builtin(&e_type),
+            };
+
+            if let Some(config) = counter_config {
+                let mut db =
+                    CounterMapDatabase::from_parts(lookup.open()?, main.map_populated()?, config)?;
+
+                if let Some(keyframes) = tape.tables.get("keyframes") {
+                    db.set_keyframes(keyframes.open()?)?;
+                }
 
-        for (e_type, lookup_file, main_file) in entries {
-            if e_type == "tributes" {
-                tributes = Some(TributesDatabase::from_files(lookup_file, main_file)?);
+                counters.insert(e_type, db);
             } else {
-                dbs.insert(
-                    e_type.clone(),
-                    Database::from_files(
-                        lookup_file,
-                        main_file,
-                        dicts
-                            .get(&e_type)
-                            .map(|p| PathBuf::from(p.as_ref().as_os_str())),
-                        cache_size,
-                    )?,
-                );
+                let dict = match dicts.get(&e_type) {
+                    Some(dict) => {
+                        let mut bytes = Vec::new();
+                        dict.open()?.read_to_end(&mut bytes)?;
+                        Some(bytes)
+                    }
+                    None => None,
+                };
+
+                let mut db =
+                    Database::from_parts(lookup.open()?, main.map()?, dict.as_deref(), cache_size)?;
+
+                if let Some(values) = tape.tables.get("values") {
+                    db.set_value_table(values.open()?)?;
+                }
+
+                dbs.insert(e_type, db);
             }
         }
 
         Ok(MultiDatabase {
             dbs,
             game_index,
-            tributes: tributes.unwrap(),
+            game_filter_index,
+            game_summary_index,
+            counters,
+            search_index,
         })
     }
 
+    /// The counter map tape for an entity type, like `tributes`.
+    pub fn counter_map(&self, e_type: &str) -> VCRResult<&CounterMapDatabase> {
+        self.counters
+            .get(e_type)
+            .ok_or(VCRError::EntityTypeNotFound)
+    }
+
     pub fn get_entity(
         &self,
         e_type: &str,
         entity: &str,
         at: u32,
     ) -> VCRResult<ChroniclerEntity<JSONValue>> {
-        if e_type == "tributes" {
-            self.tributes.get_entity(at)
+        if let Some(counters) = self.counters.get(e_type) {
+            counters.get_entity(at)
         } else {
             self.dbs
                 .get(e_type)
@@ -644,6 +799,19 @@ impl MultiDatabase {
         }
     }
 
+    /// See `Database::version_span`.
+    pub fn version_span(
+        &self,
+        e_type: &str,
+        entity: &str,
+        at: u32,
+    ) -> VCRResult<(u32, Option<u32>)> {
+        self.dbs
+            .get(e_type)
+            .ok_or(VCRError::EntityTypeNotFound)?
+            .version_span(entity, at)
+    }
+
     pub fn get_entity_versions(
         &self,
         e_type: &str,
@@ -651,8 +819,8 @@ impl MultiDatabase {
         before: u32,
         after: u32,
     ) -> VCRResult<Vec<ChroniclerEntity<JSONValue>>> {
-        if e_type == "tributes" {
-            self.tributes.get_versions(before, after)
+        if let Some(counters) = self.counters.get(e_type) {
+            counters.get_versions(before, after)
         } else {
             self.dbs
                 .get(e_type)
@@ -667,8 +835,8 @@ impl MultiDatabase {
         entities: Vec<String>,
         at: u32,
     ) -> VCRResult<Vec<ChroniclerEntity<JSONValue>>> {
-        if e_type == "tributes" {
-            self.tributes.get_entity(at).map(|v| vec![v])
+        if let Some(counters) = self.counters.get(e_type) {
+            counters.get_entity(at).map(|v| vec![v])
         } else {
             self.dbs
                 .get(e_type)
@@ -684,8 +852,8 @@ impl MultiDatabase {
         before: u32,
         after: u32,
     ) -> VCRResult<Vec<ChroniclerEntity<JSONValue>>> {
-        if e_type == "tributes" {
-            self.tributes.get_versions(before, after)
+        if let Some(counters) = self.counters.get(e_type) {
+            counters.get_versions(before, after)
         } else {
             self.dbs
                 .get(e_type)
@@ -718,7 +886,7 @@ impl MultiDatabase {
     }
 
     pub fn all_ids(&self, e_type: &str) -> VCRResult<Vec<String>> {
-        if e_type == "tributes" {
+        if self.counters.contains_key(e_type) {
             Ok(vec!["00000000-0000-0000-0000-000000000000".to_owned()])
         } else {
             let db = self.dbs.get(e_type).ok_or(VCRError::EntityTypeNotFound)?;
@@ -733,8 +901,8 @@ impl MultiDatabase {
         count: usize,
         order: Order,
     ) -> VCRResult<Vec<ChroniclerEntity<Box<RawValue>>>> {
-        if e_type == "tributes" {
-            self.tributes.fetch_page(page, count)
+        if let Some(counters) = self.counters.get(e_type) {
+            counters.fetch_page(page, count)
         } else {
             self.dbs
                 .get(e_type)
@@ -780,38 +948,133 @@ impl MultiDatabase {
     }
 
     pub fn games_for_bets(&self, date: &GameDate, at: u32) -> VCRResult<Vec<ChronV1Game>> {
+        let mut results = Vec::new();
+        for (game, start_time, end_time) in self.game_index.get(date).unwrap_or(&Vec::new()) {
+            results.push(ChronV1Game {
+                game_id: game.to_owned(),
+                start_time: *start_time,
+                end_time: *end_time,
+                data: self.game_for_bets(game, at)?,
+            });
+        }
+
+        Ok(results)
+    }
+
+    /// A game as of `at`, or as of the first version after it that has odds set.
+    pub(crate) fn game_for_bets(&self, game: &str, at: u32) -> VCRResult<JSONValue> {
         let db = self
             .dbs
             .get("game_updates")
             .ok_or(VCRError::EntityTypeNotFound)?;
-        let mut results = Vec::new();
         let json_zero = json!(0); // lol. lmao
-        for (game, start_time, end_time) in self.game_index.get(date).unwrap_or(&Vec::new()) {
-            let mut data = db.get_entity(game, at)?.data;
-            let mut time = at;
-
-            while data
-                .get("awayOdds")
+        let mut data = db.get_entity(game, at)?.data;
+        let mut time = at;
+
+        while data
+            .get("awayOdds")
+            .and_then(|v| if v == &json_zero { None } else { Some(()) })
+            .is_none()
+            && data
+                .get("homeOdds")
                 .and_then(|v| if v == &json_zero { None } else { Some(()) })
                 .is_none()
-                && data
-                    .get("homeOdds")
-                    .and_then(|v| if v == &json_zero { None } else { Some(()) })
-                    .is_none()
-            {
-                time = db.get_next_time(game, time);
-                data = db.get_entity(game, time)?.data;
+        {
+            time = db.get_next_time(game, time);
+            data = db.get_entity(game, time)?.data;
+        }
+
+        Ok(data)
+    }
+
+    /// Whether a game passes a `/games` filter. Uses the filter index when the game is in it, and decodes its last version otherwise.
+    pub fn game_matches(&self, id: &str, filter: &GameFilter) -> VCRResult<bool> {
+        if filter.is_empty() {
+            return Ok(true);
+        }
+
+        match self.game_filter_index.get(id) {
+            Some(entry) => Ok(filter.matches(entry)),
+            None => Ok(filter.matches(&GameIndexEntry::from_game(
+                &self.get_entity("game_updates", id, u32::MAX)?.data,
+            ))),
+        }
+    }
+
+    /// Summaries of every game that passes the filter, ordered by season and day. Games missing from the summary index get summarized from their last version.
+    pub fn game_summaries(&self, filter: &GameSummaryFilter) -> VCRResult<Vec<GameSummary>> {
+        let mut dates: Vec<&GameDate> = self
+            .game_index
+            .keys()
+            .filter(|date| {
+                filter.season.map_or(true, |s| s == date.season)
+                    && filter.day.map_or(true, |d| d == date.day)
+            })
+            .collect();
+        // GameDate sorts by day first
+        dates.sort_by_key(|date| (date.tournament, date.season, date.day));
+
+        let mut summaries = Vec::new();
+        for date in dates {
+            for (id, _, _) in &self.game_index[date] {
+                let summary = match self.game_summary_index.get(id) {
+                    Some(summary) => summary.clone(),
+                    None => GameSummary::from_game(
+                        id,
+                        &self.get_entity("game_updates", id, u32::MAX)?.data,
+                    ),
+                };
+
+                if filter.team.map_or(true, |team| summary.involves(&team)) {
+                    summaries.push(summary);
+                }
             }
+        }
 
-            results.push(ChronV1Game {
-                game_id: game.to_owned(),
-                start_time: *start_time,
-                end_time: *end_time,
-                data,
-            });
+        Ok(summaries)
+    }
+
+    /// Game updates whose text matches a search query (see `SearchQuery`), oldest first. Needs tapes built with a search index.
+    pub fn search_game_updates(
+        &self,
+        query: &str,
+        filter: &SearchFilter,
+    ) -> VCRResult<Vec<SearchHit>> {
+        let index = self.search_index.as_ref().ok_or(VCRError::IndexMissing)?;
+
+        let mut hits = index.search(&SearchQuery::parse(query), filter);
+        if !filter.game.is_empty() {
+            // games with several hits only need checking once
+            let mut matching: HashMap<String, bool> = HashMap::new();
+            let mut kept = Vec::with_capacity(hits.len());
+            for hit in hits {
+                let matches = match matching.get(&hit.game_id) {
+                    Some(matches) => *matches,
+                    None => {
+                        let matches = self.game_matches(&hit.game_id, &filter.game)?;
+                        matching.insert(hit.game_id.clone(), matches);
+                        matches
+                    }
+                };
+
+                if matches {
+                    kept.push(hit);
+                }
+            }
+            hits = kept;
         }
 
-        Ok(results)
+        Ok(hits)
+    }
+
+    /// A game's play-by-play, rebuilt from every one of its versions.
+    pub fn play_by_play(&self, game_id: &str) -> VCRResult<Vec<Play>> {
+        Ok(Play::from_versions(&self.get_entity_versions(
+            "game_updates",
+            game_id,
+            u32::MAX,
+            0,
+        )?))
     }
 
     pub fn games_with_date(&self, d: &GameDate) -> Vec<ChronV1Game> {
@@ -834,345 +1097,64 @@ impl MultiDatabase {
         }
     }
 
-    fn playoffs(&self, id: &str, round: Option<i64>, at: u32) -> VCRResult<JSONValue> {
-        let playoffs = self.get_entity("playoffs", id, at)?.data;
-        let round_number = round.unwrap_or_else(|| playoffs["round"].as_i64().unwrap());
-
-        let round_ids: Vec<String> = playoffs["rounds"]
-            .as_array()
-            .unwrap_or(&Vec::new())
-            .iter()
-            .map(|x| x.as_str().unwrap().to_owned())
-            .collect();
-        let all_rounds: Vec<JSONValue> = self
-            .get_entities("playoffround", round_ids, at)?
-            .into_iter()
-            .map(|t| t.data)
-            .filter(|t| t != &json!({}))
-            .collect();
-        let tomorrow_round: JSONValue = all_rounds
-            .iter()
-            .find(|r| r["roundNumber"] == playoffs["tomorrowRound"])
-            .cloned()
-            .unwrap_or(json!({}));
-        let round: JSONValue = all_rounds
-            .iter()
-            .find(|r| r["roundNumber"].as_i64().unwrap() == round_number)
-            .cloned()
-            .unwrap_or(json!({}));
-
-        let main_matchup_ids: Vec<String> = round["matchups"]
-            .as_array()
-            .unwrap_or(&Vec::new())
-            .iter()
-            .map(|x| x.as_str().unwrap().to_owned())
-            .collect();
-        let main_matchups: Vec<JSONValue> = self
-            .get_entities("playoffmatchup", main_matchup_ids, at)?
-            .into_iter()
-            .map(|t| t.data)
-            .filter(|t| t != &json!({}))
-            .collect();
-
-        let tomorrow_matchup_ids: Vec<String> = tomorrow_round["matchups"]
-            .as_array()
-            .unwrap_or(&Vec::new())
-            .iter()
-            .map(|x| x.as_str().unwrap().to_owned())
-            .collect();
-        let tomorrow_matchups: Vec<JSONValue> = self
-            .get_entities("playoffmatchup", tomorrow_matchup_ids, at)?
-            .into_iter()
-            .map(|t| t.data)
-            .filter(|t| t != &json!({}))
-            .collect();
-
-        let all_matchups_ids: Vec<String> = all_rounds
-            .iter()
-            .flat_map(|x| {
-                x["matchups"]
-                    .as_array()
-                    .unwrap_or(&Vec::new())
-                    .iter()
-                    .map(|x| x.as_str().unwrap().to_owned())
-                    .collect::<Vec<String>>()
-            })
-            .collect();
-
-        let all_matchups: Vec<JSONValue> = self
-            .get_entities("playoffmatchup", all_matchups_ids, at)?
-            .into_iter()
-            .map(|t| t.data)
-            .filter(|t| t != &json!({}))
-            .collect();
-
-        Ok(json!({
-            "round": round,
-            "matchups": main_matchups,
-            "playoffs": playoffs,
-            "allRounds": all_rounds,
-            "allMatchups": all_matchups,
-            "tomorrowRound": tomorrow_round,
-            "tomorrowMatchups": tomorrow_matchups
-        }))
-    }
-
+    /// The `streamData` frame as of `at`. Use a `StreamBuilder` for a run of frames, so entities that didn't change in between don't get fetched again.
     pub fn stream_data(&self, at: u32) -> VCRResult<JSONValue> {
-        let sim = self.get_entity("sim", "00000000-0000-0000-0000-000000000000", at)?;
-
-        let mut date = GameDate {
-            season: sim.data.get("season").unwrap().as_i64().unwrap() as i32,
-            day: sim.data.get("day").unwrap().as_i64().unwrap() as i32,
-            tournament: if sim.data.get("season") == Some(&json!(10))
-                && sim.data["day"].as_i64().unwrap() < 100
-                && sim.data.get("tournament").is_none()
-            {
-                Some(-1)
-            } else {
-                sim.data
-                    .get("tournament")
-                    .map(|x| x.as_i64().unwrap() as i32)
-            },
-        };
+        StreamBuilder::new(self).frame(at)
+    }
+}
 
-        if let Some(i) = date.tournament {
-            if i != -1 {
-                date.season = -1;
-            }
+#[cfg(test)]
+mod tests {
+    use crate::json_sequences::testing::{id, tape};
+    use serde_json::{json, Value as JSONValue};
+
+    /// Reads every version of an entity in order, each right after the one before it got cached, and checks them against cold reads.
+    fn read_through_cache(versions: Vec<(u32, JSONValue)>) {
+        let entity = id(1);
+        // a checkpoint every 4 versions, so reads go across checkpoints as well as between them
+        let cached = tape(vec![(entity.clone(), versions.clone())], 4, 100);
+
+        for (time, data) in &versions {
+            let cold = tape(vec![(entity.clone(), versions.clone())], 4, 100)
+                .get_entity(&entity, *time)
+                .unwrap();
+            let warm = cached.get_entity(&entity, *time).unwrap();
+
+            assert_eq!(warm.data, cold.data, "at {}", time);
+            assert_eq!(&warm.data, data, "at {}", time);
+            assert_eq!(warm.valid_from, cold.valid_from, "at {}", time);
         }
 
-        let schedule: JSONValue = if sim
-            .data
-            .get("phase")
-            .unwrap_or(&json!(-1))
-            .as_i64()
-            .unwrap()
-            == 14
-            && date.season == 22
-        {
-            json!([self
-                .get_entity("game_updates", "d162b23a-9832-4e78-8d78-5d131393fd61", at)?
-                .data])
-        } else {
-            self.games_by_date_and_time(&date, at)?
-                .into_iter()
-                .map(|g| g.data)
-                .collect()
-        };
-
-        date.day += 1;
-
-        let tomorrow_schedule: Vec<JSONValue> = self
-            .games_for_bets(&date, at)?
-            .into_iter()
-            .map(|g| g.data)
-            .filter(|g| g != &json!({}))
-            .collect();
-
-        let season = self
-            .all_entities("season", at)?
-            .into_iter()
-            .find(|s| s.data["seasonNumber"] == sim.data["season"])
-            .unwrap();
-
-        let standings =
-            self.get_entity("standings", season.data["standings"].as_str().unwrap(), at)?;
-
-        let mut leagues: Vec<JSONValue> = self
-            .all_entities("league", clamp(at, 1599169238, u32::MAX))?
-            .into_iter()
-            .map(|t| t.data)
-            .filter(|t| t != &json!({}))
-            .collect();
-
-        let subleague_ids: Vec<String> = leagues
-            .iter()
-            .flat_map(|x| {
-                x["subleagues"]
-                    .as_array()
-                    .unwrap_or(&Vec::new())
-                    .iter()
-                    .map(|x| x.as_str().unwrap().to_owned())
-                    .collect::<Vec<String>>()
-            })
-            .collect();
-
-        let tiebreaker_ids: Vec<String> = leagues
-            .iter()
-            .map(|x| {
-                x.get("tiebreakers")
-                    .unwrap_or(&json!(""))
-                    .as_str()
-                    .unwrap()
-                    .to_owned()
-            })
-            .collect();
-
-        let mut subleagues: Vec<JSONValue> = self
-            .get_entities("subleague", subleague_ids, clamp(at, 1599169238, u32::MAX))?
-            .into_iter()
-            .map(|s| s.data)
-            .filter(|s| s != &json!({}))
-            .collect();
-
-        let division_ids: Vec<String> = subleagues
-            .iter()
-            .flat_map(|x| {
-                x["divisions"]
-                    .as_array()
-                    .unwrap_or(&Vec::new())
-                    .iter()
-                    .map(|x| x.as_str().unwrap().to_owned())
-                    .collect::<Vec<String>>()
-            })
-            .collect();
-
-        let mut divisions: Vec<JSONValue> = self
-            .get_entities("division", division_ids, clamp(at, 1599169238, u32::MAX))?
-            .into_iter()
-            .map(|d| d.data)
-            .filter(|d| d != &json!({}))
-            .collect();
-
-        let mut tiebreakers: Vec<JSONValue> = self
-            .get_entities(
-                "tiebreakers",
-                tiebreaker_ids,
-                clamp(at, 1599760290, u32::MAX),
-            )?
-            .into_iter()
-            .map(|t| t.data)
-            .filter(|t| t != &json!({}))
-            .collect();
-
-        if at < 1598224980 {
-            for d in &mut divisions {
-                if let Some(id) = d.get("id") {
-                    d["_id"] = id.clone();
-                }
-            }
-
-            for d in &mut subleagues {
-                if let Some(id) = d.get("id") {
-                    d["_id"] = id.clone();
-                }
-            }
-
-            for d in &mut leagues {
-                if let Some(id) = d.get("id") {
-                    d["_id"] = id.clone();
-                }
-            }
-
-            for d in &mut tiebreakers {
-                if let Some(id) = d.get("id") {
-                    d["_id"] = id.clone();
-                }
-            }
+        // and again, now that every version is cached
+        for (time, data) in versions.iter().rev() {
+            assert_eq!(&cached.get_entity(&entity, *time).unwrap().data, data);
         }
+    }
 
-        let teams: Vec<JSONValue> = self
-            .all_entities("team", at)?
-            .into_iter()
-            .map(|t| t.data)
-            .filter(|t| t != &json!({}))
-            .collect();
-
-        let fights: Vec<JSONValue> = self
-            .all_entities("bossfight", at)?
-            .into_iter()
-            .map(|b| b.data)
-            .filter(|b| b != &json!({}) && b["homeHp"] != json!("0") && b["awayHp"] != json!("0"))
-            .collect();
-
-        let stadiums: Vec<JSONValue> = self
-            .all_entities("stadium", at)?
-            .into_iter()
-            .map(|s| s.data)
-            .filter(|s| s != &json!({}))
-            .collect();
-
-        let temporal = self.get_entity("temporal", "00000000-0000-0000-0000-000000000000", at)?;
-
-        let sunsun = self.get_entity("sunsun", "00000000-0000-0000-0000-000000000000", at)?;
-
-        let communitychest = self.get_entity(
-            "communitychestprogress",
-            "00000000-0000-0000-0000-000000000000",
-            at,
-        )?;
-
-        let tournament = if let Some(tourn_idx) = date.tournament {
-            if tourn_idx > -1 {
-                self.all_entities("tournament", at)?
-                    .into_iter()
-                    .last()
-                    .map_or(json!({}), |x| x.data)
-            } else {
-                json!({})
-            }
-        } else {
-            json!({})
-        };
-
-        let (playoff_key, playoffs): (&str, JSONValue) = if tournament != json!({}) {
-            (
-                "postseason",
-                self.playoffs(
-                    tournament["playoffs"].as_str().unwrap(),
-                    sim.data.get("tournamentRound").map(|i| i.as_i64().unwrap()),
-                    at,
-                )?,
-            )
-        } else if let Some(playoff_ids) = sim.data["playoffs"].as_array() {
-            let mut playoffs: Vec<JSONValue> = Vec::new();
-            for id in playoff_ids {
-                playoffs.push(self.playoffs(id.as_str().unwrap(), None, at)?);
-            }
-            ("postseasons", json!(playoffs))
-        } else if let Some(playoff_id) = sim.data["playoffs"].as_str() {
-            (
-                "postseason",
-                self.playoffs(
-                    playoff_id,
-                    sim.data.get("playOffRound").map(|i| i.as_i64().unwrap()),
-                    at,
-                )?,
-            )
-        } else {
-            ("postseason", json!({}))
-        };
+    #[test]
+    fn consecutive_object_reads_through_the_cache() {
+        read_through_cache(
+            (1..=20)
+                .map(|i| {
+                    let mut rotated: Vec<u32> = (0..8).collect();
+                    rotated.rotate_left(i as usize % 8);
+                    let appended: Vec<u32> = (0..i).collect();
+                    (
+                        i * 10,
+                        json!({ "n": i, "appended": appended, "rotated": rotated }),
+                    )
+                })
+                .collect(),
+        );
+    }
 
-        // println!("---------------\n");
-
-        Ok(json!({
-            "value": {
-                "games": {
-                    "sim": sim.data,
-                    "season": season.data,
-                    "standings": standings.data,
-                    "schedule": schedule,
-                    "tomorrowSchedule": tomorrow_schedule,
-                    "tournament": tournament,
-                    playoff_key: playoffs
-                },
-                "leagues": {
-                    "stats": {
-                        "sunsun": sunsun.data,
-                        "communityChest": communitychest.data
-                    },
-                    "teams": teams,
-                    "subleagues": subleagues,
-                    "divisions": divisions,
-                    "leagues": leagues,
-                    "tiebreakers": tiebreakers,
-                    "stadiums": stadiums
-                },
-                "fights": {
-                    "bossFights": fights
-                },
-                "temporal": temporal.data
-            }
-        }))
+    #[test]
+    fn consecutive_array_reads_through_the_cache() {
+        // checkpoints on an array insert everything into the empty base, so they can't be replayed over a cached version
+        read_through_cache(
+            (1..=20)
+                .map(|i| (i * 10, json!((0..i).collect::<Vec<u32>>())))
+                .collect(),
+        );
     }
 }
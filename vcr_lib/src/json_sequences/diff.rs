@@ -0,0 +1,541 @@
+use json_patch::{
+    AddOperation, MoveOperation, PatchOperation, PatchOperation::*, RemoveOperation,
+    ReplaceOperation,
+};
+use serde_json::{Map, Value as JSONValue};
+use std::collections::HashMap;
+
+/// Arrays whose changed middle section is bigger than this (in LCS table cells) are diffed positionally instead.
+const MAX_LCS_CELLS: usize = 1 << 20;
+
+/// A single change produced by `diff`. Array ops refer to the array by its own path, plus indices into it.
+pub(crate) enum DiffOp {
+    Patch(PatchOperation),
+    /// insert `value` at `index`, or append it if `index` is None
+    ArrayInsert {
+        array: String,
+        index: Option<usize>,
+        value: JSONValue,
+    },
+    ArrayRemove {
+        array: String,
+        index: usize,
+    },
+    /// remove the element at `from`, then insert it at `to`
+    ArrayMove {
+        array: String,
+        from: usize,
+        to: usize,
+    },
+}
+
+impl DiffOp {
+    /// Turns an array op into the equivalent regular JSON patch operation.
+    pub(crate) fn into_patch_op(self) -> PatchOperation {
+        match self {
+            DiffOp::Patch(op) => op,
+            DiffOp::ArrayInsert {
+                array,
+                index,
+                value,
+            } => Add(AddOperation {
+                path: match index {
+                    Some(i) => format!("{}/{}", array, i),
+                    None => format!("{}/-", array),
+                },
+                value,
+            }),
+            DiffOp::ArrayRemove { array, index } => Remove(RemoveOperation {
+                path: format!("{}/{}", array, index),
+            }),
+            DiffOp::ArrayMove { array, from, to } => Move(MoveOperation {
+                from: format!("{}/{}", array, from),
+                path: format!("{}/{}", array, to),
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Source {
+    Kept(usize),
+    Changed(usize),
+    Moved(usize),
+    New,
+}
+
+impl Source {
+    fn index(&self) -> Option<usize> {
+        match *self {
+            Source::Kept(i) | Source::Changed(i) | Source::Moved(i) => Some(i),
+            Source::New => None,
+        }
+    }
+}
+
+/// Like `json_patch::diff`, but detects insertions, removals and moves inside arrays instead of rewriting every element after the first change.
+pub(crate) fn diff(from: &JSONValue, to: &JSONValue) -> Vec<DiffOp> {
+    let mut ops = Vec::new();
+    diff_value(from, to, "", &mut ops);
+    ops
+}
+
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn diff_value(from: &JSONValue, to: &JSONValue, path: &str, ops: &mut Vec<DiffOp>) {
+    if from == to {
+        return;
+    }
+
+    match (from, to) {
+        (JSONValue::Object(a), JSONValue::Object(b)) => diff_object(a, b, path, ops),
+        (JSONValue::Array(a), JSONValue::Array(b)) => diff_array(a, b, path, ops),
+        _ => ops.push(DiffOp::Patch(Replace(ReplaceOperation {
+            path: path.to_owned(),
+            value: to.clone(),
+        }))),
+    }
+}
+
+fn diff_object(
+    a: &Map<String, JSONValue>,
+    b: &Map<String, JSONValue>,
+    path: &str,
+    ops: &mut Vec<DiffOp>,
+) {
+    for key in a.keys().filter(|k| !b.contains_key(*k)) {
+        ops.push(DiffOp::Patch(Remove(RemoveOperation {
+            path: format!("{}/{}", path, escape(key)),
+        })));
+    }
+
+    for (key, value) in b {
+        let key_path = format!("{}/{}", path, escape(key));
+        match a.get(key) {
+            Some(old) => diff_value(old, value, &key_path, ops),
+            None => ops.push(DiffOp::Patch(Add(AddOperation {
+                path: key_path,
+                value: value.clone(),
+            }))),
+        }
+    }
+}
+
+/// Longest common subsequence of two slices, as pairs of (index in a, index in b).
+fn lcs(a: &[JSONValue], b: &[JSONValue]) -> Vec<(usize, usize)> {
+    let width = b.len() + 1;
+    let mut table = vec![0_u32; (a.len() + 1) * width];
+
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            table[i * width + j] = if a[i] == b[j] {
+                table[(i + 1) * width + j + 1] + 1
+            } else {
+                table[(i + 1) * width + j].max(table[i * width + j + 1])
+            };
+        }
+    }
+
+    let mut pairs = Vec::with_capacity(table[0] as usize);
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] == b[j] {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if table[(i + 1) * width + j] >= table[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+
+    pairs
+}
+
+fn diff_array(a: &[JSONValue], b: &[JSONValue], path: &str, ops: &mut Vec<DiffOp>) {
+    // lineups, play-by-play lists and the like mostly change at one end, so strip the common ends before doing anything expensive
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    let mut sources = vec![Source::New; b_mid.len()];
+    let mut used = vec![false; a_mid.len()];
+
+    if a_mid.len() * b_mid.len() <= MAX_LCS_CELLS {
+        let common = lcs(a_mid, b_mid);
+        for &(i, j) in &common {
+            sources[j] = Source::Kept(i);
+            used[i] = true;
+        }
+
+        // elements that disappeared from one place and showed up in another are moves
+        let mut removed: HashMap<String, Vec<usize>> = HashMap::new();
+        for i in (0..a_mid.len()).rev().filter(|i| !used[*i]) {
+            removed.entry(a_mid[i].to_string()).or_default().push(i);
+        }
+
+        for j in 0..b_mid.len() {
+            if sources[j] != Source::New {
+                continue;
+            }
+
+            if let Some(i) = removed
+                .get_mut(&b_mid[j].to_string())
+                .and_then(|candidates| candidates.pop())
+            {
+                sources[j] = Source::Moved(i);
+                used[i] = true;
+            }
+        }
+
+        // whatever's left in between two kept elements was most likely changed in place
+        let mut gap_start = (0, 0);
+        for (ki, kj) in common
+            .into_iter()
+            .chain(std::iter::once((a_mid.len(), b_mid.len())))
+        {
+            let old = (gap_start.0..ki)
+                .filter(|i| !used[*i])
+                .collect::<Vec<usize>>();
+            let new = (gap_start.1..kj)
+                .filter(|j| sources[*j] == Source::New)
+                .collect::<Vec<usize>>();
+
+            for (i, j) in old.into_iter().zip(new) {
+                sources[j] = Source::Changed(i);
+                used[i] = true;
+            }
+
+            gap_start = (ki + 1, kj + 1);
+        }
+    } else {
+        for (j, source) in sources.iter_mut().enumerate().take(a_mid.len()) {
+            *source = Source::Changed(j);
+            used[j] = true;
+        }
+    }
+
+    let mut array_ops = Vec::new();
+    let mut working: Vec<Option<usize>> = (0..a_mid.len()).map(Some).collect();
+
+    // removals go back to front so the indices of the elements we haven't touched yet stay the same
+    for i in (0..a_mid.len()).rev().filter(|i| !used[*i]) {
+        working.remove(i);
+        array_ops.push(DiffOp::ArrayRemove {
+            array: path.to_owned(),
+            index: prefix + i,
+        });
+    }
+
+    // kept and changed elements are already in the right order, so moved elements just need to go right after whatever precedes them in the new array
+    for j in 0..b_mid.len() {
+        if let Source::Moved(i) = sources[j] {
+            let from = working.iter().position(|v| *v == Some(i)).unwrap();
+            working.remove(from);
+
+            let to = match sources[..j].iter().rev().find_map(|s| s.index()) {
+                Some(pred) => working.iter().position(|v| *v == Some(pred)).unwrap() + 1,
+                None => 0,
+            };
+            working.insert(to, Some(i));
+
+            if from != to {
+                array_ops.push(DiffOp::ArrayMove {
+                    array: path.to_owned(),
+                    from: prefix + from,
+                    to: prefix + to,
+                });
+            }
+        }
+    }
+
+    for (j, value) in b_mid.iter().enumerate() {
+        if sources[j] == Source::New {
+            let append = j == working.len() && suffix == 0;
+            working.insert(j, None);
+            array_ops.push(DiffOp::ArrayInsert {
+                array: path.to_owned(),
+                index: if append { None } else { Some(prefix + j) },
+                value: value.clone(),
+            });
+        }
+    }
+
+    let consistent = working
+        .iter()
+        .zip(sources.iter())
+        .all(|(w, s)| *w == s.index());
+
+    // when nearly everything changed, a single replace is smaller than a pile of array ops
+    if !consistent || array_ops.len() > b.len() {
+        ops.push(DiffOp::Patch(Replace(ReplaceOperation {
+            path: path.to_owned(),
+            value: JSONValue::Array(b.to_vec()),
+        })));
+        return;
+    }
+
+    ops.append(&mut array_ops);
+
+    for (j, source) in sources.iter().enumerate() {
+        if let Source::Changed(i) = *source {
+            diff_value(
+                &a_mid[i],
+                &b_mid[j],
+                &format!("{}/{}", path, prefix + j),
+                ops,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_sequences::testing::{id, tape};
+    use serde_json::json;
+
+    /// Encodes `from` then `to` as patches, reads `to` back out of the tape and checks it came out the same. Returns the ops `to` was diffed into.
+    fn round_trip(from: JSONValue, to: JSONValue) -> Vec<DiffOp> {
+        let entity = id(1);
+        // the last version is stored whole, so `to` gets repeated to make sure it's read from a patch
+        let db = tape(
+            vec![(
+                entity.clone(),
+                vec![(1, from.clone()), (2, to.clone()), (3, to.clone())],
+            )],
+            u16::MAX,
+            1,
+        );
+        assert_eq!(db.get_entity(&entity, 2).unwrap().data, to);
+
+        diff(&from, &to)
+    }
+
+    fn inserts(ops: &[DiffOp]) -> Vec<Option<usize>> {
+        ops.iter()
+            .filter_map(|op| match op {
+                DiffOp::ArrayInsert { index, .. } => Some(*index),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn removes(ops: &[DiffOp]) -> Vec<usize> {
+        ops.iter()
+            .filter_map(|op| match op {
+                DiffOp::ArrayRemove { index, .. } => Some(*index),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn moves(ops: &[DiffOp]) -> Vec<(usize, usize)> {
+        ops.iter()
+            .filter_map(|op| match op {
+                DiffOp::ArrayMove { from, to, .. } => Some((*from, *to)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn replaces(ops: &[DiffOp]) -> Vec<&str> {
+        ops.iter()
+            .filter_map(|op| match op {
+                DiffOp::Patch(Replace(op)) => Some(op.path.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prepend() {
+        let ops = round_trip(json!({ "a": [1, 2, 3] }), json!({ "a": [0, 1, 2, 3] }));
+        assert_eq!(inserts(&ops), vec![Some(0)]);
+        assert_eq!(ops.len(), 1);
+    }
+
+    #[test]
+    fn append() {
+        let ops = round_trip(json!({ "a": [1, 2, 3] }), json!({ "a": [1, 2, 3, 4, 5] }));
+        assert_eq!(inserts(&ops), vec![None, None]);
+        assert_eq!(ops.len(), 2);
+    }
+
+    #[test]
+    fn insert_in_the_middle() {
+        let ops = round_trip(
+            json!({ "a": [1, 2, 4, 5] }),
+            json!({ "a": [1, 2, 3, 4, 5] }),
+        );
+        assert_eq!(inserts(&ops), vec![Some(2)]);
+        assert_eq!(ops.len(), 1);
+    }
+
+    #[test]
+    fn remove() {
+        let ops = round_trip(
+            json!({ "a": [1, 2, 3, 4, 5, 6] }),
+            json!({ "a": [1, 3, 4, 6] }),
+        );
+        assert_eq!(removes(&ops), vec![4, 1]);
+        assert_eq!(ops.len(), 2);
+    }
+
+    #[test]
+    fn move_to_front() {
+        let ops = round_trip(
+            json!({ "a": ["a", "b", "c", "d", "e"] }),
+            json!({ "a": ["e", "a", "b", "c", "d"] }),
+        );
+        assert_eq!(moves(&ops), vec![(4, 0)]);
+        assert_eq!(ops.len(), 1);
+    }
+
+    #[test]
+    fn move_to_back() {
+        let ops = round_trip(
+            json!({ "a": ["a", "b", "c", "d", "e"] }),
+            json!({ "a": ["b", "c", "d", "e", "a"] }),
+        );
+        assert_eq!(moves(&ops).len(), 1);
+        assert_eq!(ops.len(), 1);
+    }
+
+    #[test]
+    fn swap() {
+        round_trip(
+            json!({ "a": ["a", "b", "c", "d", "e"] }),
+            json!({ "a": ["a", "d", "c", "b", "e"] }),
+        );
+    }
+
+    #[test]
+    fn duplicates() {
+        round_trip(
+            json!({ "a": [1, 1, 2, 2, 3] }),
+            json!({ "a": [2, 1, 2, 1, 1, 3, 3] }),
+        );
+        round_trip(json!({ "a": [1, 1, 1, 1] }), json!({ "a": [1, 1] }));
+        round_trip(
+            json!({ "a": [0, 1, 0, 1, 0] }),
+            json!({ "a": [1, 0, 1, 0, 1] }),
+        );
+    }
+
+    #[test]
+    fn edits_inside_moved_elements() {
+        round_trip(
+            json!({ "lineup": [
+                { "id": 1, "name": "a", "stats": [1, 2] },
+                { "id": 2, "name": "b", "stats": [3] },
+                { "id": 3, "name": "c", "stats": [] }
+            ] }),
+            json!({ "lineup": [
+                { "id": 3, "name": "c", "stats": [] },
+                { "id": 1, "name": "a", "stats": [1, 2, 5] },
+                { "id": 2, "name": "B", "stats": [3] }
+            ] }),
+        );
+
+        // an element that moved and changed at the same time isn't a move, but still has to come out right
+        round_trip(
+            json!({ "a": [{ "x": [1, 2] }, { "y": 1 }, { "z": 1 }] }),
+            json!({ "a": [{ "z": 1 }, { "x": [2, 1] }, { "y": 2 }] }),
+        );
+    }
+
+    #[test]
+    fn empty_arrays() {
+        let ops = round_trip(json!({ "a": [] }), json!({ "a": [1, 2] }));
+        assert_eq!(inserts(&ops), vec![None, None]);
+
+        // emptying an array takes more ops than the new array has elements, so it's replaced outright
+        let ops = round_trip(json!({ "a": [1, 2] }), json!({ "a": [] }));
+        assert_eq!(replaces(&ops), vec!["/a"]);
+
+        let ops = round_trip(json!({ "a": [], "b": 1 }), json!({ "a": [], "b": 2 }));
+        assert_eq!(replaces(&ops), vec!["/b"]);
+
+        round_trip(json!([]), json!([1, [2], { "3": 4 }]));
+    }
+
+    #[test]
+    fn nested_arrays() {
+        round_trip(
+            json!({ "a": [[1, 2, 3], [4, 5], []] }),
+            json!({ "a": [[4, 5, 6], [0, 1, 2, 3], [], [7]] }),
+        );
+    }
+
+    #[test]
+    fn everything_changed() {
+        let ops = round_trip(json!({ "a": [1, 2, 3] }), json!({ "a": [4, 5, 6, 7] }));
+        assert!(moves(&ops).is_empty());
+        assert!(removes(&ops).is_empty());
+    }
+
+    #[test]
+    fn too_big_for_lcs() {
+        // 1100 * 1100 cells is past MAX_LCS_CELLS, and nothing lines up at either end
+        let from: Vec<u32> = (0..1100).collect();
+        let to: Vec<u32> = (0..1100).map(|i| (i + 1) % 1100).collect();
+        assert!(from.len() * to.len() > MAX_LCS_CELLS);
+
+        let ops = round_trip(json!({ "a": from }), json!({ "a": to }));
+        assert!(inserts(&ops).is_empty());
+        assert!(removes(&ops).is_empty());
+        assert!(moves(&ops).is_empty());
+    }
+
+    #[test]
+    fn random_edits() {
+        // deterministic xorshift, so failures can be reproduced
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move |n: u64| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state % n
+        };
+
+        for _ in 0..300 {
+            let mut from: Vec<JSONValue> = (0..next(12)).map(|_| json!(next(5))).collect();
+            let mut to = from.clone();
+
+            for _ in 0..next(5) {
+                match next(4) {
+                    0 => to.insert(next(to.len() as u64 + 1) as usize, json!(next(5))),
+                    1 if !to.is_empty() => {
+                        to.remove(next(to.len() as u64) as usize);
+                    }
+                    2 if !to.is_empty() => {
+                        let value = to.remove(next(to.len() as u64) as usize);
+                        to.insert(next(to.len() as u64 + 1) as usize, value);
+                    }
+                    _ if !to.is_empty() => {
+                        let i = next(to.len() as u64) as usize;
+                        to[i] = json!({ "v": next(5) });
+                    }
+                    _ => {}
+                }
+            }
+
+            if next(2) == 0 {
+                from.push(json!([1, 2]));
+                to.push(json!([2, 1, 3]));
+            }
+
+            round_trip(json!({ "a": from }), json!({ "a": to }));
+        }
+    }
+}
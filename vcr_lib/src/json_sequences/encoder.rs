@@ -1,124 +1,452 @@
-use json_patch::{diff, PatchOperation, PatchOperation::*};
+use super::diff::{diff, DiffOp};
+use super::{op_layout, VALUE_REF_FLAG};
+use crate::VCRResult;
+use integer_encoding::VarIntWriter;
+use json_patch::PatchOperation::*;
+use serde::{Deserialize, Serialize};
 use serde_json::{json, Value as JSONValue};
-use std::collections::HashMap;
-use std::iter;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::Write;
 use std::mem;
+use uuid::Uuid;
 
 type EntityPatch = (u32, Vec<Vec<u8>>);
 
 struct Op {
     paths: Vec<String>,
     op_code: u8,
+    indices: Vec<u16>,
     value: Option<JSONValue>,
 }
 
+/// Array indices are stored as u16s, with u16::MAX standing in for "append".
+const APPEND_INDEX: u16 = u16::MAX;
+
+fn array_index(index: usize) -> Option<u16> {
+    if index < APPEND_INDEX as usize {
+        Some(index as u16)
+    } else {
+        None
+    }
+}
+
+impl From<DiffOp> for Op {
+    fn from(diff_op: DiffOp) -> Op {
+        match diff_op {
+            DiffOp::ArrayInsert {
+                array,
+                index,
+                value,
+            } if index.map_or(true, |i| array_index(i).is_some()) => Op {
+                paths: vec![array],
+                op_code: 7,
+                indices: vec![index.and_then(array_index).unwrap_or(APPEND_INDEX)],
+                value: Some(value),
+            },
+            DiffOp::ArrayRemove { array, index } if array_index(index).is_some() => Op {
+                paths: vec![array],
+                op_code: 8,
+                indices: vec![index as u16],
+                value: None,
+            },
+            DiffOp::ArrayMove { array, from, to }
+                if array_index(from).is_some() && array_index(to).is_some() =>
+            {
+                Op {
+                    paths: vec![array],
+                    op_code: 9,
+                    indices: vec![from as u16, to as u16],
+                    value: None,
+                }
+            }
+            // regular ops, and array ops with indices too big to fit
+            other => match other.into_patch_op() {
+                Add(add_op) => Op {
+                    paths: vec![add_op.path],
+                    op_code: 0,
+                    indices: vec![],
+                    value: Some(add_op.value),
+                },
+                Remove(rm_op) => Op {
+                    paths: vec![rm_op.path],
+                    op_code: 1,
+                    indices: vec![],
+                    value: None,
+                },
+                Replace(re_op) => Op {
+                    paths: vec![re_op.path],
+                    op_code: 2,
+                    indices: vec![],
+                    value: Some(re_op.value),
+                },
+                Move(mv_op) => Op {
+                    paths: vec![mv_op.path, mv_op.from],
+                    op_code: 3,
+                    indices: vec![],
+                    value: None,
+                },
+                Copy(cp_op) => Op {
+                    paths: vec![cp_op.path, cp_op.from],
+                    op_code: 4,
+                    indices: vec![],
+                    value: None,
+                },
+                Test(te_op) => Op {
+                    paths: vec![te_op.path],
+                    op_code: 5,
+                    indices: vec![],
+                    value: Some(te_op.value),
+                },
+            },
+        }
+    }
+}
+
+/// Turns an entity's versions into patches one at a time, for entities with too many versions to hold in memory at once. `encode` does the same for a whole list.
+pub struct PatchEncoder {
+    base: JSONValue,
+    last: JSONValue,
+    paths: HashMap<String, u16>,
+    checkpoint_every: u16,
+    versions: u32,
+}
+
+impl PatchEncoder {
+    /// The base value is an empty value of the same kind as `first`, the entity's first version.
+    pub fn new(first: &JSONValue, checkpoint_every: u16) -> PatchEncoder {
+        let base = match first {
+            JSONValue::Null => json!(null),
+            JSONValue::Bool(_) => json!(false),
+            JSONValue::Number(_) => json!(0),
+            JSONValue::String(_) => json!(""),
+            JSONValue::Array(_) => json!([]),
+            JSONValue::Object(_) => json!({}),
+        };
+
+        PatchEncoder {
+            last: base.clone(),
+            base,
+            paths: HashMap::new(),
+            checkpoint_every,
+            versions: 0,
+        }
+    }
+
+    /// Encodes the next version, as a patch from the one before (or from the base, for checkpoints).
+    pub fn push(&mut self, time: u32, obj: JSONValue) -> EntityPatch {
+        let diff_ops: Vec<DiffOp> = if self.versions % self.checkpoint_every as u32 == 0 {
+            diff(&self.base, &obj)
+        } else {
+            diff(&self.last, &obj)
+        };
+        self.versions += 1;
+
+        let paths = &mut self.paths;
+        let diff: Vec<Vec<u8>> = if mem::discriminant(&obj) != mem::discriminant(&self.base) {
+            let mut bytes: Vec<u8> = vec![6_u8.to_be()];
+            let mut val_bytes = rmp_serde::to_vec(&obj).unwrap();
+            bytes.extend((val_bytes.len() as u16).to_be_bytes());
+            bytes.append(&mut val_bytes);
+            vec![bytes]
+        } else {
+            diff_ops
+                .into_iter()
+                .map(|r_op| {
+                    let op = Op::from(r_op);
+
+                    let mut bytes: Vec<u8> = vec![op.op_code.to_be()];
+
+                    for path in &op.paths {
+                        if !paths.contains_key(path) {
+                            paths.insert(path.to_string(), paths.len() as u16);
+                        }
+
+                        bytes.extend(paths[path].to_be_bytes());
+                    }
+
+                    for index in &op.indices {
+                        bytes.extend(index.to_be_bytes());
+                    }
+
+                    if let Some(value) = op.value {
+                        let mut val_bytes = rmp_serde::to_vec(&value).unwrap();
+                        bytes.extend((val_bytes.len() as u16).to_be_bytes());
+                        bytes.append(&mut val_bytes);
+                    } else {
+                        bytes.extend(0_u16.to_be_bytes());
+                    }
+
+                    bytes
+                })
+                .collect()
+        };
+
+        self.last = obj;
+
+        (time, diff)
+    }
+
+    /// The final patch every entity ends with: its last version in full, as of `time`.
+    pub fn last_version(&self, time: u32) -> EntityPatch {
+        (time, vec![rmp_serde::to_vec(&self.last).unwrap()])
+    }
+
+    /// The path map and base value the patches were encoded against.
+    pub fn finish(self) -> (BTreeMap<u16, String>, JSONValue) {
+        (
+            self.paths
+                .into_iter()
+                .map(|(k, v)| (v, k))
+                .collect::<BTreeMap<u16, String>>(),
+            self.base,
+        )
+    }
+}
+
 pub fn encode(
     entity: Vec<(u32, JSONValue)>,
     checkpoint_every: u16,
-) -> (Vec<EntityPatch>, HashMap<u16, String>, JSONValue) {
-    let base = match entity[0].1 {
-        JSONValue::Null => json!(null),
-        JSONValue::Bool(_) => json!(false),
-        JSONValue::Number(_) => json!(0),
-        JSONValue::String(_) => json!(""),
-        JSONValue::Array(_) => json!([]),
-        JSONValue::Object(_) => json!({}),
-    };
-
-    let end_value = entity.last().cloned();
-
-    let mut last = base.clone();
-    let mut paths: HashMap<String, u16> = HashMap::new();
-    (
-        entity
-            .into_iter()
-            .enumerate()
-            .map(|(iter, (time, obj))| {
-                let diff_ops: Vec<PatchOperation> = if iter as u32 % checkpoint_every as u32 == 0 {
-                    diff(&base.clone(), &obj).0
-                } else {
-                    diff(&last, &obj).0
-                };
-
-                let diff: Vec<Vec<u8>> = if mem::discriminant(&obj) != mem::discriminant(&base) {
-                    let mut bytes: Vec<u8> = vec![6_u8.to_be()];
-                    let mut val_bytes = rmp_serde::to_vec(&obj).unwrap();
-                    bytes.extend((val_bytes.len() as u16).to_be_bytes());
-                    bytes.append(&mut val_bytes);
-                    vec![bytes]
-                } else {
-                    diff_ops
-                        .into_iter()
-                        .map(|r_op| {
-                            let op = match r_op {
-                                Add(add_op) => Op {
-                                    paths: vec![add_op.path],
-                                    op_code: 0,
-                                    value: Some(add_op.value),
-                                },
-                                Remove(rm_op) => Op {
-                                    paths: vec![rm_op.path],
-                                    op_code: 1,
-                                    value: None,
-                                },
-                                Replace(re_op) => Op {
-                                    paths: vec![re_op.path],
-                                    op_code: 2,
-                                    value: Some(re_op.value),
-                                },
-                                Move(mv_op) => Op {
-                                    paths: vec![mv_op.path, mv_op.from],
-                                    op_code: 3,
-                                    value: None,
-                                },
-                                Copy(cp_op) => Op {
-                                    paths: vec![cp_op.path, cp_op.from],
-                                    op_code: 4,
-                                    value: None,
-                                },
-                                Test(te_op) => Op {
-                                    paths: vec![te_op.path],
-                                    op_code: 5,
-                                    value: Some(te_op.value),
-                                },
-                            };
-
-                            let mut bytes: Vec<u8> = vec![op.op_code.to_be()];
-
-                            for path in &op.paths {
-                                if !paths.contains_key(path) {
-                                    paths.insert(path.to_string(), paths.len() as u16);
-                                }
-
-                                bytes.extend(paths[path].to_be_bytes());
-                            }
-
-                            if let Some(value) = op.value {
-                                let mut val_bytes = rmp_serde::to_vec(&value).unwrap();
-                                bytes.extend((val_bytes.len() as u16).to_be_bytes());
-                                bytes.append(&mut val_bytes);
-                            } else {
-                                bytes.extend(0_u16.to_be_bytes());
-                            }
-
-                            bytes
-                        })
-                        .collect()
-                };
-
-                last = obj;
-
-                (time, diff)
+) -> (Vec<EntityPatch>, BTreeMap<u16, String>, JSONValue) {
+    let mut encoder = PatchEncoder::new(&entity[0].1, checkpoint_every);
+    let end_time = entity.last().map(|(time, _)| *time);
+
+    let mut patches: Vec<EntityPatch> = entity
+        .into_iter()
+        .map(|(time, obj)| encoder.push(time, obj))
+        .collect();
+    patches.extend(end_time.map(|time| encoder.last_version(time)));
+
+    let (path_map, base) = encoder.finish();
+    (patches, path_map, base)
+}
+
+/// Writes an entity's entry in a tape's entity table (`{type}.header.riv.zstd`, before compression): the length of its header, where its last patch ends in the tape, its id, and then the header.
+pub fn write_entity_table_entry<W: Write>(
+    mut writer: W,
+    id: &Uuid,
+    end_position: u32,
+    header: &[u8],
+) -> VCRResult<()> {
+    writer.write_varint(header.len() as u32)?;
+    writer.write_varint(end_position)?;
+    writer.write_all(id.as_bytes())?;
+    writer.write_all(header)?;
+    Ok(())
+}
+
+/// Strings longer than this are assumed to be prose and never interned.
+const MAX_INTERNED_LEN: usize = 128;
+
+/// Sizes (in uncompressed bytes) of the values a `ValueTable` took out of patches.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ValueTableStats {
+    pub values: usize,
+    pub references: usize,
+    pub inline_bytes: usize,
+    pub table_bytes: usize,
+}
+
+impl ValueTableStats {
+    /// Bytes saved across all patches, after paying for the table itself.
+    pub fn saved_bytes(&self) -> i64 {
+        // every reference still costs its 2 byte id, which takes the place of the value length
+        self.inline_bytes as i64 - self.table_bytes as i64
+    }
+}
+
+/// Everything that happened to a `ValueTable` since the last call to `take_delta`, so a build can be journaled and resumed with the table in the same state.
+#[derive(Serialize, Deserialize, Debug, Default, Clone)]
+pub struct ValueTableDelta {
+    pub seen: Vec<Vec<u8>>,
+    pub interned: Vec<Vec<u8>>,
+    pub references: usize,
+    pub inline_bytes: usize,
+}
+
+/// A per-type table of values (ids, enum-like strings) that show up over and over in patches.
+///
+/// Like `path_map` does for paths, ops whose value is in the table store a u16 id instead of the value, with `VALUE_REF_FLAG` set on their op code.
+/// A string gets interned the second time it's seen, so one-off values stay inline.
+#[derive(Default)]
+pub struct ValueTable {
+    ids: HashMap<Vec<u8>, u16>,
+    seen: HashSet<Vec<u8>>,
+    values: Vec<JSONValue>,
+    stats: ValueTableStats,
+    delta: ValueTableDelta,
+}
+
+impl ValueTable {
+    pub fn new() -> ValueTable {
+        ValueTable::default()
+    }
+
+    /// Rewrites an entity's patches (as returned by `encode`) to refer to interned values.
+    pub fn intern(&mut self, mut patches: Vec<EntityPatch>) -> Vec<EntityPatch> {
+        // the last patch is the entity's final value in full, not a list of ops
+        if let Some((_, ops_patches)) = patches.split_last_mut() {
+            for (_, ops) in ops_patches.iter_mut() {
+                self.intern_ops(ops);
+            }
+        }
+
+        patches
+    }
+
+    /// Rewrites the ops of a single patch (as returned by `PatchEncoder::push`) to refer to interned values. Final versions, from `PatchEncoder::last_version`, aren't ops and mustn't go through this.
+    pub fn intern_ops(&mut self, ops: &mut [Vec<u8>]) {
+        for op in ops.iter_mut() {
+            self.intern_op(op);
+        }
+    }
+
+    fn intern_op(&mut self, op: &mut Vec<u8>) {
+        let op_code = op[0];
+        if op_code == 6 || op_code & VALUE_REF_FLAG != 0 {
+            return;
+        }
+
+        let (path_count, index_count) = op_layout(op_code);
+        let value_start = 1 + 2 * (path_count + index_count) + 2;
+        if op.len() <= value_start + 2 || op.len() - value_start > MAX_INTERNED_LEN + 2 {
+            return;
+        }
+
+        match rmp_serde::from_read_ref::<_, JSONValue>(&op[value_start..]) {
+            Ok(JSONValue::String(_)) => {}
+            _ => return,
+        }
+
+        let value_bytes = op[value_start..].to_vec();
+        let id = match self.ids.get(&value_bytes) {
+            Some(id) => *id,
+            None if self.seen.contains(&value_bytes) && self.values.len() < u16::MAX as usize => {
+                self.delta.interned.push(value_bytes.clone());
+                self.add_value(value_bytes)
+            }
+            None => {
+                self.delta.seen.push(value_bytes.clone());
+                self.seen.insert(value_bytes);
+                return;
+            }
+        };
+
+        self.stats.references += 1;
+        self.stats.inline_bytes += op.len() - value_start;
+        self.delta.references += 1;
+        self.delta.inline_bytes += op.len() - value_start;
+
+        op[0] = op_code | VALUE_REF_FLAG;
+        op.truncate(value_start - 2);
+        op.extend(id.to_be_bytes());
+    }
+
+    fn add_value(&mut self, value_bytes: Vec<u8>) -> u16 {
+        let id = self.values.len() as u16;
+        self.values
+            .push(rmp_serde::from_read_ref(&value_bytes).unwrap());
+        self.stats.values += 1;
+        self.stats.table_bytes += value_bytes.len();
+        self.seen.remove(&value_bytes);
+        self.ids.insert(value_bytes, id);
+        id
+    }
+
+    /// Takes the changes made to the table since the last call.
+    pub fn take_delta(&mut self) -> ValueTableDelta {
+        mem::take(&mut self.delta)
+    }
+
+    /// Replays changes taken with `take_delta`, in the order they were taken.
+    pub fn apply_delta(&mut self, delta: ValueTableDelta) {
+        self.seen.extend(delta.seen);
+        for value_bytes in delta.interned {
+            self.add_value(value_bytes);
+        }
+
+        self.stats.references += delta.references;
+        self.stats.inline_bytes += delta.inline_bytes;
+    }
+
+    pub fn stats(&self) -> ValueTableStats {
+        self.stats
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Writes the table out in the format `Database::set_value_table` reads.
+    pub fn write<W: Write>(&self, writer: W) -> VCRResult<()> {
+        let mut encoder = zstd::Encoder::new(writer, 21)?;
+        rmp_serde::encode::write(&mut encoder, &self.values)?;
+        encoder.finish()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_sequences::testing::{id, tape_from_patches, Encoded};
+
+    /// A player moving between two teams, so team ids repeat across versions and entities.
+    fn versions(n: u128) -> Vec<(u32, JSONValue)> {
+        (0..6)
+            .map(|i| {
+                let team = id(100 + (n + i as u128) % 2);
+                (
+                    i * 10 + 1,
+                    json!({ "name": format!("player {}", n), "team": team, "moves": i }),
+                )
             })
-            .chain(
-                iter::once(end_value)
-                    .filter_map(|v| v.map(|x| (x.0, vec![rmp_serde::to_vec(&x.1).unwrap()]))),
-            )
-            .collect::<Vec<EntityPatch>>(),
-        paths
-            .into_iter()
-            .map(|(k, v)| (v, k))
-            .collect::<HashMap<u16, String>>(),
-        base,
-    )
+            .collect()
+    }
+
+    #[test]
+    fn interned_values_round_trip() {
+        let mut table = ValueTable::new();
+        let mut deltas = Vec::new();
+        let mut entities: Vec<(String, Encoded)> = Vec::new();
+
+        for n in 0..3 {
+            let (patches, path_map, base) = encode(versions(n), u16::MAX);
+            let patches = table.intern(patches);
+            deltas.push(table.take_delta());
+            entities.push((id(n), (patches, path_map, base)));
+        }
+
+        assert!(!table.is_empty());
+        assert!(table.stats().references > 0);
+        let interned_ops = entities
+            .iter()
+            .flat_map(|(_, (patches, _, _))| patches.split_last().unwrap().1)
+            .flat_map(|(_, ops)| ops)
+            .filter(|op| op[0] & VALUE_REF_FLAG != 0)
+            .count();
+        assert_eq!(interned_ops, table.stats().references);
+
+        // a resumed build replays the journaled deltas, and has to intern the next entity the same way
+        let mut resumed = ValueTable::new();
+        for delta in deltas {
+            resumed.apply_delta(delta);
+        }
+        let (patches, path_map, base) = encode(versions(3), u16::MAX);
+        let next = table.intern(patches.clone());
+        assert_eq!(resumed.intern(patches), next);
+        assert_eq!(resumed.take_delta().interned, table.take_delta().interned);
+        entities.push((id(3), (next, path_map, base)));
+
+        let mut values = Vec::new();
+        table.write(&mut values).unwrap();
+        let mut db = tape_from_patches(entities, u16::MAX, 10);
+        db.set_value_table(&values[..]).unwrap();
+        assert_eq!(db.value_table().len(), table.stats().values);
+
+        for n in 0..4 {
+            for (time, data) in versions(n) {
+                assert_eq!(db.get_entity(&id(n), time).unwrap().data, data);
+            }
+        }
+    }
 }
@@ -0,0 +1,424 @@
+use crate::ChroniclerEntity;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JSONValue;
+use uuid::Uuid;
+
+/// What `/games` filters on, taken from a game's last version when it's encoded. Stored as `game_updates.index.riv.zstd`, so filtering doesn't need to decode any games.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GameIndexEntry {
+    pub home_team: Option<Uuid>,
+    pub away_team: Option<Uuid>,
+    pub home_pitcher: Option<Uuid>,
+    pub away_pitcher: Option<Uuid>,
+    pub weather: Option<i64>,
+    pub started: bool,
+    pub finalized: bool,
+    pub start_time: Option<DateTime<Utc>>,
+    pub end_time: Option<DateTime<Utc>>,
+}
+
+fn uuid_field(game: &JSONValue, field: &str) -> Option<Uuid> {
+    game.get(field)
+        .and_then(|v| v.as_str())
+        .and_then(|v| Uuid::parse_str(v).ok())
+}
+
+impl GameIndexEntry {
+    /// Reads the indexed fields out of a game's data. Start and end times aren't part of it, so they're left empty.
+    pub fn from_game(game: &JSONValue) -> GameIndexEntry {
+        GameIndexEntry {
+            home_team: uuid_field(game, "homeTeam"),
+            away_team: uuid_field(game, "awayTeam"),
+            home_pitcher: uuid_field(game, "homePitcher"),
+            away_pitcher: uuid_field(game, "awayPitcher"),
+            weather: game.get("weather").and_then(|v| v.as_i64()),
+            started: game.get("gameStart").and_then(|v| v.as_bool()) == Some(true),
+            finalized: game.get("finalized").and_then(|v| v.as_bool()) == Some(true),
+            start_time: None,
+            end_time: None,
+        }
+    }
+}
+
+/// The game filters `/games` takes. Every one that's set has to match; within one, any of the values can.
+#[derive(Debug, Default)]
+pub struct GameFilter {
+    pub teams: Option<Vec<Uuid>>,
+    pub pitchers: Option<Vec<Uuid>>,
+    pub weather: Option<Vec<i64>>,
+    pub started: Option<bool>,
+    pub finished: Option<bool>,
+}
+
+fn any_of<T: PartialEq>(wanted: &Option<Vec<T>>, values: &[Option<T>]) -> bool {
+    match wanted {
+        Some(wanted) => values.iter().flatten().any(|value| wanted.contains(value)),
+        None => true,
+    }
+}
+
+impl GameFilter {
+    /// Whether the filter lets every game through, so there's no need to look at them.
+    pub fn is_empty(&self) -> bool {
+        self.teams.is_none()
+            && self.pitchers.is_none()
+            && self.weather.is_none()
+            && self.started.is_none()
+            && self.finished.is_none()
+    }
+
+    pub fn matches(&self, game: &GameIndexEntry) -> bool {
+        self.started.map_or(true, |v| v == game.started)
+            && self.finished.map_or(true, |v| v == game.finalized)
+            && any_of(&self.teams, &[game.home_team, game.away_team])
+            && any_of(&self.pitchers, &[game.home_pitcher, game.away_pitcher])
+            && any_of(&self.weather, &[game.weather])
+    }
+}
+
+/// How a game went, taken from its last version when it's encoded. Stored as `game_updates.summaries.riv.zstd`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct GameSummary {
+    pub game_id: String,
+    pub season: i64,
+    pub day: i64,
+    pub home_team: Option<Uuid>,
+    pub away_team: Option<Uuid>,
+    pub home_team_name: String,
+    pub away_team_name: String,
+    // runs can be fractional
+    pub home_score: f64,
+    pub away_score: f64,
+    /// only set once the game is over, and not for ties
+    pub winner: Option<Uuid>,
+    pub loser: Option<Uuid>,
+    pub innings: i64,
+    pub shame: bool,
+    pub outcomes: Vec<String>,
+}
+
+impl GameSummary {
+    pub fn from_game(id: &str, game: &JSONValue) -> GameSummary {
+        let home_team = uuid_field(game, "homeTeam");
+        let away_team = uuid_field(game, "awayTeam");
+        let home_score = game
+            .get("homeScore")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0);
+        let away_score = game
+            .get("awayScore")
+            .and_then(|v| v.as_f64())
+            .unwrap_or(0.0);
+        let finalized = game.get("finalized").and_then(|v| v.as_bool()) == Some(true);
+        let started = game.get("gameStart").and_then(|v| v.as_bool()) == Some(true);
+        let string = |field: &str| {
+            game.get(field)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_owned()
+        };
+
+        let (winner, loser) = if !finalized || home_score == away_score {
+            (None, None)
+        } else if home_score > away_score {
+            (home_team, away_team)
+        } else {
+            (away_team, home_team)
+        };
+
+        GameSummary {
+            game_id: id.to_owned(),
+            season: game.get("season").and_then(|v| v.as_i64()).unwrap_or(0),
+            day: game.get("day").and_then(|v| v.as_i64()).unwrap_or(0),
+            home_team,
+            away_team,
+            home_team_name: string("homeTeamName"),
+            away_team_name: string("awayTeamName"),
+            home_score,
+            away_score,
+            winner,
+            loser,
+            // innings are zero-indexed in game data
+            innings: if started {
+                game.get("inning").and_then(|v| v.as_i64()).unwrap_or(0) + 1
+            } else {
+                0
+            },
+            shame: game.get("shame").and_then(|v| v.as_bool()) == Some(true),
+            outcomes: game
+                .get("outcomes")
+                .and_then(|v| v.as_array())
+                .map(|outcomes| {
+                    outcomes
+                        .iter()
+                        .filter_map(|o| o.as_str().map(|o| o.to_owned()))
+                        .collect()
+                })
+                .unwrap_or_default(),
+        }
+    }
+
+    pub fn involves(&self, team: &Uuid) -> bool {
+        self.home_team.as_ref() == Some(team) || self.away_team.as_ref() == Some(team)
+    }
+}
+
+/// Which games `MultiDatabase::game_summaries` returns. Seasons and days are zero-indexed, like in game data.
+#[derive(Debug, Default)]
+pub struct GameSummaryFilter {
+    pub season: Option<i32>,
+    pub day: Option<i32>,
+    pub team: Option<Uuid>,
+}
+
+/// Which half of an inning a play happened in.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Half {
+    Top,
+    Bottom,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BaseRunner {
+    pub id: Option<Uuid>,
+    pub name: String,
+    /// zero-indexed, so 0 is first base
+    pub base: i64,
+}
+
+/// The state of a game right after a pitch or play, with what it did to the score.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Play {
+    pub time: DateTime<Utc>,
+    /// the game's `playCount`, for seasons that have it
+    pub play: Option<i64>,
+    /// zero-indexed, like in game data
+    pub inning: i64,
+    pub half: Half,
+    pub batter: Option<Uuid>,
+    pub batter_name: String,
+    pub pitcher: Option<Uuid>,
+    pub pitcher_name: String,
+    pub balls: i64,
+    pub strikes: i64,
+    pub outs: i64,
+    pub runners: Vec<BaseRunner>,
+    pub home_score: f64,
+    pub away_score: f64,
+    pub home_score_delta: f64,
+    pub away_score_delta: f64,
+    pub text: String,
+}
+
+impl Play {
+    fn from_game(time: DateTime<Utc>, game: &JSONValue) -> Play {
+        let int = |field: &str| game.get(field).and_then(|v| v.as_i64()).unwrap_or(0);
+        let float = |field: &str| game.get(field).and_then(|v| v.as_f64()).unwrap_or(0.0);
+        let string = |field: &str| {
+            game.get(field)
+                .and_then(|v| v.as_str())
+                .unwrap_or_default()
+                .to_owned()
+        };
+
+        // the away team bats in the top of the inning, against the home team's pitcher
+        let top = game.get("topOfInning").and_then(|v| v.as_bool()) != Some(false);
+        let (batting, pitching) = if top {
+            ("away", "home")
+        } else {
+            ("home", "away")
+        };
+
+        let runner_ids = game.get("baseRunners").and_then(|v| v.as_array());
+        let runner_names = game.get("baseRunnerNames").and_then(|v| v.as_array());
+        let runners = game
+            .get("basesOccupied")
+            .and_then(|v| v.as_array())
+            .map(|bases| {
+                bases
+                    .iter()
+                    .enumerate()
+                    .map(|(i, base)| BaseRunner {
+                        id: runner_ids
+                            .and_then(|ids| ids.get(i))
+                            .and_then(|id| id.as_str())
+                            .and_then(|id| Uuid::parse_str(id).ok()),
+                        name: runner_names
+                            .and_then(|names| names.get(i))
+                            .and_then(|name| name.as_str())
+                            .unwrap_or_default()
+                            .to_owned(),
+                        base: base.as_i64().unwrap_or(0),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Play {
+            time,
+            play: game.get("playCount").and_then(|v| v.as_i64()),
+            inning: int("inning"),
+            half: if top { Half::Top } else { Half::Bottom },
+            batter: uuid_field(game, &format!("{}Batter", batting)),
+            batter_name: string(&format!("{}BatterName", batting)),
+            pitcher: uuid_field(game, &format!("{}Pitcher", pitching)),
+            pitcher_name: string(&format!("{}PitcherName", pitching)),
+            balls: int("atBatBalls"),
+            strikes: int("atBatStrikes"),
+            outs: int("halfInningOuts"),
+            runners,
+            home_score: float("homeScore"),
+            away_score: float("awayScore"),
+            home_score_delta: 0.0,
+            away_score_delta: 0.0,
+            text: string("lastUpdate"),
+        }
+    }
+
+    /// Turns a game's versions, oldest first, into its play-by-play. Versions that don't move the game along (same play count, or same update text in seasons without one) are skipped, and so is anything before the first update.
+    pub fn from_versions(versions: &[ChroniclerEntity<JSONValue>]) -> Vec<Play> {
+        let mut plays: Vec<Play> = Vec::new();
+
+        for version in versions {
+            let mut play = Play::from_game(version.valid_from, &version.data);
+            if play.text.is_empty() {
+                continue;
+            }
+
+            if let Some(last) = plays.last() {
+                let same_play = match (play.play, last.play) {
+                    (Some(current), Some(previous)) => current == previous,
+                    _ => play.text == last.text,
+                };
+                if same_play {
+                    continue;
+                }
+
+                play.home_score_delta = play.home_score - last.home_score;
+                play.away_score_delta = play.away_score - last.away_score;
+            }
+
+            plays.push(play);
+        }
+
+        plays
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_sequences::testing::id;
+    use chrono::TimeZone;
+    use serde_json::json;
+
+    fn versions(data: Vec<JSONValue>) -> Vec<ChroniclerEntity<JSONValue>> {
+        data.into_iter()
+            .enumerate()
+            .map(|(i, data)| ChroniclerEntity {
+                entity_id: id(0),
+                valid_from: Utc.timestamp(1000 + i as i64 * 5, 0),
+                valid_to: None,
+                hash: String::new(),
+                data,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plays_dedup_by_play_count() {
+        let plays = Play::from_versions(&versions(vec![
+            json!({"playCount": 0, "lastUpdate": ""}),
+            json!({"playCount": 1, "lastUpdate": "Ball. 1-0"}),
+            // the same play, sent again with a different text
+            json!({"playCount": 1, "lastUpdate": "Ball. 1-0 "}),
+            json!({"playCount": 2, "lastUpdate": "Ball. 2-0"}),
+            // a new play, with the same text as the last one
+            json!({"playCount": 3, "lastUpdate": "Ball. 2-0"}),
+        ]));
+
+        let plays: Vec<(Option<i64>, i64)> =
+            plays.iter().map(|p| (p.play, p.time.timestamp())).collect();
+        assert_eq!(
+            plays,
+            vec![(Some(1), 1005), (Some(2), 1015), (Some(3), 1020)]
+        );
+    }
+
+    #[test]
+    fn plays_dedup_by_text_without_play_count() {
+        let plays = Play::from_versions(&versions(vec![
+            json!({"lastUpdate": "Strike, looking. 0-1"}),
+            json!({"lastUpdate": "Strike, looking. 0-1"}),
+            json!({"lastUpdate": "Foul Ball. 0-2"}),
+            json!({"lastUpdate": "Foul Ball. 0-2"}),
+            json!({"lastUpdate": "Strike, swinging. Out."}),
+        ]));
+
+        let texts: Vec<&str> = plays.iter().map(|p| p.text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "Strike, looking. 0-1",
+                "Foul Ball. 0-2",
+                "Strike, swinging. Out."
+            ]
+        );
+        assert!(plays.iter().all(|p| p.play.is_none()));
+    }
+
+    #[test]
+    fn plays_have_score_deltas() {
+        let plays = Play::from_versions(&versions(vec![
+            json!({"playCount": 1, "lastUpdate": "Play ball!", "homeScore": 0, "awayScore": 0}),
+            json!({"playCount": 2, "lastUpdate": "hits a 2-run home run!", "homeScore": 0, "awayScore": 2}),
+            json!({"playCount": 3, "lastUpdate": "scores!", "homeScore": 1.5, "awayScore": 2}),
+            json!({"playCount": 4, "lastUpdate": "flies out.", "homeScore": 1.5, "awayScore": 2}),
+        ]));
+
+        let deltas: Vec<(f64, f64)> = plays
+            .iter()
+            .map(|p| (p.home_score_delta, p.away_score_delta))
+            .collect();
+        assert_eq!(deltas, vec![(0.0, 0.0), (0.0, 2.0), (1.5, 0.0), (0.0, 0.0)]);
+    }
+
+    #[test]
+    fn batter_and_pitcher_swap_between_halves() {
+        let game = |play: i64, top: bool| {
+            json!({
+                "playCount": play,
+                "lastUpdate": format!("play {}", play),
+                "topOfInning": top,
+                "homeBatter": id(1),
+                "homeBatterName": "Home Batter",
+                "awayBatter": id(2),
+                "awayBatterName": "Away Batter",
+                "homePitcher": id(3),
+                "homePitcherName": "Home Pitcher",
+                "awayPitcher": id(4),
+                "awayPitcherName": "Away Pitcher",
+            })
+        };
+        let plays = Play::from_versions(&versions(vec![game(1, true), game(2, false)]));
+
+        let top = &plays[0];
+        assert_eq!(top.half, Half::Top);
+        assert_eq!(top.batter, Some(Uuid::from_u128(2)));
+        assert_eq!(top.batter_name, "Away Batter");
+        assert_eq!(top.pitcher, Some(Uuid::from_u128(3)));
+        assert_eq!(top.pitcher_name, "Home Pitcher");
+
+        let bottom = &plays[1];
+        assert_eq!(bottom.half, Half::Bottom);
+        assert_eq!(bottom.batter, Some(Uuid::from_u128(1)));
+        assert_eq!(bottom.batter_name, "Home Batter");
+        assert_eq!(bottom.pitcher, Some(Uuid::from_u128(4)));
+        assert_eq!(bottom.pitcher_name, "Away Pitcher");
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{read_u8, utils::is_eof, EntityData, VCRError, VCRResult};
 use integer_encoding::{VarIntReader, VarIntWriter};
 use serde_json::{json, Value as JSONValue};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::io::{Cursor, Read, Seek, Write};
 use uuid::Uuid;
 
@@ -13,7 +13,7 @@ impl<W: Write> HeaderEncoder<W> {
     pub fn new(
         base: JSONValue,
         checkpoint_every: u16,
-        path_map: HashMap<u16, String>,
+        path_map: BTreeMap<u16, String>,
         start_pos: u32,
         mut writer: W,
     ) -> VCRResult<HeaderEncoder<W>> {
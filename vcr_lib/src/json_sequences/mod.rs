@@ -1,13 +1,25 @@
+mod counters;
 mod db;
+mod diff;
+mod games;
 mod header;
+mod search;
+mod stream;
+#[cfg(test)]
+pub(crate) mod testing;
 mod tributes;
 
 pub mod encoder;
 
+pub use counters::*;
 pub use db::*;
+pub use games::*;
 pub use header::*;
+pub use search::*;
+pub use stream::*;
 pub use tributes::*;
 
+use crate::{VCRError, VCRResult};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -15,6 +27,54 @@ use serde_json::{json, Value as JSONValue};
 
 use json_patch::Patch as JSONPatch;
 
+/// Set on an op code when the op's value is a u16 id into the entity type's value table instead of inline msgpack.
+pub const VALUE_REF_FLAG: u8 = 0x80;
+
+/// How many path ids and array indices (all u16s) follow an op code.
+pub(crate) fn op_layout(op_code: u8) -> (usize, usize) {
+    match op_code {
+        3 | 4 => (2, 0),
+        7 | 8 => (1, 1),
+        9 => (1, 2),
+        _ => (1, 0),
+    }
+}
+
+/// Walks the bytecode of a decompressed patch and returns its raw op codes (`VALUE_REF_FLAG` included) without decoding any paths or values.
+pub fn scan_op_codes(bytes: &[u8]) -> VCRResult<Vec<u8>> {
+    let mut op_codes = Vec::new();
+    let mut pos = 0;
+
+    while pos < bytes.len() {
+        let raw_op_code = bytes[pos];
+        let op_code = raw_op_code & !VALUE_REF_FLAG;
+        op_codes.push(raw_op_code);
+
+        if op_code == 6 {
+            break;
+        }
+
+        let (paths, indices) = op_layout(op_code);
+        pos += 1 + (paths + indices) * 2;
+
+        let value_length = bytes
+            .get(pos..pos + 2)
+            .map(|b| u16::from_be_bytes([b[0], b[1]]))
+            .ok_or(VCRError::InvalidPatchData)?;
+        pos += 2;
+
+        if raw_op_code & VALUE_REF_FLAG == 0 {
+            pos += value_length as usize;
+        }
+    }
+
+    if pos > bytes.len() {
+        return Err(VCRError::InvalidPatchData);
+    }
+
+    Ok(op_codes)
+}
+
 fn default_checkpoint() -> u16 {
     u16::MAX
 }
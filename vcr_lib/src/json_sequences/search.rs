@@ -0,0 +1,384 @@
+use crate::GameFilter;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JSONValue;
+use std::collections::BTreeMap;
+
+/// Splits update text into lowercase words. Anything that isn't a letter or a digit separates words, so `fielder's` is `fielder` and `s`.
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+/// The distinct `lastUpdate` texts in a game's versions (time, data; oldest first), with the time each one showed up.
+pub fn game_update_texts(versions: &[(u32, JSONValue)]) -> Vec<(u32, String)> {
+    let mut texts: Vec<(u32, String)> = Vec::new();
+
+    for (time, data) in versions {
+        let text = match data.get("lastUpdate").and_then(|v| v.as_str()) {
+            Some(text) if !text.is_empty() => text,
+            _ => continue,
+        };
+
+        if texts.last().map_or(true, |(_, last)| last != text) {
+            texts.push((*time, text.to_owned()));
+        }
+    }
+
+    texts
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct QueryWord {
+    word: String,
+    prefix: bool,
+}
+
+impl QueryWord {
+    fn matches(&self, word: &str) -> bool {
+        if self.prefix {
+            word.starts_with(&self.word)
+        } else {
+            word == self.word
+        }
+    }
+}
+
+/// A parsed search query. Every part has to match: quoted parts are phrases (their words have to show up in order, next to each other), and a word ending in `*` matches any word starting with it.
+#[derive(Debug, Clone, Default)]
+pub struct SearchQuery {
+    phrases: Vec<Vec<QueryWord>>,
+}
+
+impl SearchQuery {
+    pub fn parse(query: &str) -> SearchQuery {
+        fn words(part: &str) -> Vec<QueryWord> {
+            let mut words = Vec::new();
+            for word in part.split_whitespace() {
+                let prefix = word.ends_with('*');
+                let tokens = tokenize(word.trim_end_matches('*'));
+                let last = tokens.len().saturating_sub(1);
+                words.extend(tokens.into_iter().enumerate().map(|(i, word)| QueryWord {
+                    word,
+                    prefix: prefix && i == last,
+                }));
+            }
+            words
+        }
+
+        let mut phrases = Vec::new();
+        // splitting on quotes leaves what's inside them at odd indices
+        for (i, part) in query.split('"').enumerate() {
+            if i % 2 == 1 {
+                phrases.push(words(part));
+            } else {
+                phrases.extend(part.split_whitespace().map(words));
+            }
+        }
+        phrases.retain(|phrase| !phrase.is_empty());
+
+        SearchQuery { phrases }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.phrases.is_empty()
+    }
+
+    /// Whether an update's words contain every part of the query.
+    pub fn matches(&self, words: &[String]) -> bool {
+        self.phrases.iter().all(|phrase| {
+            words
+                .windows(phrase.len())
+                .any(|window| window.iter().zip(phrase).all(|(w, q)| q.matches(w)))
+        })
+    }
+}
+
+/// Which updates `MultiDatabase::search_game_updates` returns, besides the ones matching the query. Times are UNIX timestamps; seasons and days are zero-indexed, like in game data.
+#[derive(Debug, Default)]
+pub struct SearchFilter {
+    pub season: Option<i32>,
+    pub day: Option<i32>,
+    pub after: Option<u32>,
+    pub before: Option<u32>,
+    pub game: GameFilter,
+}
+
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    pub game_id: String,
+    pub season: i64,
+    pub day: i64,
+    pub timestamp: DateTime<Utc>,
+    pub text: String,
+}
+
+/// An inverted index over game update text, built by `build_games --search` and stored as `game_updates.search.riv.zstd`.
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct SearchIndex {
+    /// game id, season, day
+    games: Vec<(String, i64, i64)>,
+    /// index into `games`, time, text
+    updates: Vec<(u32, u32, String)>,
+    /// word:indices into `updates` that have it, in order
+    words: BTreeMap<String, Vec<u32>>,
+}
+
+impl SearchIndex {
+    pub fn new() -> SearchIndex {
+        SearchIndex::default()
+    }
+
+    pub fn add_game(&mut self, id: &str, season: i64, day: i64, texts: &[(u32, String)]) {
+        let game = self.games.len() as u32;
+        self.games.push((id.to_owned(), season, day));
+
+        for (time, text) in texts {
+            let update = self.updates.len() as u32;
+            self.updates.push((game, *time, text.clone()));
+
+            let mut words = tokenize(text);
+            words.sort_unstable();
+            words.dedup();
+            for word in words {
+                self.words.entry(word).or_default().push(update);
+            }
+        }
+    }
+
+    /// Updates that have a query word, in order.
+    fn postings(&self, word: &QueryWord) -> Vec<u32> {
+        if !word.prefix {
+            return self.words.get(&word.word).cloned().unwrap_or_default();
+        }
+
+        let mut updates: Vec<u32> = self
+            .words
+            .range(word.word.clone()..)
+            .take_while(|(w, _)| w.starts_with(&word.word))
+            .flat_map(|(_, updates)| updates.iter().copied())
+            .collect();
+        updates.sort_unstable();
+        updates.dedup();
+        updates
+    }
+
+    /// Every update matching the query and the filter's season, day and times, oldest first. The filter's game fields aren't checked here, since that takes the game filter index.
+    pub fn search(&self, query: &SearchQuery, filter: &SearchFilter) -> Vec<SearchHit> {
+        // start from the rarest word, so there's less to intersect
+        let mut postings: Vec<Vec<u32>> = query
+            .phrases
+            .iter()
+            .flatten()
+            .map(|word| self.postings(word))
+            .collect();
+        postings.sort_by_key(|p| p.len());
+
+        let mut candidates = match postings.first() {
+            Some(first) => first.clone(),
+            None => return Vec::new(),
+        };
+        for other in &postings[1..] {
+            candidates.retain(|u| other.binary_search(u).is_ok());
+        }
+
+        let mut hits: Vec<SearchHit> = candidates
+            .into_iter()
+            .filter_map(|u| {
+                let (game, time, text) = &self.updates[u as usize];
+                let (game_id, season, day) = &self.games[*game as usize];
+
+                if filter.season.map_or(false, |s| s as i64 != *season)
+                    || filter.day.map_or(false, |d| d as i64 != *day)
+                    || filter.after.map_or(false, |t| *time < t)
+                    || filter.before.map_or(false, |t| *time > t)
+                    || !query.matches(&tokenize(text))
+                {
+                    return None;
+                }
+
+                Some(SearchHit {
+                    game_id: game_id.clone(),
+                    season: *season,
+                    day: *day,
+                    timestamp: Utc.timestamp(*time as i64, 0),
+                    text: text.clone(),
+                })
+            })
+            .collect();
+        hits.sort_by(|a, b| (a.timestamp, &a.game_id).cmp(&(b.timestamp, &b.game_id)));
+
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word(word: &str, prefix: bool) -> QueryWord {
+        QueryWord {
+            word: word.to_owned(),
+            prefix,
+        }
+    }
+
+    fn index() -> SearchIndex {
+        let mut index = SearchIndex::new();
+        index.add_game(
+            "game-a",
+            0,
+            0,
+            &[
+                (
+                    10,
+                    "Home run! Jessica Telephone hits a solo home run.".to_owned(),
+                ),
+                (20, "Strike, swinging. 0-1".to_owned()),
+                (30, "Strikes out looking. Strike three.".to_owned()),
+            ],
+        );
+        index.add_game(
+            "game-b",
+            1,
+            4,
+            &[
+                (15, "Run scores on the fielder's choice.".to_owned()),
+                (25, "Home team run totals: 3".to_owned()),
+            ],
+        );
+        index
+    }
+
+    fn texts(hits: &[SearchHit]) -> Vec<&str> {
+        hits.iter().map(|hit| hit.text.as_str()).collect()
+    }
+
+    #[test]
+    fn parse_splits_words_and_phrases() {
+        let query = SearchQuery::parse(r#"  "home run"   Telephone "#);
+        assert_eq!(
+            query.phrases,
+            vec![
+                vec![word("home", false), word("run", false)],
+                vec![word("telephone", false)],
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_unclosed_quote_is_a_phrase() {
+        let query = SearchQuery::parse(r#"solo "home run"#);
+        assert_eq!(
+            query.phrases,
+            vec![
+                vec![word("solo", false)],
+                vec![word("home", false), word("run", false)],
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_prefix_only_applies_to_the_last_token() {
+        let query = SearchQuery::parse("strike* o'bri* hom*e");
+        assert_eq!(
+            query.phrases,
+            vec![
+                vec![word("strike", true)],
+                vec![word("o", false), word("bri", true)],
+                vec![word("hom", false), word("e", false)],
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_punctuation_splits_words() {
+        let query = SearchQuery::parse(r#"fielder's "0-1, swinging""#);
+        assert_eq!(
+            query.phrases,
+            vec![
+                vec![word("fielder", false), word("s", false)],
+                vec![word("0", false), word("1", false), word("swinging", false)],
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_nothing_to_search_for() {
+        for query in ["", "   ", r#""""#, "* ! ...", r#"" - ""#] {
+            assert!(SearchQuery::parse(query).is_empty(), "{:?}", query);
+        }
+    }
+
+    #[test]
+    fn search_intersects_every_word() {
+        let index = index();
+        let hits = index.search(&SearchQuery::parse("home run"), &SearchFilter::default());
+        assert_eq!(
+            texts(&hits),
+            vec![
+                "Home run! Jessica Telephone hits a solo home run.",
+                "Home team run totals: 3",
+            ]
+        );
+
+        let hits = index.search(
+            &SearchQuery::parse("telephone strike"),
+            &SearchFilter::default(),
+        );
+        assert!(hits.is_empty());
+
+        let hits = index.search(&SearchQuery::parse("home nobody"), &SearchFilter::default());
+        assert!(hits.is_empty());
+    }
+
+    #[test]
+    fn search_phrases_need_adjacent_words() {
+        let index = index();
+        let hits = index.search(
+            &SearchQuery::parse(r#""home run""#),
+            &SearchFilter::default(),
+        );
+        assert_eq!(
+            texts(&hits),
+            vec!["Home run! Jessica Telephone hits a solo home run."]
+        );
+    }
+
+    #[test]
+    fn search_prefix_merges_postings_once() {
+        let index = index();
+        assert_eq!(index.postings(&word("strike", true)), vec![1, 2]);
+
+        let hits = index.search(&SearchQuery::parse("strike*"), &SearchFilter::default());
+        assert_eq!(
+            texts(&hits),
+            vec![
+                "Strike, swinging. 0-1",
+                "Strikes out looking. Strike three."
+            ]
+        );
+    }
+
+    #[test]
+    fn search_applies_filter_and_sorts_by_time() {
+        let index = index();
+        let query = SearchQuery::parse("run");
+
+        let hits = index.search(&query, &SearchFilter::default());
+        let times: Vec<i64> = hits.iter().map(|hit| hit.timestamp.timestamp()).collect();
+        assert_eq!(times, vec![10, 15, 25]);
+
+        let filter = SearchFilter {
+            season: Some(1),
+            after: Some(20),
+            ..Default::default()
+        };
+        let hits = index.search(&query, &filter);
+        assert_eq!(texts(&hits), vec!["Home team run totals: 3"]);
+        assert_eq!((hits[0].game_id.as_str(), hits[0].day), ("game-b", 4));
+    }
+}
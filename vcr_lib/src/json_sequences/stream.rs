@@ -0,0 +1,766 @@
+use crate::{ChroniclerEntity, GameDate, MultiDatabase, VCRResult};
+use serde_json::{json, Map, Value as JSONValue};
+use std::collections::{HashMap, HashSet};
+
+fn clamp(input: u32, min: u32, max: u32) -> u32 {
+    if input < min {
+        min
+    } else if input > max {
+        max
+    } else {
+        input
+    }
+}
+
+/// The id frames are stored under in the stream tape, which only has the one entity.
+pub const STREAM_ID: &str = "00000000-0000-0000-0000-000000000000";
+
+/// Objects and arrays bigger than this (as JSON) get split up by `flatten_frame`. Tapes store values with a 16-bit length, and whole frames are a lot bigger than that.
+const FLATTEN_OVER: usize = 32 * 1024;
+
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+fn unescape(key: &str) -> String {
+    key.replace("~1", "/").replace("~0", "~")
+}
+
+fn flatten_into(pointer: String, value: &JSONValue, flat: &mut Map<String, JSONValue>) {
+    let too_big = match value {
+        JSONValue::Object(_) | JSONValue::Array(_) => {
+            serde_json::to_vec(value).map_or(0, |v| v.len()) > FLATTEN_OVER
+        }
+        _ => false,
+    };
+
+    match value {
+        JSONValue::Object(map) if too_big => {
+            flat.insert(pointer.clone(), json!({}));
+            for (key, child) in map {
+                flatten_into(format!("{}/{}", pointer, escape(key)), child, flat);
+            }
+        }
+        JSONValue::Array(items) if too_big => {
+            flat.insert(pointer.clone(), json!([]));
+            for (i, child) in items.iter().enumerate() {
+                flatten_into(format!("{}/{}", pointer, i), child, flat);
+            }
+        }
+        _ => {
+            flat.insert(pointer, value.clone());
+        }
+    }
+}
+
+/// Turns a frame into a map of JSON pointers to values, splitting up objects and arrays too big to store in one piece. Small ones are kept whole, so patches between frames still only touch what changed.
+pub fn flatten_frame(frame: &JSONValue) -> JSONValue {
+    let mut flat = Map::new();
+    flatten_into(String::new(), frame, &mut flat);
+    JSONValue::Object(flat)
+}
+
+/// Puts a frame split up by `flatten_frame` back together.
+pub fn unflatten_frame(flat: &JSONValue) -> JSONValue {
+    let mut entries: Vec<(&String, &JSONValue)> = flat
+        .as_object()
+        .map(|map| map.iter().collect())
+        .unwrap_or_default();
+    // parents have to be in place before their children
+    entries.sort_by_key(|(pointer, _)| pointer.matches('/').count());
+
+    let mut frame = JSONValue::Null;
+    for (pointer, value) in entries {
+        let (parent, key) = match pointer.rsplit_once('/') {
+            Some(split) => split,
+            None => {
+                frame = value.clone();
+                continue;
+            }
+        };
+
+        match frame.pointer_mut(parent) {
+            Some(JSONValue::Object(map)) => {
+                map.insert(unescape(key), value.clone());
+            }
+            Some(JSONValue::Array(items)) => {
+                if let Ok(i) = key.parse::<usize>() {
+                    if items.len() <= i {
+                        items.resize(i + 1, JSONValue::Null);
+                    }
+                    items[i] = value.clone();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    frame
+}
+
+/// When a version became current and when it stops being, as returned by `Database::version_span`.
+type Span = (u32, Option<u32>);
+
+/// Builds `streamData` frames, keeping every entity in the last frame along with the version it was at. Each frame only refetches the entities that changed since the one before, so building frames in order is much cheaper than building each from scratch, and comes out the same.
+pub struct StreamBuilder<'a> {
+    db: &'a MultiDatabase,
+    entities: HashMap<(String, String), (Span, ChroniclerEntity<JSONValue>)>, // (type, id):(version, entity)
+    bets: HashMap<String, (Span, JSONValue)>, // game_id:(version, game)
+    // what the frame being built used, so whatever it didn't can be dropped once it's done
+    used_entities: HashSet<(String, String)>,
+    used_bets: HashSet<String>,
+    // the frame being built: its time, and the span every entity in it shares
+    at: u32,
+    span: Span,
+}
+
+impl<'a> StreamBuilder<'a> {
+    pub fn new(db: &'a MultiDatabase) -> StreamBuilder<'a> {
+        StreamBuilder {
+            db,
+            entities: HashMap::new(),
+            bets: HashMap::new(),
+            used_entities: HashSet::new(),
+            used_bets: HashSet::new(),
+            at: 0,
+            span: (0, None),
+        }
+    }
+
+    /// When the last frame built became current and when it stops being: the last change to anything in it up to its time, and the first one after.
+    pub fn frame_span(&self) -> (u32, Option<u32>) {
+        self.span
+    }
+
+    /// Narrows the frame's span down to an entity's, fetched at `at`.
+    fn track(&mut self, (from, to): Span, at: u32) {
+        // clamped lookups see the same version at every time before the one they're clamped to
+        let from = if at > self.at { 0 } else { from };
+        self.span.0 = self.span.0.max(from);
+        self.span.1 = match (self.span.1, to) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+    }
+
+    fn entity(
+        &mut self,
+        e_type: &str,
+        id: &str,
+        at: u32,
+    ) -> VCRResult<ChroniclerEntity<JSONValue>> {
+        Ok(self
+            .entities(e_type, vec![id.to_owned()], at)?
+            .pop()
+            .unwrap())
+    }
+
+    /// Entities as of `at`, in the order asked for. Ones whose version moved since they were last fetched get fetched again, in parallel.
+    fn entities(
+        &mut self,
+        e_type: &str,
+        ids: Vec<String>,
+        at: u32,
+    ) -> VCRResult<Vec<ChroniclerEntity<JSONValue>>> {
+        let mut stale: Vec<(String, Span)> = Vec::new();
+        for id in &ids {
+            let version = self.db.version_span(e_type, id, at)?;
+            self.track(version, at);
+            self.used_entities
+                .insert((e_type.to_owned(), id.to_owned()));
+            if self
+                .entities
+                .get(&(e_type.to_owned(), id.to_owned()))
+                .map_or(true, |(v, _)| *v != version)
+            {
+                stale.push((id.to_owned(), version));
+            }
+        }
+
+        if !stale.is_empty() {
+            let fetched = self.db.get_entities(
+                e_type,
+                stale.iter().map(|(id, _)| id.to_owned()).collect(),
+                at,
+            )?;
+            for ((id, version), entity) in stale.into_iter().zip(fetched) {
+                self.entities
+                    .insert((e_type.to_owned(), id), (version, entity));
+            }
+        }
+
+        Ok(ids
+            .into_iter()
+            .map(|id| self.entities[&(e_type.to_owned(), id)].1.clone())
+            .collect())
+    }
+
+    /// Every entity of a type as of `at`, in the same order as `MultiDatabase::all_entities`.
+    fn all_entities(
+        &mut self,
+        e_type: &str,
+        at: u32,
+    ) -> VCRResult<Vec<ChroniclerEntity<JSONValue>>> {
+        let ids = self.db.all_ids(e_type)?;
+        self.entities(e_type, ids, at)
+    }
+
+    /// A day's games as of `at`.
+    fn schedule(&mut self, date: &GameDate, at: u32) -> VCRResult<Vec<JSONValue>> {
+        let ids: Vec<String> = self
+            .db
+            .game_index
+            .get(date)
+            .map(|games| games.iter().map(|(id, _, _)| id.to_owned()).collect())
+            .unwrap_or_default();
+
+        Ok(self
+            .entities("game_updates", ids, at)?
+            .into_iter()
+            .map(|g| g.data)
+            .collect())
+    }
+
+    /// A day's games as `MultiDatabase::games_for_bets` returns them.
+    fn bets(&mut self, date: &GameDate, at: u32) -> VCRResult<Vec<JSONValue>> {
+        let db = self.db;
+        let mut games = Vec::new();
+        for (id, _, _) in db.game_index.get(date).unwrap_or(&Vec::new()) {
+            let version = db.version_span("game_updates", id, at)?;
+            self.track(version, at);
+            self.used_bets.insert(id.to_owned());
+            match self.bets.get(id) {
+                Some((v, game)) if *v == version => games.push(game.clone()),
+                _ => {
+                    let game = db.game_for_bets(id, at)?;
+                    self.bets.insert(id.to_owned(), (version, game.clone()));
+                    games.push(game);
+                }
+            }
+        }
+
+        Ok(games)
+    }
+
+    fn playoffs(&mut self, id: &str, round: Option<i64>, at: u32) -> VCRResult<JSONValue> {
+        let playoffs = self.entity("playoffs", id, at)?.data;
+        let round_number = round.unwrap_or_else(|| playoffs["round"].as_i64().unwrap());
+
+        let round_ids: Vec<String> = playoffs["rounds"]
+            .as_array()
+            .unwrap_or(&Vec::new())
+            .iter()
+            .map(|x| x.as_str().unwrap().to_owned())
+            .collect();
+        let all_rounds: Vec<JSONValue> = self
+            .entities("playoffround", round_ids, at)?
+            .into_iter()
+            .map(|t| t.data)
+            .filter(|t| t != &json!({}))
+            .collect();
+        let tomorrow_round: JSONValue = all_rounds
+            .iter()
+            .find(|r| r["roundNumber"] == playoffs["tomorrowRound"])
+            .cloned()
+            .unwrap_or(json!({}));
+        let round: JSONValue = all_rounds
+            .iter()
+            .find(|r| r["roundNumber"].as_i64().unwrap() == round_number)
+            .cloned()
+            .unwrap_or(json!({}));
+
+        let main_matchup_ids: Vec<String> = round["matchups"]
+            .as_array()
+            .unwrap_or(&Vec::new())
+            .iter()
+            .map(|x| x.as_str().unwrap().to_owned())
+            .collect();
+        let main_matchups: Vec<JSONValue> = self
+            .entities("playoffmatchup", main_matchup_ids, at)?
+            .into_iter()
+            .map(|t| t.data)
+            .filter(|t| t != &json!({}))
+            .collect();
+
+        let tomorrow_matchup_ids: Vec<String> = tomorrow_round["matchups"]
+            .as_array()
+            .unwrap_or(&Vec::new())
+            .iter()
+            .map(|x| x.as_str().unwrap().to_owned())
+            .collect();
+        let tomorrow_matchups: Vec<JSONValue> = self
+            .entities("playoffmatchup", tomorrow_matchup_ids, at)?
+            .into_iter()
+            .map(|t| t.data)
+            .filter(|t| t != &json!({}))
+            .collect();
+
+        let all_matchups_ids: Vec<String> = all_rounds
+            .iter()
+            .flat_map(|x| {
+                x["matchups"]
+                    .as_array()
+                    .unwrap_or(&Vec::new())
+                    .iter()
+                    .map(|x| x.as_str().unwrap().to_owned())
+                    .collect::<Vec<String>>()
+            })
+            .collect();
+
+        let all_matchups: Vec<JSONValue> = self
+            .entities("playoffmatchup", all_matchups_ids, at)?
+            .into_iter()
+            .map(|t| t.data)
+            .filter(|t| t != &json!({}))
+            .collect();
+
+        Ok(json!({
+            "round": round,
+            "matchups": main_matchups,
+            "playoffs": playoffs,
+            "allRounds": all_rounds,
+            "allMatchups": all_matchups,
+            "tomorrowRound": tomorrow_round,
+            "tomorrowMatchups": tomorrow_matchups
+        }))
+    }
+
+    /// The `streamData` frame as of `at`. Read from the stream tape when there is one, and put together from every other tape otherwise.
+    pub fn frame(&mut self, at: u32) -> VCRResult<JSONValue> {
+        self.at = at;
+        self.span = (0, None);
+
+        if self.db.dbs.contains_key("stream") {
+            let span = self.db.version_span("stream", STREAM_ID, at)?;
+            self.track(span, at);
+            let flat = self.db.get_entity("stream", STREAM_ID, at)?;
+            return Ok(unflatten_frame(&flat.data));
+        }
+
+        let frame = self.assemble(at);
+
+        // games from past days and the like would pile up otherwise
+        let (used_entities, used_bets) = (
+            std::mem::take(&mut self.used_entities),
+            std::mem::take(&mut self.used_bets),
+        );
+        self.entities.retain(|key, _| used_entities.contains(key));
+        self.bets.retain(|id, _| used_bets.contains(id));
+
+        frame
+    }
+
+    fn assemble(&mut self, at: u32) -> VCRResult<JSONValue> {
+        let sim = self.entity("sim", "00000000-0000-0000-0000-000000000000", at)?;
+
+        let mut date = GameDate {
+            season: sim.data.get("season").unwrap().as_i64().unwrap() as i32,
+            day: sim.data.get("day").unwrap().as_i64().unwrap() as i32,
+            tournament: if sim.data.get("season") == Some(&json!(10))
+                && sim.data["day"].as_i64().unwrap() < 100
+                && sim.data.get("tournament").is_none()
+            {
+                Some(-1)
+            } else {
+                sim.data
+                    .get("tournament")
+                    .map(|x| x.as_i64().unwrap() as i32)
+            },
+        };
+
+        if let Some(i) = date.tournament {
+            if i != -1 {
+                date.season = -1;
+            }
+        }
+
+        let schedule: JSONValue = if sim
+            .data
+            .get("phase")
+            .unwrap_or(&json!(-1))
+            .as_i64()
+            .unwrap()
+            == 14
+            && date.season == 22
+        {
+            json!([self
+                .entity("game_updates", "d162b23a-9832-4e78-8d78-5d131393fd61", at)?
+                .data])
+        } else {
+            json!(self.schedule(&date, at)?)
+        };
+
+        date.day += 1;
+
+        let tomorrow_schedule: Vec<JSONValue> = self
+            .bets(&date, at)?
+            .into_iter()
+            .filter(|g| g != &json!({}))
+            .collect();
+
+        let season = self
+            .all_entities("season", at)?
+            .into_iter()
+            .find(|s| s.data["seasonNumber"] == sim.data["season"])
+            .unwrap();
+
+        let standings = self.entity("standings", season.data["standings"].as_str().unwrap(), at)?;
+
+        let mut leagues: Vec<JSONValue> = self
+            .all_entities("league", clamp(at, 1599169238, u32::MAX))?
+            .into_iter()
+            .map(|t| t.data)
+            .filter(|t| t != &json!({}))
+            .collect();
+
+        let subleague_ids: Vec<String> = leagues
+            .iter()
+            .flat_map(|x| {
+                x["subleagues"]
+                    .as_array()
+                    .unwrap_or(&Vec::new())
+                    .iter()
+                    .map(|x| x.as_str().unwrap().to_owned())
+                    .collect::<Vec<String>>()
+            })
+            .collect();
+
+        let tiebreaker_ids: Vec<String> = leagues
+            .iter()
+            .map(|x| {
+                x.get("tiebreakers")
+                    .unwrap_or(&json!(""))
+                    .as_str()
+                    .unwrap()
+                    .to_owned()
+            })
+            .collect();
+
+        let mut subleagues: Vec<JSONValue> = self
+            .entities("subleague", subleague_ids, clamp(at, 1599169238, u32::MAX))?
+            .into_iter()
+            .map(|s| s.data)
+            .filter(|s| s != &json!({}))
+            .collect();
+
+        let division_ids: Vec<String> = subleagues
+            .iter()
+            .flat_map(|x| {
+                x["divisions"]
+                    .as_array()
+                    .unwrap_or(&Vec::new())
+                    .iter()
+                    .map(|x| x.as_str().unwrap().to_owned())
+                    .collect::<Vec<String>>()
+            })
+            .collect();
+
+        let mut divisions: Vec<JSONValue> = self
+            .entities("division", division_ids, clamp(at, 1599169238, u32::MAX))?
+            .into_iter()
+            .map(|d| d.data)
+            .filter(|d| d != &json!({}))
+            .collect();
+
+        let mut tiebreakers: Vec<JSONValue> = self
+            .entities(
+                "tiebreakers",
+                tiebreaker_ids,
+                clamp(at, 1599760290, u32::MAX),
+            )?
+            .into_iter()
+            .map(|t| t.data)
+            .filter(|t| t != &json!({}))
+            .collect();
+
+        if at < 1598224980 {
+            for d in &mut divisions {
+                if let Some(id) = d.get("id") {
+                    d["_id"] = id.clone();
+                }
+            }
+
+            for d in &mut subleagues {
+                if let Some(id) = d.get("id") {
+                    d["_id"] = id.clone();
+                }
+            }
+
+            for d in &mut leagues {
+                if let Some(id) = d.get("id") {
+                    d["_id"] = id.clone();
+                }
+            }
+
+            for d in &mut tiebreakers {
+                if let Some(id) = d.get("id") {
+                    d["_id"] = id.clone();
+                }
+            }
+        }
+
+        let teams: Vec<JSONValue> = self
+            .all_entities("team", at)?
+            .into_iter()
+            .map(|t| t.data)
+            .filter(|t| t != &json!({}))
+            .collect();
+
+        let fights: Vec<JSONValue> = self
+            .all_entities("bossfight", at)?
+            .into_iter()
+            .map(|b| b.data)
+            .filter(|b| b != &json!({}) && b["homeHp"] != json!("0") && b["awayHp"] != json!("0"))
+            .collect();
+
+        let stadiums: Vec<JSONValue> = self
+            .all_entities("stadium", at)?
+            .into_iter()
+            .map(|s| s.data)
+            .filter(|s| s != &json!({}))
+            .collect();
+
+        let temporal = self.entity("temporal", "00000000-0000-0000-0000-000000000000", at)?;
+
+        let sunsun = self.entity("sunsun", "00000000-0000-0000-0000-000000000000", at)?;
+
+        let communitychest = self.entity(
+            "communitychestprogress",
+            "00000000-0000-0000-0000-000000000000",
+            at,
+        )?;
+
+        let tournament = if let Some(tourn_idx) = date.tournament {
+            if tourn_idx > -1 {
+                self.all_entities("tournament", at)?
+                    .into_iter()
+                    .last()
+                    .map_or(json!({}), |x| x.data)
+            } else {
+                json!({})
+            }
+        } else {
+            json!({})
+        };
+
+        let (playoff_key, playoffs): (&str, JSONValue) = if tournament != json!({}) {
+            (
+                "postseason",
+                self.playoffs(
+                    tournament["playoffs"].as_str().unwrap(),
+                    sim.data.get("tournamentRound").map(|i| i.as_i64().unwrap()),
+                    at,
+                )?,
+            )
+        } else if let Some(playoff_ids) = sim.data["playoffs"].as_array() {
+            let mut playoffs: Vec<JSONValue> = Vec::new();
+            for id in playoff_ids {
+                playoffs.push(self.playoffs(id.as_str().unwrap(), None, at)?);
+            }
+            ("postseasons", json!(playoffs))
+        } else if let Some(playoff_id) = sim.data["playoffs"].as_str() {
+            (
+                "postseason",
+                self.playoffs(
+                    playoff_id,
+                    sim.data.get("playOffRound").map(|i| i.as_i64().unwrap()),
+                    at,
+                )?,
+            )
+        } else {
+            ("postseason", json!({}))
+        };
+
+        // println!("---------------\n");
+
+        Ok(json!({
+            "value": {
+                "games": {
+                    "sim": sim.data,
+                    "season": season.data,
+                    "standings": standings.data,
+                    "schedule": schedule,
+                    "tomorrowSchedule": tomorrow_schedule,
+                    "tournament": tournament,
+                    playoff_key: playoffs
+                },
+                "leagues": {
+                    "stats": {
+                        "sunsun": sunsun.data,
+                        "communityChest": communitychest.data
+                    },
+                    "teams": teams,
+                    "subleagues": subleagues,
+                    "divisions": divisions,
+                    "leagues": leagues,
+                    "tiebreakers": tiebreakers,
+                    "stadiums": stadiums
+                },
+                "fights": {
+                    "bossFights": fights
+                },
+                "temporal": temporal.data
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_sequences::encoder::{PatchEncoder, ValueTable};
+    use crate::json_sequences::testing::{id, stream_world, tape_from_patches, WORLD_START};
+
+    /// A string long enough that two of them put an object or array over `FLATTEN_OVER`.
+    fn filler(n: usize) -> JSONValue {
+        json!(format!("{}", n).repeat(FLATTEN_OVER / 2))
+    }
+
+    fn round_trip(frame: JSONValue) -> Map<String, JSONValue> {
+        let flat = flatten_frame(&frame);
+        assert_eq!(unflatten_frame(&flat), frame);
+        flat.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn small_frames_stay_whole() {
+        let frame = json!({ "value": { "games": { "sim": { "season": 11, "day": 98 } } } });
+        let flat = round_trip(frame.clone());
+        assert_eq!(flat.len(), 1);
+        assert_eq!(flat[""], frame);
+    }
+
+    #[test]
+    fn big_objects_and_arrays_get_split() {
+        let frame = json!({
+            "value": {
+                "games": {
+                    "sim": { "season": 11, "day": 98 },
+                    "schedule": (0..10).map(|i| json!({ "id": i, "text": filler(i) })).collect::<Vec<_>>(),
+                },
+                "leagues": { "stats": { "sunsun": { "current": 1 } } },
+                "empty": {},
+                "nothing": [],
+            }
+        });
+        let flat = round_trip(frame);
+
+        assert_eq!(flat[""], json!({}));
+        assert_eq!(flat["/value/games"], json!({}));
+        assert_eq!(flat["/value/games/schedule"], json!([]));
+        // small parts of a split value are kept whole
+        assert_eq!(
+            flat["/value/games/schedule/3"],
+            json!({ "id": 3, "text": filler(3) })
+        );
+        assert_eq!(flat["/value/games/sim"], json!({ "season": 11, "day": 98 }));
+        assert_eq!(
+            flat["/value/leagues"],
+            json!({ "stats": { "sunsun": { "current": 1 } } })
+        );
+        assert_eq!(flat["/value/empty"], json!({}));
+        assert_eq!(flat["/value/nothing"], json!([]));
+    }
+
+    #[test]
+    fn keys_with_slashes_and_tildes() {
+        let frame = json!({
+            "a/b": { "~": filler(1), "~1": filler(2), "/~0/": filler(3) },
+            "~0": [filler(4), { "c/d": filler(5), "e~f": filler(6) }, filler(7)],
+            "": { "": filler(8), "x": filler(9) },
+            "plain": filler(10),
+        });
+        let flat = round_trip(frame);
+
+        assert!(flat.contains_key("/a~1b/~0"));
+        assert!(flat.contains_key("/a~1b/~01"));
+        assert!(flat.contains_key("/a~1b/~1~00~1"));
+        assert!(flat.contains_key("/~00/1/c~1d"));
+        assert!(flat.contains_key("/~00/1/e~0f"));
+        assert!(flat.contains_key("//"));
+    }
+
+    #[test]
+    fn arrays_of_more_than_ten_come_back_in_order() {
+        // pointers sort "10" before "2", so the array gets filled in out of order
+        let frame = json!((0..12).map(filler).collect::<Vec<_>>());
+        let flat = round_trip(frame);
+        assert_eq!(flat.len(), 13);
+    }
+
+    #[test]
+    fn consecutive_frames_match_fresh_ones() {
+        let db = stream_world();
+        let mut builder = StreamBuilder::new(&db);
+
+        // across every change, and past the last one
+        for at in (WORLD_START..WORLD_START + 300).step_by(10) {
+            let frame = builder.frame(at).unwrap();
+            assert_eq!(frame, db.stream_data(at).unwrap(), "at {}", at);
+            assert_eq!(
+                frame["value"]["games"]["sim"]["day"],
+                ((at - WORLD_START) / 100).min(2)
+            );
+        }
+
+        // only what's in the last frame is kept: today's game and tomorrow's, not the ones from days before
+        let games: HashSet<&String> = builder
+            .entities
+            .keys()
+            .filter(|(e_type, _)| e_type == "game_updates")
+            .map(|(_, id)| id)
+            .collect();
+        assert_eq!(games, [id(102)].iter().collect());
+        let bets: HashSet<&String> = builder.bets.keys().collect();
+        assert_eq!(bets, [id(103)].iter().collect());
+    }
+
+    #[test]
+    fn frames_read_back_from_a_stream_tape() {
+        // written the way build_stream writes it
+        let world = stream_world();
+        let mut builder = StreamBuilder::new(&world);
+        let mut encoder: Option<PatchEncoder> = None;
+        let mut value_table = ValueTable::new();
+        let mut patches = Vec::new();
+        let mut at = WORLD_START;
+
+        loop {
+            let frame = flatten_frame(&builder.frame(at).unwrap());
+            let (time, mut patch) = encoder
+                .get_or_insert_with(|| PatchEncoder::new(&frame, 4))
+                .push(at, frame);
+            value_table.intern_ops(&mut patch);
+            patches.push((time, patch));
+
+            match builder.frame_span().1 {
+                Some(next) => at = next,
+                None => break,
+            }
+        }
+
+        let encoder = encoder.unwrap();
+        patches.push(encoder.last_version(at));
+        let (path_map, base) = encoder.finish();
+        let mut stream = tape_from_patches(
+            vec![(STREAM_ID.to_owned(), (patches, path_map, base))],
+            4,
+            100,
+        );
+        let mut values = Vec::new();
+        value_table.write(&mut values).unwrap();
+        stream.set_value_table(&values[..]).unwrap();
+
+        let mut db = stream_world();
+        db.dbs.insert("stream".to_owned(), stream);
+        let mut builder = StreamBuilder::new(&db);
+        for at in (WORLD_START..WORLD_START + 300).step_by(5) {
+            assert_eq!(
+                builder.frame(at).unwrap(),
+                world.stream_data(at).unwrap(),
+                "at {}",
+                at
+            );
+        }
+    }
+}
@@ -0,0 +1,293 @@
+// builds small tapes in memory for tests, the same way build_entities writes them
+
+use super::encoder::{encode, write_entity_table_entry};
+use crate::{archive::MappedSlice, write_counters_header, Database, HeaderEncoder};
+use crate::{CounterMapConfig, CounterMapDatabase, GameDate, MultiDatabase};
+use integer_encoding::VarIntWriter;
+use serde_json::{json, Value as JSONValue};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Seek, SeekFrom, Write};
+use uuid::Uuid;
+
+pub(crate) fn id(n: u128) -> String {
+    Uuid::from_u128(n).to_string()
+}
+
+/// Patches, path map and base value, as returned by `encode`.
+pub(crate) type Encoded = (Vec<(u32, Vec<Vec<u8>>)>, BTreeMap<u16, String>, JSONValue);
+
+/// Encodes every entity's versions (time, data; oldest first) into a tape and opens it.
+pub(crate) fn tape(
+    entities: Vec<(String, Vec<(u32, JSONValue)>)>,
+    checkpoint_every: u16,
+    cache_size: usize,
+) -> Database {
+    let encoded = entities
+        .into_iter()
+        .map(|(id, versions)| (id, encode(versions, checkpoint_every)))
+        .collect();
+    tape_from_patches(encoded, checkpoint_every, cache_size)
+}
+
+/// Writes already encoded entities into a tape and opens it.
+pub(crate) fn tape_from_patches(
+    entities: Vec<(String, Encoded)>,
+    checkpoint_every: u16,
+    cache_size: usize,
+) -> Database {
+    let mut out = tempfile::tempfile().unwrap();
+    let mut header: Vec<u8> = Vec::new();
+    let mut compressor = zstd::block::Compressor::new();
+    let mut position: u32 = 0;
+
+    for (id, (patches, path_map, base)) in entities {
+        let mut header_encoder =
+            HeaderEncoder::new(base, checkpoint_every, path_map, position, Vec::new()).unwrap();
+        let mut last_position = position;
+
+        for (time, patch) in patches {
+            header_encoder
+                .write_patch(time, position - last_position)
+                .unwrap();
+
+            let bytes = compressor.compress(&patch.concat(), 3).unwrap();
+            out.write_all(&bytes).unwrap();
+            last_position = position;
+            position += bytes.len() as u32;
+        }
+
+        write_entity_table_entry(
+            &mut header,
+            &Uuid::parse_str(&id).unwrap(),
+            position,
+            &header_encoder.release(),
+        )
+        .unwrap();
+    }
+
+    out.flush().unwrap();
+    out.seek(SeekFrom::Start(0)).unwrap();
+    let header = zstd::stream::encode_all(&header[..], 3).unwrap();
+
+    Database::from_parts(
+        &header[..],
+        MappedSlice::from_file(&out).unwrap(),
+        None,
+        cache_size,
+    )
+    .unwrap()
+}
+
+/// A counter map version: its time, and the (id, kind, count) of every key in it.
+pub(crate) type CounterVersion = (u32, Vec<(u128, u8, u64)>);
+
+/// Writes a counter map tape from every version's (time, counts), the same way encode_counters does, with a keyframe every `keyframe_every` records.
+/// Returns the tape without its keyframes loaded, and the keyframes file.
+pub(crate) fn counter_tape(
+    versions: &[CounterVersion],
+    keyframe_every: usize,
+    config: CounterMapConfig,
+) -> (CounterMapDatabase, Vec<u8>) {
+    let mut out: Vec<u8> = Vec::new();
+    let mut ids: HashMap<Uuid, (u32, u8)> = HashMap::new();
+    let mut times: Vec<(u32, u32, u32)> = Vec::new();
+    let mut vals: HashMap<Uuid, u64> = HashMap::new();
+    let mut last_seen: Vec<Uuid> = Vec::new();
+    let mut keyframes: Vec<u8> = Vec::new();
+
+    for (time, counts) in versions {
+        let start = out.len() as u32;
+        let keyframe = times.len() % keyframe_every == 0;
+        if keyframe {
+            keyframes.extend((times.len() as u32).to_be_bytes());
+        }
+
+        let mut seen: Vec<Uuid> = Vec::new();
+        for (id, kind, n) in counts {
+            let id = Uuid::from_u128(*id);
+            seen.push(id);
+            let l = ids.len() as u32 + 1;
+            let idx = ids.entry(id).or_insert((l, *kind)).0;
+
+            if keyframe || vals.get(&id) != Some(n) {
+                out.write_varint(idx).unwrap();
+                out.write_varint(*n).unwrap();
+                vals.insert(id, *n);
+            }
+        }
+
+        let removed: Vec<Uuid> = last_seen
+            .iter()
+            .filter(|id| !seen.contains(id))
+            .copied()
+            .collect();
+        last_seen = seen;
+
+        if !removed.is_empty() {
+            out.write_varint(0u32).unwrap();
+            out.write_varint(removed.len() as u32).unwrap();
+            for id in removed {
+                out.write_varint(ids[&id].0).unwrap();
+                vals.remove(&id);
+            }
+        }
+
+        times.push((*time, start, out.len() as u32 - start));
+    }
+
+    let mut ids: Vec<(Uuid, u32, u8)> = ids
+        .into_iter()
+        .map(|(id, (idx, kind))| (id, idx, kind))
+        .collect();
+    ids.sort_by_key(|(_, idx, _)| *idx);
+
+    let mut header: Vec<u8> = Vec::new();
+    write_counters_header(&mut header, &ids, &times).unwrap();
+
+    let mut file = tempfile::tempfile().unwrap();
+    file.write_all(&out).unwrap();
+    file.flush().unwrap();
+
+    let db =
+        CounterMapDatabase::from_parts(&header[..], MappedSlice::from_file(&file).unwrap(), config)
+            .unwrap();
+    (db, keyframes)
+}
+
+/// When `stream_world` starts. Late enough that the clamped league lookups in `StreamBuilder` don't come into it.
+pub(crate) const WORLD_START: u32 = 1_600_000_000;
+
+/// A small league with everything a `streamData` frame is put together from. The sim moves a day forward at `WORLD_START + 100` and `+ 200`, each day has a game (which scores partway through), and standings and a team change in between.
+pub(crate) fn stream_world() -> MultiDatabase {
+    let t = WORLD_START;
+    let one = |n: u128, data: JSONValue| (id(n), vec![(t, data)]);
+    let db = |entities: Vec<(String, Vec<(u32, JSONValue)>)>| tape(entities, 4, 100);
+
+    let mut dbs = HashMap::new();
+    dbs.insert(
+        "sim".to_owned(),
+        db(vec![(
+            id(0),
+            (0..3)
+                .map(|day| {
+                    (
+                        t + day * 100,
+                        json!({ "season": 11, "day": day, "phase": 2 }),
+                    )
+                })
+                .collect(),
+        )]),
+    );
+
+    let mut game_index = HashMap::new();
+    let mut games = Vec::new();
+    for day in 0..4 {
+        let game = id(100 + day as u128);
+        let update = |score: u32| {
+            json!({
+                "id": game,
+                "day": day,
+                "homeOdds": 0.5,
+                "awayOdds": 0.5,
+                "homeScore": score,
+            })
+        };
+        let start = t + day * 100;
+        games.push((game.clone(), vec![(t, update(0)), (start + 50, update(1))]));
+        game_index.insert(
+            GameDate {
+                day: day as i32,
+                season: 11,
+                tournament: None,
+            },
+            vec![(game, None, None)],
+        );
+    }
+    dbs.insert("game_updates".to_owned(), db(games));
+
+    dbs.insert(
+        "season".to_owned(),
+        db(vec![one(
+            200,
+            json!({ "seasonNumber": 11, "standings": id(201) }),
+        )]),
+    );
+    dbs.insert(
+        "standings".to_owned(),
+        db(vec![(
+            id(201),
+            vec![
+                (t, json!({ "wins": {} })),
+                (t + 150, json!({ "wins": { id(400): 1 } })),
+            ],
+        )]),
+    );
+    dbs.insert(
+        "league".to_owned(),
+        db(vec![one(
+            300,
+            json!({ "id": id(300), "subleagues": [id(301)], "tiebreakers": id(303) }),
+        )]),
+    );
+    dbs.insert(
+        "subleague".to_owned(),
+        db(vec![one(
+            301,
+            json!({ "id": id(301), "divisions": [id(302)] }),
+        )]),
+    );
+    dbs.insert(
+        "division".to_owned(),
+        db(vec![one(
+            302,
+            json!({ "id": id(302), "teams": [id(400), id(401)] }),
+        )]),
+    );
+    dbs.insert(
+        "tiebreakers".to_owned(),
+        db(vec![one(
+            303,
+            json!({ "id": id(303), "order": [id(400), id(401)] }),
+        )]),
+    );
+    dbs.insert(
+        "team".to_owned(),
+        db(vec![
+            (
+                id(400),
+                vec![
+                    (t, json!({ "id": id(400), "nickname": "Pies" })),
+                    (
+                        t + 120,
+                        json!({ "id": id(400), "nickname": "Pies", "shame": 1 }),
+                    ),
+                ],
+            ),
+            one(401, json!({ "id": id(401), "nickname": "Crabs" })),
+        ]),
+    );
+    dbs.insert(
+        "bossfight".to_owned(),
+        db(vec![one(500, json!({ "homeHp": "10", "awayHp": "10" }))]),
+    );
+    dbs.insert(
+        "stadium".to_owned(),
+        db(vec![one(600, json!({ "id": id(600) }))]),
+    );
+    for (e_type, data) in [
+        ("temporal", json!({ "doc": { "zeta": "..." } })),
+        ("sunsun", json!({ "current": 0 })),
+        ("communitychestprogress", json!({ "progress": "0.5" })),
+    ] {
+        dbs.insert(e_type.to_owned(), db(vec![one(0, data)]));
+    }
+
+    MultiDatabase {
+        dbs,
+        game_index,
+        game_filter_index: HashMap::new(),
+        game_summary_index: HashMap::new(),
+        counters: HashMap::new(),
+        search_index: None,
+    }
+}
@@ -1,283 +1,88 @@
-use crate::{hash_entities, read_u32, ChronV2EndpointKind, InternalPaging};
-use crate::{ChroniclerEntity, VCRResult};
-use chrono::{DateTime, NaiveDateTime, Utc};
-use integer_encoding::VarIntReader;
-use memmap2::{Mmap, MmapOptions};
-use serde_json::{json, value::RawValue, Value as JSONValue};
-use std::collections::HashMap;
-use std::convert::TryInto;
-use std::fs::File;
-use std::io::{BufReader, Read};
-use std::path::Path;
+use crate::{CounterKind, CounterMapConfig, CounterRank, CounterShape, CounterValue, ShapeFrom};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
 use uuid::Uuid;
 
 static TEAMS_EPOCH: u32 = 1623642600;
 
-pub struct TributesDatabase {
-    times: Vec<(u32, u32, u16)>,     // (time, start, length)
-    ids: HashMap<u16, (Uuid, bool)>, // (id_number, (id, is_team))
-    reader: Mmap,
-}
-
-impl TributesDatabase {
-    pub fn from_files<P: AsRef<Path> + std::fmt::Debug>(
-        header_path: P,
-        db_path: P,
-    ) -> VCRResult<TributesDatabase> {
-        let header_f = File::open(header_path)?;
-        let mut header_reader = BufReader::new(header_f);
-
-        let ids: HashMap<u16, (Uuid, bool)> = {
-            let mut ids = HashMap::new();
-            let ids_len = read_u32!(header_reader);
-            let mut bytes: Vec<u8> = vec![0; ids_len as usize];
-            header_reader.read_exact(&mut bytes)?;
-
-            while !bytes.is_empty() {
-                let uuid = Uuid::from_slice(&bytes.drain(..16).collect::<Vec<u8>>()).unwrap();
-                let id_bytes =
-                    u16::from_be_bytes(bytes.drain(..2).collect::<Vec<u8>>().try_into().unwrap());
-                let is_team = ((id_bytes >> 15) & 0xFFF) != 0;
-                let idx = id_bytes & 0xFFF;
-
-                ids.insert(idx, (uuid, is_team));
-            }
-
-            ids
-        };
-
-        let mut times: Vec<(u32, u32, u16)> = Vec::new();
-
-        loop {
-            let mut bytes: [u8; 10] = [0; 10];
-            if header_reader.read_exact(&mut bytes).is_err() {
-                break;
-            }
-
-            times.push((
-                u32::from_be_bytes(bytes[0..4].try_into().unwrap()),
-                u32::from_be_bytes(bytes[4..8].try_into().unwrap()),
-                u16::from_be_bytes(bytes[8..10].try_into().unwrap()),
-            ));
-        }
-
-        let main_file = File::open(db_path)?;
-
-        Ok(TributesDatabase {
-            times,
-            ids,
-            reader: unsafe { MmapOptions::new().populate().map(&main_file)? },
-        })
-    }
-
-    pub fn get_versions(
-        &self,
-        before: u32,
-        after: u32,
-    ) -> VCRResult<Vec<ChroniclerEntity<JSONValue>>> {
-        let mut vals: HashMap<(Uuid, bool), u64> = HashMap::new();
-        let mut versions: Vec<ChroniclerEntity<JSONValue>> = Vec::new();
-
-        for (time, start, length) in &self.times {
-            if time > &before {
-                break;
-            }
-
-            let mut bytes = &self.reader[*start as usize..(*start as usize + *length as usize)];
-
-            loop {
-                let idx = bytes.read_varint::<u16>();
-                if idx.is_err() {
-                    // todo: check specifically if it's an io::ErrorKind::UnexpectedEof
-                    break;
-                }
-
-                let idx = idx.unwrap();
-                if idx == 0 {
-                    let len = bytes.read_varint::<u8>()?;
-                    for _ in 0..len {
-                        let ridx = bytes.read_varint::<u16>()?;
-                        vals.remove(&self.ids[&ridx]);
-                    }
-                } else {
-                    let val = bytes.read_varint::<u64>()?;
-                    vals.insert(self.ids[&idx], val);
-                }
-            }
-
-            if time > &after {
-                versions.push(ChroniclerEntity {
-                    data: if time < &TEAMS_EPOCH {
-                        let mut players = vals
-                            .iter()
-                            .map(|(k, v)| {
-                                json!({
-                                    "playerId": k.0.to_string(),
-                                    "peanuts": v
-                                })
-                            })
-                            .collect::<Vec<JSONValue>>();
-                        players.sort_by_key(|v| v["peanuts"].as_u64().unwrap());
-                        players.reverse();
-                        json!(players)
-                    } else {
-                        let (teams, players): (
-                            Vec<(&(Uuid, bool), &u64)>,
-                            Vec<(&(Uuid, bool), &u64)>,
-                        ) = vals.iter().partition(|&(k, _)| k.1);
-                        let mut players: Vec<JSONValue> = players
-                            .iter()
-                            .map(|(k, v)| {
-                                json!({
-                                    "playerId": k.0.to_string(),
-                                    "peanuts": v
-                                })
-                            })
-                            .collect::<Vec<JSONValue>>();
-                        players.sort_by_key(|v| v["peanuts"].as_u64().unwrap());
-                        players.reverse();
-
-                        let mut teams: Vec<JSONValue> = teams
-                            .iter()
-                            .map(|(k, v)| {
-                                json!({
-                                    "teamId": k.0.to_string(),
-                                    "peanuts": v
-                                })
-                            })
-                            .collect::<Vec<JSONValue>>();
-                        teams.sort_by_key(|v| v["peanuts"].as_u64().unwrap());
-                        teams.reverse();
-
-                        json!({
-                            "teams": teams,
-                            "players": players,
-                        })
-                    },
-                    entity_id: Uuid::nil().to_string(),
-                    valid_from: DateTime::<Utc>::from_utc(
-                        NaiveDateTime::from_timestamp(*time as i64, 0),
-                        Utc,
-                    ),
-                    valid_to: None,
-                    hash: String::new(),
-                })
-            }
-        }
-
-        Ok(versions)
+/// Key kinds in the tributes tape.
+pub const TRIBUTE_PLAYER: u8 = 0;
+pub const TRIBUTE_TEAM: u8 = 1;
+
+/// Tributes as a counter map: peanuts per player, and per team too once teams got tributes.
+/// Shaped like Chronicler does it: a list of players before teams had tributes, an object with both after.
+pub fn tributes_config() -> CounterMapConfig {
+    CounterMapConfig {
+        kinds: vec![
+            CounterKind {
+                name: "player".to_owned(),
+                id_field: "playerId".to_owned(),
+                value_field: "peanuts".to_owned(),
+                group: "players".to_owned(),
+            },
+            CounterKind {
+                name: "team".to_owned(),
+                id_field: "teamId".to_owned(),
+                value_field: "peanuts".to_owned(),
+                group: "teams".to_owned(),
+            },
+        ],
+        shapes: vec![
+            ShapeFrom {
+                from: 0,
+                shape: CounterShape::List {
+                    kind: TRIBUTE_PLAYER,
+                },
+            },
+            ShapeFrom {
+                from: TEAMS_EPOCH,
+                // Chronicler has teams before players
+                shape: CounterShape::Grouped {
+                    order: vec![TRIBUTE_TEAM, TRIBUTE_PLAYER],
+                },
+            },
+        ],
     }
+}
 
-    pub fn get_entity(&self, at: u32) -> VCRResult<ChroniclerEntity<JSONValue>> {
-        let mut vals: HashMap<(Uuid, bool), u64> = HashMap::new();
-        let mut last_time = 0;
-
-        for (time, start, length) in &self.times {
-            last_time = *time;
-
-            let mut bytes = &self.reader[*start as usize..(*start as usize + *length as usize)];
-
-            loop {
-                let idx = bytes.read_varint::<u16>();
-                if idx.is_err() {
-                    // todo: check specifically if it's an io::ErrorKind::UnexpectedEof
-                    break;
-                }
-                let idx = idx.unwrap();
-                if idx == 0 {
-                    let len = bytes.read_varint::<u8>()?;
-                    for _ in 0..len {
-                        let ridx = bytes.read_varint::<u16>()?;
-                        vals.remove(&self.ids[&ridx]);
-                    }
-                } else {
-                    let val = bytes.read_varint::<u64>()?;
-                    vals.insert(self.ids[&idx], val);
-                }
-            }
+/// A player or team's peanut count from `valid_from` on, or `None` while they're not on the leaderboard.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PeanutCount {
+    pub valid_from: DateTime<Utc>,
+    pub peanuts: Option<u64>,
+}
 
-            if time >= &at {
-                break;
-            }
+impl From<CounterValue> for PeanutCount {
+    fn from(value: CounterValue) -> PeanutCount {
+        PeanutCount {
+            valid_from: value.valid_from,
+            peanuts: value.value,
         }
-
-        Ok(ChroniclerEntity {
-            data: if last_time < TEAMS_EPOCH {
-                let mut players = vals
-                    .iter()
-                    .map(|(k, v)| {
-                        json!({
-                            "playerId": k.0.to_string(),
-                            "peanuts": v
-                        })
-                    })
-                    .collect::<Vec<JSONValue>>();
-                players.sort_by_key(|v| v["peanuts"].as_u64().unwrap());
-                players.reverse();
-                json!(players)
-            } else {
-                let (teams, players): (Vec<(&(Uuid, bool), &u64)>, Vec<(&(Uuid, bool), &u64)>) =
-                    vals.iter().partition(|&(k, _)| k.1);
-                let mut players: Vec<JSONValue> = players
-                    .iter()
-                    .map(|(k, v)| {
-                        json!({
-                            "playerId": k.0.to_string(),
-                            "peanuts": v
-                        })
-                    })
-                    .collect::<Vec<JSONValue>>();
-                players.sort_by_key(|v| v["peanuts"].as_u64().unwrap());
-                players.reverse();
-
-                let mut teams: Vec<JSONValue> = teams
-                    .iter()
-                    .map(|(k, v)| {
-                        json!({
-                            "teamId": k.0.to_string(),
-                            "peanuts": v
-                        })
-                    })
-                    .collect::<Vec<JSONValue>>();
-                teams.sort_by_key(|v| v["peanuts"].as_u64().unwrap());
-                teams.reverse();
-
-                json!({
-                    "teams": teams,
-                    "players": players,
-                })
-            },
-            entity_id: Uuid::nil().to_string(),
-            valid_from: DateTime::<Utc>::from_utc(
-                NaiveDateTime::from_timestamp(last_time as i64, 0),
-                Utc,
-            ),
-            valid_to: None,
-            hash: String::new(),
-        })
     }
+}
 
-    pub fn fetch_page(
-        &self,
-        page: &mut InternalPaging<Box<RawValue>>,
-        count: usize,
-    ) -> VCRResult<Vec<ChroniclerEntity<Box<RawValue>>>> {
-        page.remaining_ids = vec![];
+/// Where a player or team sits on the leaderboard, among players or among teams.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TributeRank {
+    pub id: Uuid,
+    pub is_team: bool,
+    pub peanuts: u64,
+    pub rank: usize,
+    pub out_of: usize,
+    pub valid_from: DateTime<Utc>,
+}
 
-        if page.remaining_data.len() < count {
-            page.remaining_data = match page.kind {
-                ChronV2EndpointKind::Versions(before, after) => {
-                    self.get_versions(before, after).and_then(hash_entities)?
-                }
-                ChronV2EndpointKind::Entities(at) => {
-                    self.get_entity(at).and_then(|v| hash_entities(vec![v]))?
-                }
-            };
+impl From<CounterRank> for TributeRank {
+    fn from(rank: CounterRank) -> TributeRank {
+        TributeRank {
+            id: rank.id,
+            is_team: rank.kind == TRIBUTE_TEAM,
+            peanuts: rank.value,
+            rank: rank.rank,
+            out_of: rank.out_of,
+            valid_from: rank.valid_from,
         }
-
-        Ok(page
-            .remaining_data
-            .drain(..std::cmp::min(count, page.remaining_data.len()))
-            .collect())
     }
 }
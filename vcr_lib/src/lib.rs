@@ -1,9 +1,11 @@
+pub mod archive;
 mod err;
 mod json_sequences;
 pub mod site;
 #[macro_use]
 pub mod utils;
 pub mod feed;
+pub mod stats;
 pub use err::*;
 pub use json_sequences::*;
 
@@ -49,7 +51,7 @@ pub enum ChronV2EndpointKind {
     Entities(u32),
 }
 
-#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(rename_all = "camelCase")]
 pub struct GameDate {
     pub day: i32,
@@ -1,8 +1,9 @@
 use super::chron::*;
 use super::*;
+use crate::archive::{Archive, MappedSlice};
+use crate::utils::group_tape_files;
 use crate::*;
 use bsdiff::patch::patch;
-use memmap2::{Mmap, MmapOptions};
 use std::collections::HashMap;
 use std::fs::{read_dir, File};
 use std::io::{self, BufReader};
@@ -10,53 +11,38 @@ use std::path::{Path, PathBuf};
 
 pub struct ResourceManager {
     headers: HashMap<String, EncodedResource>,
-    resources: HashMap<String, Mmap>,
+    resources: HashMap<String, MappedSlice>,
 }
 
 impl ResourceManager {
     // type, header, main file
     pub fn from_folder<P: AsRef<Path>>(folder: P) -> VCRResult<ResourceManager> {
-        let (mut header_paths, mut db_paths): (Vec<PathBuf>, Vec<PathBuf>) = read_dir(folder)?
+        let files: Vec<(String, PathBuf)> = read_dir(folder)?
             .map(|res| res.map(|e| e.path()))
             .collect::<Result<Vec<PathBuf>, io::Error>>()?
             .into_iter()
             .filter(|path| path.is_file())
-            .partition(|path| {
-                if let Some(name) = path.file_name() {
-                    name.to_str().unwrap().contains(".header.riv")
-                } else {
-                    false
-                }
-            });
-        header_paths.sort();
-        db_paths.sort();
-        let entries: Vec<(String, PathBuf, PathBuf)> = header_paths
-            .into_iter()
-            .zip(db_paths.into_iter())
-            .map(|(header, main)| {
-                let e_type = main
-                    .file_name()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .split_once('.')
-                    .unwrap()
-                    .0
-                    .to_owned();
-                (e_type, header, main)
+            .filter_map(|path| {
+                let name = path.file_name()?.to_str()?.to_owned();
+                Some((name, path))
             })
             .collect();
 
         let mut headers: HashMap<String, EncodedResource> = HashMap::new();
-        let mut resources: HashMap<String, Mmap> = HashMap::new();
+        let mut resources: HashMap<String, MappedSlice> = HashMap::new();
+
+        for (r_type, files) in group_tape_files(files) {
+            let (r_header, r_file) = match (files.header, files.main) {
+                (Some(header), Some(main)) => (header, main),
+                _ => continue,
+            };
 
-        for (r_type, r_header, r_file) in entries {
             let header_f = File::open(r_header)?;
             let header_r = BufReader::new(header_f);
             let header: EncodedResource = rmp_serde::from_read(header_r)?;
 
             let main_f = File::open(r_file)?;
-            let reader = unsafe { MmapOptions::new().populate().map(&main_f)? };
+            let reader = MappedSlice::from_file_populated(&main_f)?;
 
             resources.insert(r_type.to_owned(), reader);
             headers.insert(r_type.to_owned(), header);
@@ -67,14 +53,14 @@ impl ResourceManager {
 
     pub fn from_files(files: Vec<(&str, &str, &str)>) -> VCRResult<ResourceManager> {
         let mut headers: HashMap<String, EncodedResource> = HashMap::new();
-        let mut resources: HashMap<String, Mmap> = HashMap::new();
+        let mut resources: HashMap<String, MappedSlice> = HashMap::new();
 
         for (r_type, r_header, r_file) in files {
             let header_f = File::open(r_header)?;
             let header: EncodedResource = rmp_serde::from_read(header_f)?;
 
             let main_f = File::open(r_file)?;
-            let reader = unsafe { MmapOptions::new().populate().map(&main_f)? };
+            let reader = MappedSlice::from_file_populated(&main_f)?;
 
             resources.insert(r_type.to_owned(), reader);
             headers.insert(r_type.to_owned(), header);
@@ -83,6 +69,26 @@ impl ResourceManager {
         Ok(ResourceManager { headers, resources })
     }
 
+    /// Reads the site data stored in the `site_data/` folder of a .vcr archive.
+    pub fn from_archive(archive: &Archive) -> VCRResult<ResourceManager> {
+        let mut headers: HashMap<String, EncodedResource> = HashMap::new();
+        let mut resources: HashMap<String, MappedSlice> = HashMap::new();
+
+        for (r_type, files) in group_tape_files(archive.folder("site_data")) {
+            let (r_header, r_file) = match (files.header, files.main) {
+                (Some(header), Some(main)) => (header, main),
+                _ => continue,
+            };
+
+            let header: EncodedResource = rmp_serde::from_read(&r_header[..])?;
+
+            resources.insert(r_type.to_owned(), r_file);
+            headers.insert(r_type.to_owned(), header);
+        }
+
+        Ok(ResourceManager { headers, resources })
+    }
+
     pub fn get_resource(&self, name: &str, delta_idx: u16) -> VCRResult<Vec<u8>> {
         let delta_file = &self.resources[name];
         let header = &self.headers[name];
@@ -0,0 +1,741 @@
+use crate::{GameSummary, GameSummaryFilter, Half, MultiDatabase, Play, VCRResult};
+use rayon::prelude::*;
+use rocket::FromFormField;
+use serde::{ser::SerializeStruct, Deserialize, Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use uuid::Uuid;
+
+// batting and pitching lines, derived from the play-by-play in game updates.
+// update texts changed a lot over the seasons, so this goes by the phrases that stayed the same and skips whatever it doesn't recognize.
+
+/// Bumped whenever box scores are derived differently, so stale cache files get ignored.
+const STATS_VERSION: u32 = 1;
+
+/// What a play did to the batter, going by its update text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    Single,
+    Double,
+    Triple,
+    Quadruple,
+    HomeRun,
+    Walk,
+    HitByPitch,
+    Strikeout,
+    FieldOut,
+    DoublePlay,
+    FieldersChoice,
+    Sacrifice,
+}
+
+impl Outcome {
+    fn from_text(text: &str) -> Option<Outcome> {
+        let text = text.to_lowercase();
+        let has = |phrase: &str| text.contains(phrase);
+
+        Some(if has("home run") || has("grand slam") {
+            Outcome::HomeRun
+        } else if has("hits a single") {
+            Outcome::Single
+        } else if has("hits a double") {
+            Outcome::Double
+        } else if has("hits a triple") {
+            Outcome::Triple
+        } else if has("hits a quadruple") {
+            Outcome::Quadruple
+        } else if has("draws a walk") {
+            Outcome::Walk
+        } else if has("with a pitch") {
+            Outcome::HitByPitch
+        } else if has("strikes out") || has("struck out") {
+            Outcome::Strikeout
+        } else if has("double play") {
+            Outcome::DoublePlay
+        } else if has("fielder's choice") {
+            Outcome::FieldersChoice
+        } else if has("sacrifice") {
+            Outcome::Sacrifice
+        } else if has("ground out") || has("flyout") || has("grounds out") || has("flies out") {
+            Outcome::FieldOut
+        } else {
+            return None;
+        })
+    }
+
+    fn is_hit(self) -> bool {
+        matches!(
+            self,
+            Outcome::Single
+                | Outcome::Double
+                | Outcome::Triple
+                | Outcome::Quadruple
+                | Outcome::HomeRun
+        )
+    }
+
+    fn is_at_bat(self) -> bool {
+        !matches!(
+            self,
+            Outcome::Walk | Outcome::HitByPitch | Outcome::Sacrifice
+        )
+    }
+
+    fn outs(self) -> u32 {
+        match self {
+            Outcome::Strikeout
+            | Outcome::FieldOut
+            | Outcome::FieldersChoice
+            | Outcome::Sacrifice => 1,
+            Outcome::DoublePlay => 2,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct BattingLine {
+    pub plate_appearances: u32,
+    pub at_bats: u32,
+    pub hits: u32,
+    pub doubles: u32,
+    pub triples: u32,
+    pub quadruples: u32,
+    pub home_runs: u32,
+    pub walks: u32,
+    pub hit_by_pitch: u32,
+    pub strikeouts: u32,
+    pub sacrifices: u32,
+    pub runs: u32,
+    pub runs_batted_in: u32,
+}
+
+impl BattingLine {
+    pub fn add(&mut self, other: &BattingLine) {
+        self.plate_appearances += other.plate_appearances;
+        self.at_bats += other.at_bats;
+        self.hits += other.hits;
+        self.doubles += other.doubles;
+        self.triples += other.triples;
+        self.quadruples += other.quadruples;
+        self.home_runs += other.home_runs;
+        self.walks += other.walks;
+        self.hit_by_pitch += other.hit_by_pitch;
+        self.strikeouts += other.strikeouts;
+        self.sacrifices += other.sacrifices;
+        self.runs += other.runs;
+        self.runs_batted_in += other.runs_batted_in;
+    }
+
+    fn record(&mut self, outcome: Outcome, runs: u32) {
+        self.plate_appearances += 1;
+        self.at_bats += outcome.is_at_bat() as u32;
+        self.hits += outcome.is_hit() as u32;
+        match outcome {
+            Outcome::Double => self.doubles += 1,
+            Outcome::Triple => self.triples += 1,
+            Outcome::Quadruple => self.quadruples += 1,
+            Outcome::HomeRun => self.home_runs += 1,
+            Outcome::Walk => self.walks += 1,
+            Outcome::HitByPitch => self.hit_by_pitch += 1,
+            Outcome::Strikeout => self.strikeouts += 1,
+            Outcome::Sacrifice => self.sacrifices += 1,
+            _ => {}
+        }
+        if outcome != Outcome::DoublePlay {
+            self.runs_batted_in += runs;
+        }
+    }
+}
+
+/// Serialized with `inningsPitched` in the usual notation (5.1 is five and a third) next to the outs it's made from.
+#[derive(Deserialize, Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct PitchingLine {
+    pub outs_recorded: u32,
+    pub batters_faced: u32,
+    pub hits_allowed: u32,
+    pub home_runs_allowed: u32,
+    pub walks: u32,
+    pub strikeouts: u32,
+    pub runs_allowed: u32,
+}
+
+impl PitchingLine {
+    pub fn add(&mut self, other: &PitchingLine) {
+        self.outs_recorded += other.outs_recorded;
+        self.batters_faced += other.batters_faced;
+        self.hits_allowed += other.hits_allowed;
+        self.home_runs_allowed += other.home_runs_allowed;
+        self.walks += other.walks;
+        self.strikeouts += other.strikeouts;
+        self.runs_allowed += other.runs_allowed;
+    }
+
+    pub fn innings_pitched(&self) -> String {
+        format!("{}.{}", self.outs_recorded / 3, self.outs_recorded % 3)
+    }
+
+    fn record(&mut self, outcome: Outcome) {
+        self.batters_faced += 1;
+        self.outs_recorded += outcome.outs();
+        self.hits_allowed += outcome.is_hit() as u32;
+        match outcome {
+            Outcome::HomeRun => self.home_runs_allowed += 1,
+            Outcome::Walk => self.walks += 1,
+            Outcome::Strikeout => self.strikeouts += 1,
+            _ => {}
+        }
+    }
+}
+
+impl Serialize for PitchingLine {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut line = serializer.serialize_struct("PitchingLine", 8)?;
+        line.serialize_field("inningsPitched", &self.innings_pitched())?;
+        line.serialize_field("outsRecorded", &self.outs_recorded)?;
+        line.serialize_field("battersFaced", &self.batters_faced)?;
+        line.serialize_field("hitsAllowed", &self.hits_allowed)?;
+        line.serialize_field("homeRunsAllowed", &self.home_runs_allowed)?;
+        line.serialize_field("walks", &self.walks)?;
+        line.serialize_field("strikeouts", &self.strikeouts)?;
+        line.serialize_field("runsAllowed", &self.runs_allowed)?;
+        line.end()
+    }
+}
+
+/// One player's line in one game. Players who switched teams mid-game count for the team they played for first.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct BoxScore {
+    pub game_id: String,
+    pub season: i64,
+    pub day: i64,
+    pub player: Uuid,
+    pub team: Option<Uuid>,
+    pub batting: BattingLine,
+    pub pitching: PitchingLine,
+}
+
+fn line_for<'a>(
+    lines: &'a mut HashMap<Uuid, BoxScore>,
+    game: &GameSummary,
+    player: Uuid,
+    team: Option<Uuid>,
+) -> &'a mut BoxScore {
+    lines.entry(player).or_insert_with(|| BoxScore {
+        game_id: game.game_id.clone(),
+        season: game.season,
+        day: game.day,
+        player,
+        team,
+        batting: BattingLine::default(),
+        pitching: PitchingLine::default(),
+    })
+}
+
+/// Works out every player's box score in a game from its play-by-play.
+pub fn box_scores(game: &GameSummary, plays: &[Play]) -> Vec<BoxScore> {
+    let mut lines: HashMap<Uuid, BoxScore> = HashMap::new();
+
+    // the update that ends a plate appearance usually clears the batter, so remember who it was
+    let mut batter: Option<Uuid> = None;
+    let mut previous: Option<&Play> = None;
+
+    for play in plays {
+        let (batting_team, pitching_team, runs) = match play.half {
+            Half::Top => (game.away_team, game.home_team, play.away_score_delta),
+            Half::Bottom => (game.home_team, game.away_team, play.home_score_delta),
+        };
+        let runs = runs.max(0.0).round() as u32;
+        batter = play.batter.or(batter);
+
+        if let Some(pitcher) = play.pitcher {
+            line_for(&mut lines, game, pitcher, pitching_team)
+                .pitching
+                .runs_allowed += runs;
+        }
+
+        // runners who were on base before this play and are named as scoring in it
+        if let Some(previous) = previous {
+            for runner in &previous.runners {
+                if let Some(id) = runner.id {
+                    if !runner.name.is_empty()
+                        && play.text.contains(&format!("{} scores", runner.name))
+                    {
+                        line_for(&mut lines, game, id, batting_team).batting.runs += 1;
+                    }
+                }
+            }
+        }
+
+        if let Some(outcome) = Outcome::from_text(&play.text) {
+            if let Some(batter) = batter {
+                let line = &mut line_for(&mut lines, game, batter, batting_team).batting;
+                line.record(outcome, runs);
+                if outcome == Outcome::HomeRun {
+                    line.runs += 1;
+                }
+            }
+            if let Some(pitcher) = play.pitcher {
+                line_for(&mut lines, game, pitcher, pitching_team)
+                    .pitching
+                    .record(outcome);
+            }
+
+            batter = None;
+        }
+
+        previous = Some(play);
+    }
+
+    let mut lines: Vec<BoxScore> = lines.into_values().collect();
+    lines.sort_by_key(|line| line.player);
+    lines
+}
+
+/// How `StatsCache::player_stats` and `team_stats` add box scores up.
+#[derive(Debug, Copy, Clone, FromFormField, PartialEq, Eq)]
+pub enum StatGrouping {
+    #[field(value = "game")]
+    Game,
+    #[field(value = "day")]
+    Day,
+    #[field(value = "season")]
+    Season,
+}
+
+/// Box scores added up over a game, a day or a season. `day` and `gameId` are only set when grouping by them.
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StatRow {
+    pub season: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub day: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub game_id: Option<String>,
+    pub games: u32,
+    pub batting: BattingLine,
+    pub pitching: PitchingLine,
+}
+
+/// What rows are grouped by: season, day and game id, with the parts finer than the grouping left out.
+type RowKey = (i64, Option<i64>, Option<String>);
+
+fn aggregate<'a, I: Iterator<Item = &'a BoxScore>>(scores: I, group: StatGrouping) -> Vec<StatRow> {
+    let mut rows: BTreeMap<RowKey, StatRow> = BTreeMap::new();
+    // a team has a box score per player, but each game only counts once
+    let mut games: BTreeMap<RowKey, Vec<&str>> = BTreeMap::new();
+
+    for score in scores {
+        let key = match group {
+            StatGrouping::Game => (score.season, Some(score.day), Some(score.game_id.clone())),
+            StatGrouping::Day => (score.season, Some(score.day), None),
+            StatGrouping::Season => (score.season, None, None),
+        };
+
+        let row = rows.entry(key.clone()).or_insert_with(|| StatRow {
+            season: key.0,
+            day: key.1,
+            game_id: key.2.clone(),
+            games: 0,
+            batting: BattingLine::default(),
+            pitching: PitchingLine::default(),
+        });
+        row.batting.add(&score.batting);
+        row.pitching.add(&score.pitching);
+
+        let seen = games.entry(key).or_default();
+        if !seen.contains(&score.game_id.as_str()) {
+            seen.push(&score.game_id);
+            row.games += 1;
+        }
+    }
+
+    rows.into_values().collect()
+}
+
+/// Derives box scores on demand and keeps them, since working them out means decoding every version of every game.
+/// They're kept on disk (one file per game) when given a folder, and in memory otherwise.
+pub struct StatsCache {
+    folder: Option<PathBuf>,
+    memory: RwLock<HashMap<String, Vec<BoxScore>>>,
+}
+
+impl StatsCache {
+    pub fn new<P: AsRef<Path>>(folder: Option<P>) -> VCRResult<StatsCache> {
+        let folder = folder.map(|f| f.as_ref().to_path_buf());
+        if let Some(folder) = &folder {
+            fs::create_dir_all(folder)?;
+        }
+
+        Ok(StatsCache {
+            folder,
+            memory: RwLock::new(HashMap::new()),
+        })
+    }
+
+    fn cache_path(&self, game_id: &str) -> Option<PathBuf> {
+        self.folder
+            .as_ref()
+            .map(|f| f.join(format!("{}.v{}.stats", game_id, STATS_VERSION)))
+    }
+
+    /// Box scores for one game, from the cache if they're in it.
+    pub fn game_box_scores(
+        &self,
+        db: &MultiDatabase,
+        game: &GameSummary,
+    ) -> VCRResult<Vec<BoxScore>> {
+        let path = self.cache_path(&game.game_id);
+
+        if path.is_none() {
+            if let Some(cached) = self.memory.read().unwrap().get(&game.game_id) {
+                return Ok(cached.clone());
+            }
+        }
+
+        // an unreadable cache file just gets worked out again
+        if let Some(cached) = path
+            .as_ref()
+            .and_then(|p| fs::read(p).ok())
+            .and_then(|bytes| rmp_serde::from_read_ref(&bytes).ok())
+        {
+            return Ok(cached);
+        }
+
+        let scores = box_scores(game, &db.play_by_play(&game.game_id)?);
+        match path {
+            Some(path) => fs::write(path, rmp_serde::to_vec_named(&scores)?)?,
+            None => {
+                self.memory
+                    .write()
+                    .unwrap()
+                    .insert(game.game_id.clone(), scores.clone());
+            }
+        }
+
+        Ok(scores)
+    }
+
+    fn season_box_scores(
+        &self,
+        db: &MultiDatabase,
+        filter: &GameSummaryFilter,
+    ) -> VCRResult<Vec<BoxScore>> {
+        Ok(db
+            .game_summaries(filter)?
+            .par_iter()
+            .map(|game| self.game_box_scores(db, game))
+            .collect::<VCRResult<Vec<Vec<BoxScore>>>>()?
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+
+    /// A player's stats, over every season or just one.
+    pub fn player_stats(
+        &self,
+        db: &MultiDatabase,
+        player: &Uuid,
+        season: Option<i32>,
+        group: StatGrouping,
+    ) -> VCRResult<Vec<StatRow>> {
+        let scores = self.season_box_scores(
+            db,
+            &GameSummaryFilter {
+                season,
+                ..Default::default()
+            },
+        )?;
+
+        Ok(aggregate(
+            scores.iter().filter(|s| &s.player == player),
+            group,
+        ))
+    }
+
+    /// A team's stats: what every one of its players did while playing for it.
+    pub fn team_stats(
+        &self,
+        db: &MultiDatabase,
+        team: &Uuid,
+        season: Option<i32>,
+        group: StatGrouping,
+    ) -> VCRResult<Vec<StatRow>> {
+        let scores = self.season_box_scores(
+            db,
+            &GameSummaryFilter {
+                season,
+                day: None,
+                team: Some(*team),
+            },
+        )?;
+
+        Ok(aggregate(
+            scores.iter().filter(|s| s.team.as_ref() == Some(team)),
+            group,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_sequences::testing::id;
+    use chrono::{TimeZone, Utc};
+    use serde_json::{json, Value as JSONValue};
+
+    #[test]
+    fn outcomes_from_update_text() {
+        let cases = [
+            (
+                "Jessica Telephone hits a Single! 1 base.",
+                Some(Outcome::Single),
+            ),
+            ("Nagomi Mcdaniel hits a Double!", Some(Outcome::Double)),
+            ("Aldon Cashmoney hits a grand slam!", Some(Outcome::HomeRun)),
+            (
+                "Paula Turnip hits a 2-run home run!",
+                Some(Outcome::HomeRun),
+            ),
+            (
+                "York Silk hits into a double play!",
+                Some(Outcome::DoublePlay),
+            ),
+            (
+                "Wyatt Quitter hits a sacrifice fly. Don Mitchell tags up and scores!",
+                Some(Outcome::Sacrifice),
+            ),
+            ("Comfort Septemberish draws a walk.", Some(Outcome::Walk)),
+            (
+                "Chorby Short hits Jaylen Hotdogfingers with a pitch!",
+                Some(Outcome::HitByPitch),
+            ),
+            (
+                "Don Mitchell strikes out looking.",
+                Some(Outcome::Strikeout),
+            ),
+            (
+                "Baldwin Breadwinner hit a ground out to Wyatt Mason.",
+                Some(Outcome::FieldOut),
+            ),
+            (
+                "Sebastian Telephone reaches on fielder's choice. Mason out at second base.",
+                Some(Outcome::FieldersChoice),
+            ),
+            ("Ball. 1-0", None),
+            ("Foul Ball. 2-2", None),
+        ];
+
+        for (text, outcome) in cases {
+            assert_eq!(Outcome::from_text(text), outcome, "{}", text);
+        }
+    }
+
+    #[test]
+    fn innings_pitched_counts_thirds() {
+        let ip = |outs_recorded| {
+            PitchingLine {
+                outs_recorded,
+                ..Default::default()
+            }
+            .innings_pitched()
+        };
+        assert_eq!(ip(0), "0.0");
+        assert_eq!(ip(2), "0.2");
+        assert_eq!(ip(7), "2.1");
+        assert_eq!(ip(27), "9.0");
+
+        let json = serde_json::to_value(PitchingLine {
+            outs_recorded: 16,
+            ..Default::default()
+        })
+        .unwrap();
+        assert_eq!(json["inningsPitched"], "5.1");
+        assert_eq!(json["outsRecorded"], 16);
+    }
+
+    fn summary(game_id: &str, season: i64, day: i64) -> GameSummary {
+        GameSummary {
+            game_id: game_id.to_owned(),
+            season,
+            day,
+            home_team: Some(Uuid::from_u128(10)),
+            away_team: Some(Uuid::from_u128(20)),
+            home_team_name: "Home".to_owned(),
+            away_team_name: "Away".to_owned(),
+            home_score: 0.0,
+            away_score: 3.0,
+            winner: None,
+            loser: None,
+            innings: 9,
+            shame: false,
+            outcomes: Vec::new(),
+        }
+    }
+
+    /// A top half update: who's batting and on base (by player number), the away team's score and the update text.
+    fn update(
+        play: i64,
+        batter: Option<u128>,
+        on_base: &[u128],
+        away_score: f64,
+        text: &str,
+    ) -> JSONValue {
+        let name = |n: &u128| format!("Batter {}", n);
+        json!({
+            "playCount": play,
+            "topOfInning": true,
+            "awayBatter": batter.map(id),
+            "awayBatterName": batter.as_ref().map(name).unwrap_or_default(),
+            "homePitcher": id(3),
+            "homePitcherName": "Pitcher",
+            "basesOccupied": (0..on_base.len()).collect::<Vec<usize>>(),
+            "baseRunners": on_base.iter().map(|n| id(*n)).collect::<Vec<String>>(),
+            "baseRunnerNames": on_base.iter().map(name).collect::<Vec<String>>(),
+            "homeScore": 0,
+            "awayScore": away_score,
+            "lastUpdate": text,
+        })
+    }
+
+    #[test]
+    fn box_scores_from_plays() {
+        let updates = vec![
+            update(1, Some(1), &[], 0.0, "Batter 1 batting for the Away."),
+            update(2, None, &[1], 0.0, "Batter 1 hits a Single! 1 base."),
+            update(3, Some(2), &[1], 0.0, "Batter 2 batting for the Away."),
+            update(
+                4,
+                None,
+                &[2],
+                1.0,
+                "Batter 2 hits a Double! Batter 1 scores!",
+            ),
+            update(5, Some(5), &[2], 1.0, "Batter 5 batting for the Away."),
+            update(
+                6,
+                None,
+                &[],
+                2.0,
+                "Batter 5 hits into a double play! Batter 2 scores!",
+            ),
+            update(7, Some(6), &[], 2.0, "Batter 6 batting for the Away."),
+            update(8, None, &[], 3.0, "Batter 6 hits a solo home run!"),
+        ];
+        let versions: Vec<_> = updates
+            .into_iter()
+            .enumerate()
+            .map(|(i, data)| crate::ChroniclerEntity {
+                entity_id: id(0),
+                valid_from: Utc.timestamp(1000 + i as i64, 0),
+                valid_to: None,
+                hash: String::new(),
+                data,
+            })
+            .collect();
+
+        let scores = box_scores(&summary("game", 1, 1), &Play::from_versions(&versions));
+        let line = |n: u128| {
+            scores
+                .iter()
+                .find(|s| s.player == Uuid::from_u128(n))
+                .unwrap()
+        };
+
+        let batting = |hits, runs, runs_batted_in| BattingLine {
+            plate_appearances: 1,
+            at_bats: 1,
+            hits,
+            runs,
+            runs_batted_in,
+            ..Default::default()
+        };
+        assert_eq!(line(1).batting, batting(1, 1, 0));
+        assert_eq!(
+            line(2).batting,
+            BattingLine {
+                doubles: 1,
+                ..batting(1, 1, 1)
+            }
+        );
+        // double plays don't count as driving anyone in
+        assert_eq!(line(5).batting, batting(0, 0, 0));
+        assert_eq!(
+            line(6).batting,
+            BattingLine {
+                home_runs: 1,
+                ..batting(1, 1, 1)
+            }
+        );
+        assert!(scores
+            .iter()
+            .filter(|s| s.player != Uuid::from_u128(3))
+            .all(|s| s.team == Some(Uuid::from_u128(20))));
+
+        let pitcher = line(3);
+        assert_eq!(pitcher.team, Some(Uuid::from_u128(10)));
+        assert_eq!(
+            pitcher.pitching,
+            PitchingLine {
+                outs_recorded: 2,
+                batters_faced: 4,
+                hits_allowed: 3,
+                home_runs_allowed: 1,
+                walks: 0,
+                strikeouts: 0,
+                runs_allowed: 3,
+            }
+        );
+    }
+
+    fn score(game_id: &str, day: i64, player: u128, hits: u32) -> BoxScore {
+        BoxScore {
+            game_id: game_id.to_owned(),
+            season: 1,
+            day,
+            player: Uuid::from_u128(player),
+            team: Some(Uuid::from_u128(20)),
+            batting: BattingLine {
+                hits,
+                ..Default::default()
+            },
+            pitching: PitchingLine::default(),
+        }
+    }
+
+    #[test]
+    fn aggregate_counts_each_game_once() {
+        let scores = [
+            score("a", 1, 1, 1),
+            score("a", 1, 2, 2),
+            score("b", 1, 1, 0),
+            score("b", 1, 2, 1),
+            score("c", 2, 1, 3),
+        ];
+        let rows = |group| -> Vec<(Option<i64>, Option<String>, u32, u32)> {
+            aggregate(scores.iter(), group)
+                .into_iter()
+                .map(|row| (row.day, row.game_id, row.games, row.batting.hits))
+                .collect()
+        };
+
+        assert_eq!(rows(StatGrouping::Season), vec![(None, None, 3, 7)]);
+        assert_eq!(
+            rows(StatGrouping::Day),
+            vec![(Some(1), None, 2, 4), (Some(2), None, 1, 3)]
+        );
+        assert_eq!(
+            rows(StatGrouping::Game),
+            vec![
+                (Some(1), Some("a".to_owned()), 1, 3),
+                (Some(1), Some("b".to_owned()), 1, 1),
+                (Some(2), Some("c".to_owned()), 1, 3),
+            ]
+        );
+    }
+}
@@ -1,3 +1,5 @@
+use serde::{Serialize, Serializer};
+use std::collections::{BTreeMap, HashMap};
 use std::io;
 
 pub fn encode_varint(i: u16) -> Vec<u8> {
@@ -78,9 +80,57 @@ macro_rules! read_i64 {
     }};
 }
 
+/// Serializes a HashMap with its keys in sorted order, so that encoded indexes come out byte-for-byte the same on every build.
+pub fn serialize_sorted<S: Serializer, K: Serialize + Ord, V: Serialize>(
+    map: &HashMap<K, V>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    map.iter()
+        .collect::<BTreeMap<&K, &V>>()
+        .serialize(serializer)
+}
+
 pub fn is_eof<T>(err: &io::Result<T>) -> bool {
     match err {
         Ok(_) => false,
         Err(e) => e.kind() == io::ErrorKind::UnexpectedEof,
     }
 }
+
+/// The files making up a single entity type: the main .riv file, its header, and any side tables (such as the game date index).
+pub(crate) struct TapeFiles<T> {
+    pub main: Option<T>,
+    pub header: Option<T>,
+    pub tables: HashMap<String, T>,
+}
+
+/// Groups tape files by entity type, going by their names: `player.riv` is the main file for `player`, `player.header.riv.zstd` its header, and `game_updates.dates.riv.zstd` the `dates` table for `game_updates`.
+pub(crate) fn group_tape_files<T>(files: Vec<(String, T)>) -> BTreeMap<String, TapeFiles<T>> {
+    let mut groups: BTreeMap<String, TapeFiles<T>> = BTreeMap::new();
+
+    for (name, file) in files {
+        let (e_type, rest) = match name.split_once('.') {
+            Some(v) => v,
+            None => continue,
+        };
+
+        let group = groups
+            .entry(e_type.to_owned())
+            .or_insert_with(|| TapeFiles {
+                main: None,
+                header: None,
+                tables: HashMap::new(),
+            });
+
+        match rest.split('.').next() {
+            Some("riv") => group.main = Some(file),
+            Some("header") => group.header = Some(file),
+            Some(kind) => {
+                group.tables.insert(kind.to_owned(), file);
+            }
+            None => {}
+        }
+    }
+
+    groups
+}